@@ -1,37 +1,50 @@
 use async_trait::async_trait;
 use ethers::{
-    providers::{Http, Provider},
+    providers::Middleware,
     types::{Address, U256},
 };
 use serde::Serialize;
 use std::sync::Arc;
 
 use crate::{
-    errors::FydeError, utils::ToF32, AddressList, Chain, GovernanceModuleContract,
+    errors::FydeError, utils::FydeAmount, AddressList, Chain, GovernanceModuleContract,
     LiquidVaultContract, RelayerContract, TaxModuleContract, ERC20,
 };
 
-pub struct Asset {
+pub struct Asset<M: Middleware> {
     asset_address: Address,
-    contract: ERC20<Provider<Http>>,
-    liquid_vault: LiquidVaultContract<Provider<Http>>,
-    tax_module: TaxModuleContract<Provider<Http>>,
-    governance_module: GovernanceModuleContract<Provider<Http>>,
-    relayer: RelayerContract<Provider<Http>>,
+    contract: ERC20<M>,
+    liquid_vault: LiquidVaultContract<M>,
+    tax_module: TaxModuleContract<M>,
+    governance_module: GovernanceModuleContract<M>,
+    relayer: RelayerContract<M>,
 }
 
 #[derive(Debug, Serialize, Clone)]
 pub struct AssetAccounting {
-    pub token_in_protocol: f32,
-    pub token_in_standard_pool: f32,
-    pub token_in_governance_pool: f32,
+    pub token_in_protocol: FydeAmount,
+    pub token_in_standard_pool: FydeAmount,
+    pub token_in_governance_pool: FydeAmount,
 }
 
 #[derive(Debug, Serialize, Clone)]
 pub struct TargetConcentrations {
-    pub target_concentration_deposit: f32,
-    pub target_concentration_withdraw: f32,
-    pub target_concentration_fyde: f32,
+    pub target_concentration_deposit: FydeAmount,
+    pub target_concentration_withdraw: FydeAmount,
+    pub target_concentration_fyde: FydeAmount,
+}
+
+impl TargetConcentrations {
+    /// Targets as percents, the unit of [`AssetTrait::get_current_concentration`]
+    pub fn to_percents(&self) -> Option<Self> {
+        Some(Self {
+            target_concentration_deposit: self.target_concentration_deposit.checked_mul_u64(100)?,
+            target_concentration_withdraw: self
+                .target_concentration_withdraw
+                .checked_mul_u64(100)?,
+            target_concentration_fyde: self.target_concentration_fyde.checked_mul_u64(100)?,
+        })
+    }
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -63,36 +76,40 @@ pub struct UniswapInfo {
 
 // We make trait here in case we want to implement trait for Vec<Asset> in the future
 #[async_trait]
-pub trait AssetTrait {
-    async fn get_symbol(&self) -> Result<String, FydeError>;
-    async fn get_decimals(&self) -> Result<u8, FydeError>;
-    async fn get_address(&self) -> Result<Address, FydeError>;
-    async fn get_asset_aum(&self) -> Result<f32, FydeError>;
-    async fn get_oracle_price(&self, decimals: u8) -> Result<f32, FydeError>;
-    async fn get_uniswap_info(&self) -> Result<UniswapInfo, FydeError>;
-    async fn get_asset_accounting(&self, decimals: u8) -> Result<AssetAccounting, FydeError>;
-    async fn get_asset_target_concentrations(&self) -> Result<TargetConcentrations, FydeError>;
-    async fn get_current_concentration(&self, asset_aum: f32, tvl: f32) -> Result<f32, FydeError>;
+pub trait AssetTrait<M: Middleware> {
+    async fn get_symbol(&self) -> Result<String, FydeError<M>>;
+    async fn get_decimals(&self) -> Result<u8, FydeError<M>>;
+    async fn get_address(&self) -> Result<Address, FydeError<M>>;
+    async fn get_asset_aum(&self) -> Result<FydeAmount, FydeError<M>>;
+    async fn get_oracle_price(&self, decimals: u8) -> Result<FydeAmount, FydeError<M>>;
+    async fn get_uniswap_info(&self) -> Result<UniswapInfo, FydeError<M>>;
+    async fn get_asset_accounting(&self, decimals: u8) -> Result<AssetAccounting, FydeError<M>>;
+    async fn get_asset_target_concentrations(&self) -> Result<TargetConcentrations, FydeError<M>>;
+    async fn get_current_concentration(
+        &self,
+        asset_aum: &FydeAmount,
+        tvl: &FydeAmount,
+    ) -> Result<FydeAmount, FydeError<M>>;
     async fn get_weight_status(
         &self,
         target_concentrations: &TargetConcentrations,
-        current_concentration: f32,
-    ) -> Result<WeightStatus, FydeError>;
+        current_concentration: &FydeAmount,
+    ) -> Result<WeightStatus, FydeError<M>>;
     async fn get_liquidity_profile(
         &self,
         asset_accounting: AssetAccounting,
-    ) -> Result<f32, FydeError>;
-    async fn get_is_allowed_on_governance(&self) -> Result<bool, FydeError>;
-    async fn get_st_address(&self) -> Result<Address, FydeError>;
-    async fn get_is_quarantined(&self) -> Result<bool, FydeError>;
+    ) -> Result<FydeAmount, FydeError<M>>;
+    async fn get_is_allowed_on_governance(&self) -> Result<bool, FydeError<M>>;
+    async fn get_st_address(&self) -> Result<Address, FydeError<M>>;
+    async fn get_is_quarantined(&self) -> Result<bool, FydeError<M>>;
 }
 
-impl Asset {
-    pub fn new(address: Address, provider: Arc<Provider<Http>>, chain: Chain) -> Self {
+impl<M: Middleware> Asset<M> {
+    pub fn new(address: Address, provider: Arc<M>, chain: Chain) -> Self {
         let address_list: AddressList = AddressList::new(&chain);
 
         Self {
-            asset_address: address.clone(),
+            asset_address: address,
             contract: ERC20::new(address, provider.clone()),
             liquid_vault: LiquidVaultContract::new(address_list.liquid_vault, provider.clone()),
             tax_module: TaxModuleContract::new(address_list.tax_module, provider.clone()),
@@ -106,8 +123,8 @@ impl Asset {
 }
 
 #[async_trait]
-impl AssetTrait for Asset {
-    async fn get_symbol(&self) -> Result<String, FydeError> {
+impl<M: Middleware + 'static> AssetTrait<M> for Asset<M> {
+    async fn get_symbol(&self) -> Result<String, FydeError<M>> {
         let mkr_address: Address = String::from("0x9f8f72aa9304c8b593d555f12ef6589cc3a579a2")
             .parse()
             .expect("Failed to create address");
@@ -118,34 +135,34 @@ impl AssetTrait for Asset {
         Ok(symbol)
     }
 
-    async fn get_decimals(&self) -> Result<u8, FydeError> {
+    async fn get_decimals(&self) -> Result<u8, FydeError<M>> {
         Ok(self.contract.decimals().call().await?)
     }
 
-    async fn get_address(&self) -> Result<Address, FydeError> {
+    async fn get_address(&self) -> Result<Address, FydeError<M>> {
         Ok(self.contract.address())
     }
 
-    async fn get_asset_aum(&self) -> Result<f32, FydeError> {
+    async fn get_asset_aum(&self) -> Result<FydeAmount, FydeError<M>> {
         let asset_aum = self
             .liquid_vault
             .get_asset_aum(self.asset_address)
             .call()
             .await?;
-        Ok(asset_aum.to_f32(18.0))
+        Ok(FydeAmount::new(asset_aum, 18))
     }
 
-    async fn get_oracle_price(&self, decimals: u8) -> Result<f32, FydeError> {
+    async fn get_oracle_price(&self, decimals: u8) -> Result<FydeAmount, FydeError<M>> {
         let amount: U256 = U256::from(10).pow(U256::from(decimals));
         let oracle_price = self
             .liquid_vault
             .get_quote(self.asset_address, amount)
             .call()
             .await?;
-        Ok(oracle_price.to_f32(18.0))
+        Ok(FydeAmount::new(oracle_price, 18))
     }
 
-    async fn get_uniswap_info(&self) -> Result<UniswapInfo, FydeError> {
+    async fn get_uniswap_info(&self) -> Result<UniswapInfo, FydeError<M>> {
         let asset_info = self
             .liquid_vault
             .asset_info(self.asset_address)
@@ -164,7 +181,7 @@ impl AssetTrait for Asset {
         })
     }
 
-    async fn get_asset_accounting(&self, decimals: u8) -> Result<AssetAccounting, FydeError> {
+    async fn get_asset_accounting(&self, decimals: u8) -> Result<AssetAccounting, FydeError<M>> {
         let token_in_protocol = self
             .liquid_vault
             .total_asset_accounting(self.asset_address)
@@ -181,13 +198,13 @@ impl AssetTrait for Asset {
             .call()
             .await?;
         Ok(AssetAccounting {
-            token_in_protocol: token_in_protocol.to_f32(decimals as f32),
-            token_in_standard_pool: token_in_standard_pool.to_f32(decimals as f32),
-            token_in_governance_pool: token_in_governance_pool.to_f32(decimals as f32),
+            token_in_protocol: FydeAmount::new(token_in_protocol, decimals),
+            token_in_standard_pool: FydeAmount::new(token_in_standard_pool, decimals),
+            token_in_governance_pool: FydeAmount::new(token_in_governance_pool, decimals),
         })
     }
 
-    async fn get_asset_target_concentrations(&self) -> Result<TargetConcentrations, FydeError> {
+    async fn get_asset_target_concentrations(&self) -> Result<TargetConcentrations, FydeError<M>> {
         let concentrations_from_tax: (u128, u128) = self
             .tax_module
             .tax_params(self.asset_address)
@@ -199,31 +216,42 @@ impl AssetTrait for Asset {
             .call()
             .await?;
         Ok(TargetConcentrations {
-            target_concentration_deposit: U256::from(concentrations_from_tax.0).to_f32(18.0),
-            target_concentration_withdraw: U256::from(concentrations_from_tax.1).to_f32(18.0),
-            target_concentration_fyde: U256::from(fyde_concentration.0).to_f32(18.0),
+            target_concentration_deposit: FydeAmount::new(
+                U256::from(concentrations_from_tax.0),
+                18,
+            ),
+            target_concentration_withdraw: FydeAmount::new(
+                U256::from(concentrations_from_tax.1),
+                18,
+            ),
+            target_concentration_fyde: FydeAmount::new(U256::from(fyde_concentration.0), 18),
         })
     }
 
-    async fn get_current_concentration(&self, asset_aum: f32, tvl: f32) -> Result<f32, FydeError> {
-        Ok(100.0 * asset_aum / tvl)
+    async fn get_current_concentration(
+        &self,
+        asset_aum: &FydeAmount,
+        tvl: &FydeAmount,
+    ) -> Result<FydeAmount, FydeError<M>> {
+        asset_aum
+            .checked_mul_u64(100)
+            .and_then(|aum| aum.checked_div(tvl))
+            .ok_or(FydeError::ArithmeticError(String::from(
+                "Current concentration of an empty protocol",
+            )))
     }
 
     async fn get_weight_status(
         &self,
         target_concentrations: &TargetConcentrations,
-        current_concentration: f32,
-    ) -> Result<WeightStatus, FydeError> {
+        current_concentration: &FydeAmount,
+    ) -> Result<WeightStatus, FydeError<M>> {
+        let deposit = &target_concentrations.target_concentration_deposit;
+        let withdraw = &target_concentrations.target_concentration_withdraw;
         let weight_status = match current_concentration {
-            _ if current_concentration > target_concentrations.target_concentration_deposit => {
-                WeightStatus::Overweight
-            }
-            _ if current_concentration < target_concentrations.target_concentration_withdraw => {
-                WeightStatus::Underweight
-            }
-            _ if current_concentration > target_concentrations.target_concentration_withdraw
-                && current_concentration < target_concentrations.target_concentration_deposit =>
-            {
+            _ if current_concentration > deposit => WeightStatus::Overweight,
+            _ if current_concentration < withdraw => WeightStatus::Underweight,
+            _ if current_concentration > withdraw && current_concentration < deposit => {
                 WeightStatus::InRange
             }
             _ => WeightStatus::Undertemined,
@@ -235,14 +263,22 @@ impl AssetTrait for Asset {
     async fn get_liquidity_profile(
         &self,
         asset_accounting: AssetAccounting,
-    ) -> Result<f32, FydeError> {
-        if asset_accounting.token_in_protocol == 0.0 {
-            return Ok(0.0);
+    ) -> Result<FydeAmount, FydeError<M>> {
+        if asset_accounting.token_in_protocol.is_zero() {
+            return Ok(FydeAmount::zero(
+                asset_accounting.token_in_protocol.decimals(),
+            ));
         }
-        Ok(100.0 * asset_accounting.token_in_standard_pool / asset_accounting.token_in_protocol)
+        asset_accounting
+            .token_in_standard_pool
+            .checked_mul_u64(100)
+            .and_then(|amount| amount.checked_div(&asset_accounting.token_in_protocol))
+            .ok_or(FydeError::ArithmeticError(String::from(
+                "Liquidity profile overflow",
+            )))
     }
 
-    async fn get_is_allowed_on_governance(&self) -> Result<bool, FydeError> {
+    async fn get_is_allowed_on_governance(&self) -> Result<bool, FydeError<M>> {
         let is_allowed = self
             .governance_module
             .is_on_governance_whitelist(self.asset_address)
@@ -251,7 +287,7 @@ impl AssetTrait for Asset {
         Ok(is_allowed)
     }
 
-    async fn get_st_address(&self) -> Result<Address, FydeError> {
+    async fn get_st_address(&self) -> Result<Address, FydeError<M>> {
         let st_address = self
             .governance_module
             .asset_to_strsy(self.asset_address)
@@ -260,7 +296,7 @@ impl AssetTrait for Asset {
         Ok(st_address)
     }
 
-    async fn get_is_quarantined(&self) -> Result<bool, FydeError> {
+    async fn get_is_quarantined(&self) -> Result<bool, FydeError<M>> {
         let is_quarantined = self
             .relayer
             .is_quarantined(self.asset_address)
@@ -269,3 +305,32 @@ impl AssetTrait for Asset {
         Ok(is_quarantined)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::providers::Provider;
+
+    #[tokio::test]
+    async fn test_weight_status_in_percents() {
+        let (provider, _mock) = Provider::mocked();
+        let asset = Asset::new(Address::random(), Arc::new(provider), Chain::Mainnet);
+        let targets = TargetConcentrations {
+            target_concentration_deposit: "0.4".parse().unwrap(),
+            target_concentration_withdraw: "0.3".parse().unwrap(),
+            target_concentration_fyde: "0.35".parse().unwrap(),
+        }
+        .to_percents()
+        .unwrap();
+        let current = asset
+            .get_current_concentration(&"350".parse().unwrap(), &"1000".parse().unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(targets.target_concentration_deposit, "40".parse().unwrap());
+        assert!(matches!(
+            asset.get_weight_status(&targets, &current).await.unwrap(),
+            WeightStatus::InRange
+        ));
+    }
+}
@@ -0,0 +1,513 @@
+use chrono::DateTime;
+use clap::{Parser, Subcommand};
+use ethers::{
+    providers::{Http, Provider},
+    types::{Address, U256},
+};
+use serde::Serialize;
+use std::{error::Error, sync::Arc};
+
+use fyde_rs_sdk::{
+    asset::{Asset, AssetTrait},
+    liquid_vault::LiquidVault,
+    protocol_history::{ProtocolHistory, UserAction},
+    snapshot::Snapshot,
+    user::User,
+    utils::FydeAmount,
+    ve_fyde::VeFyde,
+    AddressList, Chain,
+};
+
+type Client = Provider<Http>;
+type CliResult<T> = Result<T, Box<dyn Error>>;
+
+#[derive(Parser)]
+#[command(name = "fyde", about = "Query the Fyde protocol")]
+struct Cli {
+    /// `mainnet`, `sepolia`, or the path of an address list file (JSON or TOML)
+    #[arg(long, global = true, default_value = "mainnet")]
+    chain: String,
+    /// Ethereum JSON-RPC url
+    #[arg(long, global = true, env = "FYDE_RPC_URL")]
+    rpc: Option<String>,
+    /// Print JSON instead of a table
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Protocol TVL, TRSY supply and price
+    Tvl,
+    /// Assets with their AUM, concentration and weight status
+    Assets,
+    /// Balances, allowances, governance data and veFyde lock of a user
+    User { address: Address },
+    /// Deposits, withdrawals and swaps between two blocks
+    History {
+        #[arg(long)]
+        from: Option<u64>,
+        #[arg(long)]
+        to: Option<u64>,
+    },
+    /// Closed governance proposals, most recent first
+    Proposals {
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+        /// Number of more recent proposals to skip
+        #[arg(long, default_value_t = 0)]
+        skip: usize,
+    },
+    /// Votes of a proposal
+    Votes {
+        proposal_id: String,
+        #[arg(long, default_value_t = 100)]
+        limit: usize,
+        #[arg(long, default_value_t = 0)]
+        skip: usize,
+    },
+}
+
+#[derive(Serialize)]
+struct TvlReport {
+    tvl: FydeAmount,
+    trsy_supply: FydeAmount,
+    trsy_price: FydeAmount,
+}
+
+#[derive(Serialize)]
+struct AssetReport {
+    address: Address,
+    symbol: String,
+    aum: FydeAmount,
+    current_concentration: FydeAmount,
+    target_concentration_deposit: FydeAmount,
+    target_concentration_withdraw: FydeAmount,
+    weight_status: String,
+    is_quarantined: bool,
+}
+
+#[derive(Serialize)]
+struct UserAssetReport {
+    address: Address,
+    symbol: String,
+    balance: FydeAmount,
+    allowance: FydeAmount,
+    st_trsy_balance: Option<FydeAmount>,
+    governance_rights: Option<FydeAmount>,
+    voting_rights: Option<FydeAmount>,
+}
+
+#[derive(Serialize)]
+struct UserReport {
+    address: Address,
+    trsy_balance: FydeAmount,
+    ve_fyde_balance: FydeAmount,
+    fyde_locked: FydeAmount,
+    unlock_date: u64,
+    assets: Vec<UserAssetReport>,
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    if let Err(e) = run(cli).await {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(cli: Cli) -> CliResult<()> {
+    let chain = parse_chain(&cli.chain)?;
+    match cli.command {
+        Command::Tvl => tvl(&provider(&cli.rpc)?, chain, cli.json).await,
+        Command::Assets => assets(&provider(&cli.rpc)?, chain, cli.json).await,
+        Command::User { address } => user(&provider(&cli.rpc)?, chain, address, cli.json).await,
+        Command::History { from, to } => {
+            history(&provider(&cli.rpc)?, chain, from, to, cli.json).await
+        }
+        Command::Proposals { limit, skip } => proposals(chain, limit, skip, cli.json).await,
+        Command::Votes {
+            proposal_id,
+            limit,
+            skip,
+        } => votes(chain, &proposal_id, limit, skip, cli.json).await,
+    }
+}
+
+fn parse_chain(chain: &str) -> CliResult<Chain> {
+    match chain.to_lowercase().as_str() {
+        "mainnet" => Ok(Chain::Mainnet),
+        "sepolia" => Ok(Chain::Sepolia),
+        _ => {
+            let address_list = AddressList::load(chain)
+                .map_err(|e| format!("Cannot load address list {}: {}", chain, e))?;
+            Ok(Chain::Custom(Box::new(address_list)))
+        }
+    }
+}
+
+fn provider(rpc: &Option<String>) -> CliResult<Arc<Client>> {
+    let rpc = rpc
+        .as_ref()
+        .ok_or("An RPC url is required, use --rpc or FYDE_RPC_URL")?;
+    Ok(Arc::new(Provider::<Http>::try_from(rpc.as_str())?))
+}
+
+async fn tvl(client: &Arc<Client>, chain: Chain, json: bool) -> CliResult<()> {
+    let liquid_vault = LiquidVault::new(client.clone(), chain).await?;
+    let tvl = FydeAmount::new(liquid_vault.get_tvl().await?, 18);
+    let trsy_supply = FydeAmount::new(liquid_vault.get_trsy_supply().await?, 18);
+    let trsy_price = tvl
+        .checked_div(&trsy_supply)
+        .unwrap_or(FydeAmount::zero(18));
+    let report = TvlReport {
+        tvl,
+        trsy_supply,
+        trsy_price,
+    };
+
+    print_report(
+        json,
+        &report,
+        &["TVL (USD)", "TRSY supply", "TRSY price (USD)"],
+        vec![vec![
+            format!("{:.2}", report.tvl),
+            format!("{:.2}", report.trsy_supply),
+            format!("{:.4}", report.trsy_price),
+        ]],
+    )
+}
+
+async fn assets(client: &Arc<Client>, chain: Chain, json: bool) -> CliResult<()> {
+    let liquid_vault = LiquidVault::new(client.clone(), chain.clone()).await?;
+    let tvl = FydeAmount::new(liquid_vault.get_tvl().await?, 18);
+
+    let mut reports = vec![];
+    for address in liquid_vault.get_assets_list().await? {
+        let asset = Asset::new(address, client.clone(), chain.clone());
+        let aum = asset.get_asset_aum().await?;
+        let current_concentration = asset.get_current_concentration(&aum, &tvl).await?;
+        // Targets are 1e18 fractions, compared and shown as percents like the current
+        // concentration
+        let target_concentrations = asset
+            .get_asset_target_concentrations()
+            .await?
+            .to_percents()
+            .ok_or("Target concentration overflow")?;
+        let weight_status = asset
+            .get_weight_status(&target_concentrations, &current_concentration)
+            .await?;
+        reports.push(AssetReport {
+            address,
+            symbol: asset.get_symbol().await?,
+            aum,
+            current_concentration,
+            target_concentration_deposit: target_concentrations.target_concentration_deposit,
+            target_concentration_withdraw: target_concentrations.target_concentration_withdraw,
+            weight_status: weight_status.to_string(),
+            is_quarantined: asset.get_is_quarantined().await?,
+        });
+    }
+
+    let rows = reports
+        .iter()
+        .map(|report| {
+            vec![
+                report.symbol.clone(),
+                format!("{:?}", report.address),
+                format!("{:.2}", report.aum),
+                format!("{:.2}", report.current_concentration),
+                format!("{:.2}", report.target_concentration_deposit),
+                format!("{:.2}", report.target_concentration_withdraw),
+                report.weight_status.clone(),
+                report.is_quarantined.to_string(),
+            ]
+        })
+        .collect();
+    print_report(
+        json,
+        &reports,
+        &[
+            "Symbol",
+            "Address",
+            "AUM (USD)",
+            "Concentration (%)",
+            "Deposit target (%)",
+            "Withdraw target (%)",
+            "Weight",
+            "Quarantined",
+        ],
+        rows,
+    )
+}
+
+async fn user(client: &Arc<Client>, chain: Chain, address: Address, json: bool) -> CliResult<()> {
+    let liquid_vault = LiquidVault::new(client.clone(), chain.clone()).await?;
+    let assets = liquid_vault.get_assets_list().await?;
+    let user = User::new(client.clone(), chain.clone(), address).await?;
+    let balances = user.get_balances(&assets).await?;
+    let allowances = user.get_allowances(&assets).await?;
+
+    let mut assets_in_gov = vec![];
+    let mut asset_infos = vec![];
+    for address in &assets {
+        let asset = Asset::new(*address, client.clone(), chain.clone());
+        if asset.get_is_allowed_on_governance().await? {
+            assets_in_gov.push(*address);
+        }
+        asset_infos.push((
+            *address,
+            asset.get_symbol().await?,
+            asset.get_decimals().await?,
+        ));
+    }
+    let governance_data = user.get_governance_data(&assets_in_gov).await?;
+    let ve_fyde_user = VeFyde::new(client.clone(), chain)
+        .await?
+        .get_ve_fyde_data(address, false)
+        .await?;
+
+    let trsy_amount = |value: Option<&U256>| value.map(|value| FydeAmount::new(*value, 18));
+    let report = UserReport {
+        address,
+        trsy_balance: FydeAmount::new(user.get_trsy_balance().await?, 18),
+        ve_fyde_balance: FydeAmount::new(ve_fyde_user.ve_fyde_balance.into(), 18),
+        fyde_locked: FydeAmount::new(ve_fyde_user.fyde_locked.into(), 18),
+        unlock_date: ve_fyde_user.unlock_date,
+        assets: asset_infos
+            .into_iter()
+            .map(|(address, symbol, decimals)| {
+                let amount = |values: &std::collections::HashMap<Address, U256>| {
+                    FydeAmount::new(values.get(&address).copied().unwrap_or_default(), decimals)
+                };
+                UserAssetReport {
+                    address,
+                    symbol,
+                    balance: amount(&balances),
+                    allowance: amount(&allowances),
+                    st_trsy_balance: trsy_amount(governance_data.st_trsy_balance.get(&address)),
+                    governance_rights: trsy_amount(
+                        governance_data.current_governance_rights.get(&address),
+                    ),
+                    voting_rights: trsy_amount(governance_data.total_voting_rights.get(&address)),
+                }
+            })
+            .collect(),
+    };
+
+    if json {
+        return print_json(&report);
+    }
+    print_table(
+        &[
+            "TRSY balance",
+            "veFYDE balance",
+            "FYDE locked",
+            "Unlock date",
+        ],
+        &[vec![
+            format!("{:.4}", report.trsy_balance),
+            format!("{:.4}", report.ve_fyde_balance),
+            format!("{:.4}", report.fyde_locked),
+            format_date(report.unlock_date),
+        ]],
+    );
+    println!();
+    let optional = |amount: &Option<FydeAmount>| match amount {
+        Some(amount) => format!("{:.4}", amount),
+        None => String::from("-"),
+    };
+    let rows: Vec<Vec<String>> = report
+        .assets
+        .iter()
+        .map(|asset| {
+            vec![
+                asset.symbol.clone(),
+                format!("{:.4}", asset.balance),
+                format!("{:.4}", asset.allowance),
+                optional(&asset.st_trsy_balance),
+                optional(&asset.governance_rights),
+                optional(&asset.voting_rights),
+            ]
+        })
+        .collect();
+    print_table(
+        &[
+            "Asset",
+            "Balance",
+            "Allowance",
+            "stTRSY",
+            "Governance rights",
+            "Voting rights",
+        ],
+        &rows,
+    );
+    Ok(())
+}
+
+async fn history(
+    client: &Arc<Client>,
+    chain: Chain,
+    from: Option<u64>,
+    to: Option<u64>,
+    json: bool,
+) -> CliResult<()> {
+    let user_actions = ProtocolHistory::new(client.clone(), chain)
+        .get_data(from, to)
+        .await?;
+
+    let rows = user_actions
+        .iter()
+        .map(|action| {
+            let (kind, user, request_id, timestamp, trsy) = match action {
+                UserAction::Deposit {
+                    user,
+                    request_id,
+                    timestamp,
+                    trsy_minted,
+                    ..
+                } => ("Deposit", user, request_id, timestamp, Some(trsy_minted)),
+                UserAction::Withdraw {
+                    user,
+                    request_id,
+                    timestamp,
+                    trsy_burned,
+                    ..
+                } => ("Withdraw", user, request_id, timestamp, Some(trsy_burned)),
+                UserAction::Swap {
+                    user,
+                    request_id,
+                    timestamp,
+                    ..
+                } => ("Swap", user, request_id, timestamp, None),
+            };
+            vec![
+                kind.to_string(),
+                action.block_number().to_string(),
+                format_date(*timestamp),
+                request_id.to_string(),
+                format!("{:?}", user),
+                trsy.map_or(String::from("-"), |trsy| {
+                    format!("{:.4}", FydeAmount::new(*trsy, 18))
+                }),
+                format!("{:?}", action.tx_hash()),
+            ]
+        })
+        .collect();
+    print_report(
+        json,
+        &user_actions,
+        &[
+            "Action", "Block", "Date", "Request", "User", "TRSY", "Tx hash",
+        ],
+        rows,
+    )
+}
+
+async fn proposals(chain: Chain, limit: usize, skip: usize, json: bool) -> CliResult<()> {
+    let proposals = Snapshot::new(chain).fetch_proposals(limit, skip).await?;
+
+    let rows = proposals
+        .iter()
+        .map(|proposal| {
+            vec![
+                proposal.id.clone(),
+                proposal.title.clone(),
+                proposal.state.clone(),
+                format_date(proposal.start),
+                format_date(proposal.end),
+                format!("{:.2}", proposal.scores_total),
+            ]
+        })
+        .collect();
+    print_report(
+        json,
+        &proposals,
+        &["Id", "Title", "State", "Start", "End", "Total score"],
+        rows,
+    )
+}
+
+async fn votes(
+    chain: Chain,
+    proposal_id: &str,
+    limit: usize,
+    skip: usize,
+    json: bool,
+) -> CliResult<()> {
+    let votes = Snapshot::new(chain)
+        .fetch_votes(proposal_id, limit, skip)
+        .await?;
+
+    let rows = votes
+        .iter()
+        .map(|vote| {
+            vec![
+                vote.voter.clone(),
+                format!("{:.2}", vote.vp),
+                vote.choice.to_string(),
+                format_date(vote.created),
+            ]
+        })
+        .collect();
+    print_report(
+        json,
+        &votes,
+        &["Voter", "Voting power", "Choice", "Date"],
+        rows,
+    )
+}
+
+fn print_report<T: Serialize>(
+    json: bool,
+    value: &T,
+    headers: &[&str],
+    rows: Vec<Vec<String>>,
+) -> CliResult<()> {
+    match json {
+        true => print_json(value),
+        false => {
+            print_table(headers, &rows);
+            Ok(())
+        }
+    }
+}
+
+fn print_json<T: Serialize>(value: &T) -> CliResult<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: &[String]| {
+        let cells: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", cells.join("  ").trim_end());
+    };
+    let headers: Vec<String> = headers.iter().map(|header| header.to_string()).collect();
+    let separator: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+    line(&headers);
+    line(&separator);
+    for row in rows {
+        line(row);
+    }
+}
+
+fn format_date(timestamp: u64) -> String {
+    DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|date| date.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
@@ -0,0 +1,483 @@
+use ethers::{
+    contract::LogMeta,
+    providers::Middleware,
+    types::{Address, BlockId, H256},
+};
+use futures::future::try_join_all;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    fs, io,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+use tokio::sync::OnceCell;
+
+use crate::errors::{DataError, EventContext, FydeError};
+
+pub const DEFAULT_BATCH_SIZE: usize = 20;
+/// Number of blocks kept by a [`MemoryStore`]
+pub const DEFAULT_MEMORY_CAPACITY: usize = 10_000;
+
+/// What history queries need from a block
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockMeta {
+    pub hash: H256,
+    pub timestamp: u64,
+    /// Sender of each transaction of the block
+    pub senders: HashMap<H256, Address>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxMeta {
+    pub tx_hash: H256,
+    pub block_number: u64,
+    pub timestamp: u64,
+    pub from: Address,
+}
+
+/// Cached blocks are keyed by chain, so a store can be shared between chains
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockKey {
+    pub chain_id: u64,
+    pub number: u64,
+}
+
+/// Storage of the cached blocks
+pub trait BlockMetaStore: Send + Sync {
+    fn get(&self, key: BlockKey) -> Option<BlockMeta>;
+    fn insert(&self, key: BlockKey, meta: BlockMeta) -> io::Result<()>;
+    fn remove(&self, key: BlockKey) -> io::Result<()>;
+}
+
+/// Keeps the last `capacity` blocks inserted
+#[derive(Debug)]
+pub struct MemoryStore {
+    blocks: RwLock<(HashMap<BlockKey, BlockMeta>, VecDeque<BlockKey>)>,
+    capacity: usize,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_MEMORY_CAPACITY)
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            blocks: RwLock::new((HashMap::new(), VecDeque::new())),
+            capacity: capacity.max(1),
+        }
+    }
+}
+
+impl BlockMetaStore for MemoryStore {
+    fn get(&self, key: BlockKey) -> Option<BlockMeta> {
+        self.blocks.read().ok()?.0.get(&key).cloned()
+    }
+
+    fn insert(&self, key: BlockKey, meta: BlockMeta) -> io::Result<()> {
+        if let Ok(mut blocks) = self.blocks.write() {
+            let (blocks, order) = &mut *blocks;
+            if blocks.insert(key, meta).is_none() {
+                order.push_back(key);
+            }
+            while blocks.len() > self.capacity {
+                match order.pop_front() {
+                    Some(oldest) => blocks.remove(&oldest),
+                    None => break,
+                };
+            }
+        }
+        Ok(())
+    }
+
+    fn remove(&self, key: BlockKey) -> io::Result<()> {
+        if let Ok(mut blocks) = self.blocks.write() {
+            let (blocks, order) = &mut *blocks;
+            if blocks.remove(&key).is_some() {
+                order.retain(|cached| *cached != key);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One JSON file per block in a directory per chain, the recent ones kept in memory
+#[derive(Debug)]
+pub struct DiskStore {
+    dir: PathBuf,
+    memory: MemoryStore,
+}
+
+impl DiskStore {
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            memory: MemoryStore::new(),
+        })
+    }
+
+    fn path(&self, key: BlockKey) -> PathBuf {
+        self.dir
+            .join(key.chain_id.to_string())
+            .join(format!("{}.json", key.number))
+    }
+}
+
+impl BlockMetaStore for DiskStore {
+    fn get(&self, key: BlockKey) -> Option<BlockMeta> {
+        if let Some(meta) = self.memory.get(key) {
+            return Some(meta);
+        }
+        let content = fs::read_to_string(self.path(key)).ok()?;
+        let meta: BlockMeta = serde_json::from_str(&content).ok()?;
+        self.memory.insert(key, meta.clone()).ok()?;
+        Some(meta)
+    }
+
+    fn insert(&self, key: BlockKey, meta: BlockMeta) -> io::Result<()> {
+        let path = self.path(key);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string(&meta)?)?;
+        self.memory.insert(key, meta)
+    }
+
+    fn remove(&self, key: BlockKey) -> io::Result<()> {
+        match fs::remove_file(self.path(key)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        self.memory.remove(key)
+    }
+}
+
+/// Deduplicating cache of block timestamps and transaction senders, so that events
+/// sharing a block only cost one `eth_getBlockByNumber` call.
+pub struct BlockMetaCache<M: Middleware> {
+    client: Arc<M>,
+    store: Arc<dyn BlockMetaStore>,
+    chain_id: Arc<OnceCell<u64>>,
+    batch_size: usize,
+}
+
+impl<M: Middleware> Clone for BlockMetaCache<M> {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            store: self.store.clone(),
+            chain_id: self.chain_id.clone(),
+            batch_size: self.batch_size,
+        }
+    }
+}
+
+impl<M: Middleware> BlockMetaCache<M> {
+    /// In-memory cache
+    pub fn new(client: Arc<M>) -> Self {
+        Self::with_store(client, Arc::new(MemoryStore::new()))
+    }
+
+    pub fn with_store(client: Arc<M>, store: Arc<dyn BlockMetaStore>) -> Self {
+        Self {
+            client,
+            store,
+            chain_id: Arc::new(OnceCell::new()),
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+
+    /// Number of blocks requested concurrently
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Set the chain id instead of reading it from the client on first use
+    pub fn chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = Arc::new(OnceCell::new_with(Some(chain_id)));
+        self
+    }
+
+    async fn key(&self, number: u64) -> Result<BlockKey, FydeError<M>> {
+        let chain_id = *self
+            .chain_id
+            .get_or_try_init(|| async {
+                self.client
+                    .get_chainid()
+                    .await
+                    .map(|chain_id| chain_id.low_u64())
+                    .map_err(FydeError::MiddlewareError)
+            })
+            .await?;
+        Ok(BlockKey { chain_id, number })
+    }
+
+    /// Fetch the blocks missing from the cache, `batch_size` at a time
+    pub async fn prefetch(
+        &self,
+        block_numbers: impl IntoIterator<Item = u64>,
+    ) -> Result<(), FydeError<M>> {
+        let mut missing = vec![];
+        for number in block_numbers.into_iter().collect::<BTreeSet<u64>>() {
+            let key = self.key(number).await?;
+            if self.store.get(key).is_none() {
+                missing.push(key);
+            }
+        }
+
+        for batch in missing.chunks(self.batch_size) {
+            let blocks =
+                try_join_all(batch.iter().map(|key| self.fetch(key.number.into()))).await?;
+            for (key, meta) in batch.iter().zip(blocks) {
+                self.store.insert(*key, meta).map_err(DataError::from)?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn fetch(&self, block: BlockId) -> Result<BlockMeta, FydeError<M>> {
+        let context = match block {
+            BlockId::Number(number) => {
+                EventContext::default().with_block(number.as_number().unwrap_or_default().as_u64())
+            }
+            BlockId::Hash(_) => EventContext::default(),
+        };
+        let block = self
+            .client
+            .get_block_with_txs(block)
+            .await
+            .map_err(FydeError::MiddlewareError)?
+            .ok_or_else(|| DataError::missing("block", context.clone()))?;
+        let timestamp = u64::try_from(block.timestamp)
+            .map_err(|_| DataError::out_of_range("block timestamp", context))?;
+
+        Ok(BlockMeta {
+            hash: block.hash.unwrap_or_default(),
+            timestamp,
+            senders: block
+                .transactions
+                .iter()
+                .map(|tx| (tx.hash, tx.from))
+                .collect(),
+        })
+    }
+
+    pub async fn get(&self, block_number: u64) -> Result<BlockMeta, FydeError<M>> {
+        let key = self.key(block_number).await?;
+        if let Some(meta) = self.store.get(key) {
+            return Ok(meta);
+        }
+        self.prefetch([block_number]).await?;
+        self.store.get(key).ok_or_else(|| {
+            DataError::missing(
+                "cached block",
+                EventContext::default().with_block(block_number),
+            )
+            .into()
+        })
+    }
+
+    /// Block `block_number` with hash `block_hash`. A cached block with another hash was
+    /// reorged out, it is replaced by the block fetched by hash.
+    pub async fn get_at(
+        &self,
+        block_number: u64,
+        block_hash: H256,
+    ) -> Result<BlockMeta, FydeError<M>> {
+        let key = self.key(block_number).await?;
+        if let Some(meta) = self.store.get(key) {
+            if meta.hash == block_hash {
+                return Ok(meta);
+            }
+        }
+        let meta = self.fetch(block_hash.into()).await?;
+        if meta.hash != block_hash {
+            return Err(DataError::inconsistent(
+                format!("block hash {:?}", meta.hash),
+                EventContext::default().with_block(block_number),
+            )
+            .into());
+        }
+        self.store
+            .insert(key, meta.clone())
+            .map_err(DataError::from)?;
+        Ok(meta)
+    }
+
+    /// Block of a log, checked against the log block hash
+    pub async fn get_for_log(&self, meta: &LogMeta) -> Result<BlockMeta, FydeError<M>> {
+        self.get_at(meta.block_number.as_u64(), meta.block_hash)
+            .await
+    }
+
+    /// Drop a cached block, e.g. after it was reorged out
+    pub async fn invalidate(&self, block_number: u64) -> Result<(), FydeError<M>> {
+        let key = self.key(block_number).await?;
+        self.store
+            .remove(key)
+            .map_err(|e| DataError::from(e).into())
+    }
+
+    /// Sender and timestamp of a transaction of a log
+    pub async fn tx_meta(&self, meta: &LogMeta) -> Result<TxMeta, FydeError<M>> {
+        let block_number = meta.block_number.as_u64();
+        let tx_hash = meta.transaction_hash;
+        let block = self.get_for_log(meta).await?;
+        let from = *block.senders.get(&tx_hash).ok_or_else(|| {
+            DataError::missing(
+                "transaction in block",
+                EventContext::tx(tx_hash).with_block(block_number),
+            )
+        })?;
+
+        Ok(TxMeta {
+            tx_hash,
+            block_number,
+            timestamp: block.timestamp,
+            from,
+        })
+    }
+}
+
+/// Clients reading block timestamps and senders through a [`BlockMetaCache`]
+pub trait WithBlockCache<M: Middleware>: Sized {
+    fn block_cache_mut(&mut self) -> &mut BlockMetaCache<M>;
+
+    /// Use a custom block cache (disk backed, shared between clients)
+    fn with_block_cache(mut self, block_cache: BlockMetaCache<M>) -> Self {
+        *self.block_cache_mut() = block_cache;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        providers::Provider,
+        types::{Block, Transaction, U256, U64},
+    };
+
+    fn block(number: u64, hash: H256, tx_hash: H256, from: Address) -> Block<Transaction> {
+        Block {
+            number: Some(U64::from(number)),
+            hash: Some(hash),
+            timestamp: U256::from(1_700_000_000 + number),
+            transactions: vec![Transaction {
+                hash: tx_hash,
+                from,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_deduplicates_blocks() {
+        let (provider, mock) = Provider::mocked();
+        let tx_hash = H256::from_low_u64_be(1);
+        let from = Address::from_low_u64_be(2);
+        // Responses are served last in, first out, and only two are available
+        mock.push(block(11, H256::from_low_u64_be(11), tx_hash, from))
+            .unwrap();
+        mock.push(block(10, H256::from_low_u64_be(10), tx_hash, from))
+            .unwrap();
+
+        let cache = BlockMetaCache::new(Arc::new(provider)).chain_id(1);
+        cache.prefetch([10, 11, 10, 11, 10]).await.unwrap();
+
+        let log = |number: u64, tx_hash| LogMeta {
+            address: Address::zero(),
+            block_number: U64::from(number),
+            block_hash: H256::from_low_u64_be(number),
+            transaction_hash: tx_hash,
+            transaction_index: U64::zero(),
+            log_index: U256::zero(),
+        };
+        let tx_meta = cache.tx_meta(&log(11, tx_hash)).await.unwrap();
+        assert_eq!(tx_meta.from, from);
+        assert_eq!(tx_meta.timestamp, 1_700_000_011);
+        assert!(cache.tx_meta(&log(10, H256::zero())).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_at_replaces_reorged_block() {
+        let (provider, mock) = Provider::mocked();
+        let (tx_hash, from) = (H256::from_low_u64_be(1), Address::from_low_u64_be(2));
+        let canonical = H256::from_low_u64_be(0xc0ffee);
+        mock.push(block(10, canonical, tx_hash, from)).unwrap();
+        mock.push(block(10, H256::from_low_u64_be(0xbad), tx_hash, from))
+            .unwrap();
+
+        let cache = BlockMetaCache::new(Arc::new(provider)).chain_id(1);
+        assert_eq!(
+            cache.get(10).await.unwrap().hash,
+            H256::from_low_u64_be(0xbad)
+        );
+        assert_eq!(cache.get_at(10, canonical).await.unwrap().hash, canonical);
+        // Cached from now on
+        assert_eq!(cache.get(10).await.unwrap().hash, canonical);
+    }
+
+    #[test]
+    fn test_stores() {
+        let dir = std::env::temp_dir().join(format!("fyde-block-cache-{}", std::process::id()));
+        let meta = BlockMeta {
+            hash: H256::from_low_u64_be(7),
+            timestamp: 42,
+            senders: HashMap::from([(H256::from_low_u64_be(1), Address::from_low_u64_be(2))]),
+        };
+        let mainnet = BlockKey {
+            chain_id: 1,
+            number: 7,
+        };
+        let sepolia = BlockKey {
+            chain_id: 11_155_111,
+            number: 7,
+        };
+
+        DiskStore::open(&dir)
+            .unwrap()
+            .insert(mainnet, meta.clone())
+            .unwrap();
+        assert_eq!(
+            DiskStore::open(&dir).unwrap().get(mainnet),
+            Some(meta.clone())
+        );
+        assert_eq!(DiskStore::open(&dir).unwrap().get(sepolia), None);
+        fs::remove_dir_all(dir).unwrap();
+
+        let memory = MemoryStore::with_capacity(2);
+        for number in 0..3 {
+            let key = BlockKey {
+                chain_id: 1,
+                number,
+            };
+            memory.insert(key, meta.clone()).unwrap();
+        }
+        assert_eq!(
+            memory.get(BlockKey {
+                chain_id: 1,
+                number: 0
+            }),
+            None
+        );
+        assert_eq!(
+            memory.get(BlockKey {
+                chain_id: 1,
+                number: 2
+            }),
+            Some(meta)
+        );
+    }
+}
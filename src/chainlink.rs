@@ -0,0 +1,248 @@
+use ethers::{
+    contract::Multicall,
+    providers::Middleware,
+    types::{Address, H160, I256, U256},
+};
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::{
+    errors::{DataError, EventContext, FydeError},
+    liquid_vault::LiquidVault,
+    oracle::{deviation_bps, Oracle},
+    utils::{latest_timestamp, FydeAmount},
+    AddressList, Chain, ChainlinkFeedRegistry, LiquidVaultContract,
+};
+
+/// Registry denomination of USD, `0x...0348`
+pub const USD_DENOMINATION: Address = H160([
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x03, 0x48,
+]);
+/// Registry denomination of ETH, `0xEeee...EEeE`
+pub const ETH_DENOMINATION: Address = H160([0xee; 20]);
+
+pub struct Chainlink<M: Middleware> {
+    registry: ChainlinkFeedRegistry<M>,
+    oracle: Oracle<M>,
+    liquid_vault: LiquidVaultContract<M>,
+    vault: LiquidVault<M>,
+    multicall: Multicall<M>,
+    client: Arc<M>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ChainlinkRound {
+    pub base: Address,
+    pub quote: Address,
+    pub feed: Address,
+    pub round_id: u128,
+    pub answer: I256,
+    pub decimals: u8,
+    pub started_at: u64,
+    pub updated_at: u64,
+    pub answered_in_round: u128,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ChainlinkAssetReport {
+    pub asset: Address,
+    /// Round of the asset feed, none when the registry has no feed for the pair
+    pub round: Option<ChainlinkRound>,
+    /// ETH/USD round used to convert ETH pairs
+    pub eth_usd_round: Option<ChainlinkRound>,
+    pub chainlink_price: Option<FydeAmount>,
+    /// Price of the asset from `LiquidVault.getQuote`
+    pub fyde_price: FydeAmount,
+    /// Deviation of the Fyde quote from the Chainlink price, in basis points
+    pub deviation_bps: Option<u64>,
+    /// A round used is older than the oracle module stale period
+    pub stale: bool,
+    /// A round used is not finished or has no positive answer
+    pub incomplete: bool,
+}
+
+impl ChainlinkRound {
+    /// Answer with 18 decimals, none when not positive
+    pub fn price(&self) -> Option<FydeAmount> {
+        if self.answer <= I256::zero() {
+            return None;
+        }
+        FydeAmount::new(self.answer.into_raw(), self.decimals).rescale(18)
+    }
+
+    pub fn is_incomplete(&self) -> bool {
+        self.updated_at == 0
+            || self.answered_in_round < self.round_id
+            || self.answer <= I256::zero()
+    }
+
+    pub fn is_stale(&self, timestamp: u64, stale_period: u32) -> bool {
+        timestamp.saturating_sub(self.updated_at) > stale_period as u64
+    }
+}
+
+impl<M: Middleware> Chainlink<M> {
+    /// Client of the feed registry set in the oracle module
+    pub async fn new(client: Arc<M>, chain: Chain) -> Result<Self, FydeError<M>> {
+        let address_list: AddressList = AddressList::new(&chain);
+        let oracle = Oracle::new(client.clone(), chain.clone()).await?;
+        let registry = oracle.get_config().await?.cl_registry;
+        Ok(Self {
+            registry: ChainlinkFeedRegistry::new(registry, client.clone()),
+            oracle,
+            liquid_vault: LiquidVaultContract::new(address_list.liquid_vault, client.clone()),
+            vault: LiquidVault::new(client.clone(), chain).await?,
+            multicall: Multicall::new(client.clone(), None).await?,
+            client,
+        })
+    }
+
+    /// Latest round of the `base`/`quote` pair, none when the registry has no feed for it
+    pub async fn get_latest_round(
+        &self,
+        base: Address,
+        quote: Address,
+    ) -> Result<Option<ChainlinkRound>, FydeError<M>> {
+        let feed = match self.registry.get_feed(base, quote).call().await {
+            Ok(feed) => feed,
+            Err(err) if err.is_revert() => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut multicall = self.multicall.clone();
+        multicall.clear_calls();
+        multicall.add_call(self.registry.latest_round_data(base, quote), false);
+        multicall.add_call(self.registry.decimals(base, quote), false);
+        let ((round_id, answer, started_at, updated_at, answered_in_round), decimals): (
+            (u128, I256, U256, U256, u128),
+            u8,
+        ) = multicall.call().await?;
+
+        let to_u64 = |value: U256, what: &str| {
+            u64::try_from(value).map_err(|_| {
+                DataError::out_of_range(
+                    format!("Chainlink {} of {:?}", what, feed),
+                    EventContext::default(),
+                )
+            })
+        };
+
+        Ok(Some(ChainlinkRound {
+            base,
+            quote,
+            feed,
+            round_id,
+            answer,
+            decimals,
+            started_at: to_u64(started_at, "startedAt")?,
+            updated_at: to_u64(updated_at, "updatedAt")?,
+            answered_in_round,
+        }))
+    }
+
+    /// Chainlink price of `asset` next to its Fyde quote, through the ETH pair when the
+    /// oracle module is configured so
+    pub async fn get_asset_report(
+        &self,
+        asset: Address,
+        stale_period: u32,
+        timestamp: u64,
+    ) -> Result<ChainlinkAssetReport, FydeError<M>> {
+        let asset_info = self.oracle.get_asset_info(asset).await?;
+        let use_eth_pair = self.oracle.uses_chainlink_eth_pair(asset).await?;
+
+        let (round, eth_usd_round) = match use_eth_pair {
+            true => (
+                self.get_latest_round(asset, ETH_DENOMINATION).await?,
+                self.get_latest_round(ETH_DENOMINATION, USD_DENOMINATION)
+                    .await?,
+            ),
+            false => (self.get_latest_round(asset, USD_DENOMINATION).await?, None),
+        };
+        let rounds: Vec<&ChainlinkRound> = round.iter().chain(eth_usd_round.iter()).collect();
+        let chainlink_price = match (&round, &eth_usd_round, use_eth_pair) {
+            (Some(round), _, false) => round.price(),
+            (Some(round), Some(eth_usd), true) => round
+                .price()
+                .zip(eth_usd.price())
+                .and_then(|(in_eth, eth_price)| in_eth.checked_mul(&eth_price)),
+            _ => None,
+        };
+
+        let amount = U256::from(10).pow(U256::from(asset_info.asset_decimals));
+        let fyde_price =
+            FydeAmount::new(self.liquid_vault.get_quote(asset, amount).call().await?, 18);
+
+        Ok(ChainlinkAssetReport {
+            asset,
+            deviation_bps: chainlink_price
+                .as_ref()
+                .and_then(|price| deviation_bps(&fyde_price, price)),
+            stale: rounds
+                .iter()
+                .any(|round| round.is_stale(timestamp, stale_period)),
+            incomplete: chainlink_price.is_none()
+                || rounds.iter().any(|round| round.is_incomplete()),
+            round,
+            eth_usd_round,
+            chainlink_price,
+            fyde_price,
+        })
+    }
+
+    /// Report of every asset of the protocol, against the latest block
+    pub async fn get_reports(&self) -> Result<Vec<ChainlinkAssetReport>, FydeError<M>> {
+        let stale_period = self.oracle.get_config().await?.stale_period;
+        let timestamp = latest_timestamp(self.client.as_ref()).await?;
+
+        let mut reports = vec![];
+        for asset in self.vault.get_assets_list().await? {
+            reports.push(
+                self.get_asset_report(asset, stale_period, timestamp)
+                    .await?,
+            );
+        }
+        Ok(reports)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_flags() {
+        let round = ChainlinkRound {
+            base: Address::random(),
+            quote: USD_DENOMINATION,
+            feed: Address::random(),
+            round_id: 10,
+            answer: I256::from(200_012_345_678i64),
+            decimals: 8,
+            started_at: 1_000,
+            updated_at: 1_000,
+            answered_in_round: 10,
+        };
+        assert_eq!(round.price(), Some("2000.12345678".parse().unwrap()));
+        assert!(!round.is_incomplete());
+        assert!(!round.is_stale(4_600, 3_600));
+        assert!(round.is_stale(4_601, 3_600));
+
+        let carried_over = ChainlinkRound {
+            answered_in_round: 9,
+            ..round.clone()
+        };
+        assert!(carried_over.is_incomplete());
+        let negative = ChainlinkRound {
+            answer: I256::from(-1),
+            ..round
+        };
+        assert!(negative.is_incomplete());
+        assert_eq!(negative.price(), None);
+
+        assert_eq!(
+            format!("{:?}", USD_DENOMINATION),
+            "0x0000000000000000000000000000000000000348"
+        );
+    }
+}
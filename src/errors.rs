@@ -1,12 +1,148 @@
-use ethers::prelude::{ContractError, Http, MulticallError, Provider};
+use ethers::prelude::{Address, ContractError, Middleware, MulticallError, H256};
+use std::fmt;
+
+use crate::log_scanner::ScanResult;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
-pub enum FydeError {
+pub enum FydeError<M: Middleware> {
     #[error("Provider error: {0}")]
     ProviderError(#[from] ethers::providers::ProviderError),
+    #[error("Middleware error: {0}")]
+    MiddlewareError(M::Error),
+    /// A log scan failed, `partial` holds the logs found before and where to resume
+    #[error("Log scan interrupted at block {}: {error}", partial.cursor.next_block)]
+    ScanInterrupted {
+        partial: Box<ScanResult>,
+        error: M::Error,
+    },
     #[error("Contract error")]
-    ContractError(#[from] ContractError<Provider<Http>>),
+    ContractError(#[from] ContractError<M>),
     #[error("Multicall error")]
-    MulticallError(#[from] MulticallError<Provider<Http>>),
+    MulticallError(#[from] MulticallError<M>),
+    #[error(transparent)]
+    DataError(#[from] DataError),
+    #[error("Invalid address for {name} ({address:?}): {reason}")]
+    InvalidAddress {
+        name: String,
+        address: Address,
+        reason: String,
+    },
+    #[error("Arithmetic error: {0}")]
+    ArithmeticError(String),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Protocol is paused")]
+    ProtocolPaused,
+    #[error("Swaps are paused")]
+    SwapPaused,
+    #[error("Asset {0:?} is not supported")]
+    UnsupportedAsset(Address),
+    #[error("Asset {0:?} is quarantined")]
+    QuarantinedAsset(Address),
+    #[error("Swap not allowed for asset {0:?}")]
+    SwapNotAllowed(Address),
+    #[error("Asset {0:?} is not on the governance whitelist")]
+    NotOnGovernanceWhitelist(Address),
+}
+
+/// Errors on remote data (HTTP APIs and on-chain events), independent of the middleware
+#[derive(Debug, Error)]
+pub enum DataError {
+    #[error("HTTP error: {0}")]
+    HttpError(#[from] reqwest::Error),
+    #[error("GraphQL error: {0}")]
+    GraphQLError(String),
+    #[error("Decoding error: {0}")]
+    DecodingError(#[from] serde_json::Error),
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[cfg(feature = "storage")]
+    #[error("Storage error: {0}")]
+    StorageError(#[from] sqlx::Error),
+    #[error("Missing {what}{context}")]
+    MissingData { what: String, context: EventContext },
+    #[error("Inconsistent event: {reason}{context}")]
+    InconsistentEvent {
+        reason: String,
+        context: EventContext,
+    },
+    #[error("Value out of range: {what}{context}")]
+    OutOfRange { what: String, context: EventContext },
+}
+
+impl DataError {
+    pub fn missing(what: impl Into<String>, context: EventContext) -> Self {
+        DataError::MissingData {
+            what: what.into(),
+            context,
+        }
+    }
+
+    pub fn inconsistent(reason: impl Into<String>, context: EventContext) -> Self {
+        DataError::InconsistentEvent {
+            reason: reason.into(),
+            context,
+        }
+    }
+
+    pub fn out_of_range(what: impl Into<String>, context: EventContext) -> Self {
+        DataError::OutOfRange {
+            what: what.into(),
+            context,
+        }
+    }
+}
+
+/// Where a piece of remote data was expected
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventContext {
+    pub request_id: Option<u32>,
+    pub tx_hash: Option<H256>,
+    pub block_number: Option<u64>,
+}
+
+impl EventContext {
+    pub fn request(request_id: u32) -> Self {
+        Self {
+            request_id: Some(request_id),
+            ..Default::default()
+        }
+    }
+
+    pub fn tx(tx_hash: H256) -> Self {
+        Self {
+            tx_hash: Some(tx_hash),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_block(mut self, block_number: u64) -> Self {
+        self.block_number = Some(block_number);
+        self
+    }
+
+    pub fn with_tx(mut self, tx_hash: H256) -> Self {
+        self.tx_hash = Some(tx_hash);
+        self
+    }
+}
+
+impl fmt::Display for EventContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = vec![];
+        if let Some(request_id) = self.request_id {
+            parts.push(format!("request {}", request_id));
+        }
+        if let Some(tx_hash) = self.tx_hash {
+            parts.push(format!("tx {:?}", tx_hash));
+        }
+        if let Some(block_number) = self.block_number {
+            parts.push(format!("block {}", block_number));
+        }
+        match parts.is_empty() {
+            true => Ok(()),
+            false => write!(f, " ({})", parts.join(", ")),
+        }
+    }
 }
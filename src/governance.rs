@@ -1,40 +1,38 @@
 use ethers::{
     contract::Multicall,
-    providers::{Http, Provider},
+    providers::Middleware,
     types::{Address, I256},
 };
 use std::sync::Arc;
 
 use crate::{errors::FydeError, AddressList, Chain, GovernanceModuleContract};
 
-pub struct Governance {
-    governance_module: GovernanceModuleContract<Provider<Http>>,
-    multicall: Multicall<Provider<Http>>,
+pub struct Governance<M: Middleware> {
+    governance_module: GovernanceModuleContract<M>,
+    multicall: Multicall<M>,
 }
 
-impl Governance {
-    pub async fn new(provider: Arc<Provider<Http>>, chain: Chain) -> Self {
+impl<M: Middleware> Governance<M> {
+    pub async fn new(provider: Arc<M>, chain: Chain) -> Result<Self, FydeError<M>> {
         let address_list: AddressList = AddressList::new(&chain);
 
         let governance_module =
             GovernanceModuleContract::new(address_list.governance_module, provider.clone());
-        let multicall = Multicall::new(provider, None)
-            .await
-            .expect("Failed to create Multicall");
-        Self {
+        let multicall = Multicall::new(provider, None).await?;
+        Ok(Self {
             governance_module,
             multicall,
-        }
+        })
     }
 
-    pub async fn get_list_of_governance_users(&self) -> Result<Vec<Address>, FydeError> {
+    pub async fn get_list_of_governance_users(&self) -> Result<Vec<Address>, FydeError<M>> {
         Ok(self.governance_module.get_all_gov_users().call().await?)
     }
 
     pub async fn get_proxy_to_rebalance(
         &mut self,
         asset: Address,
-    ) -> Result<Vec<Address>, FydeError> {
+    ) -> Result<Vec<Address>, FydeError<M>> {
         let gov_users = self.get_list_of_governance_users().await?;
 
         self.multicall.clear_calls();
@@ -51,12 +49,12 @@ impl Governance {
         // First, create a vector of tuples containing Address and I256 values
         let mut user_unbalance: Vec<(Address, I256)> = gov_users
             .into_iter()
-            .zip(unbalance.into_iter())
+            .zip(unbalance)
             .filter(|&(_, unbalance)| unbalance != I256::zero())
             .collect();
 
         // Sort the vector of tuples based on I256 values in ascending order
-        user_unbalance.sort_by(|a, b| a.1.cmp(&b.1));
+        user_unbalance.sort_by_key(|&(_, unbalance)| unbalance);
 
         // Extract the sorted gov_users from the sorted vector of tuples
         let sorted_gov_users: Vec<Address> =
@@ -0,0 +1,363 @@
+use ethers::{providers::Middleware, types::H256};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::{
+    block_cache::{BlockMetaCache, WithBlockCache},
+    errors::{DataError, EventContext, FydeError},
+    protocol_history::{
+        FeeEvent, PendingRequests, ProtocolHistory, StakingUnstaking, UserAction, VeFydeLock,
+    },
+    Chain,
+};
+
+pub const DEFAULT_CONFIRMATIONS: u64 = 12;
+/// Number of blocks below the confirmed head whose hashes are kept to detect deep reorgs
+pub const DEFAULT_REORG_WINDOW: u64 = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockRef {
+    pub number: u64,
+    pub hash: H256,
+}
+
+#[derive(Debug, Serialize)]
+pub enum IndexerEvent {
+    UserAction {
+        /// Block of the Relayer request
+        block: BlockRef,
+        /// Block of the LiquidVault event processing the request
+        processed: BlockRef,
+        action: UserAction,
+    },
+    StakingUnstaking {
+        block: BlockRef,
+        record: StakingUnstaking,
+    },
+    VeFydeLock {
+        block: BlockRef,
+        lock: VeFydeLock,
+    },
+    Fee {
+        block: BlockRef,
+        fee: FeeEvent,
+    },
+    /// The block was reorged out, records indexed from it must be rolled back
+    Retracted(BlockRef),
+}
+
+/// Persist it to restart the indexer where it stopped
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexerState {
+    /// First block not indexed yet
+    pub next_block: u64,
+    /// Hashes of the recently indexed blocks holding records, and of the last indexed block
+    pub recent_blocks: BTreeMap<u64, H256>,
+    /// Requests waiting for the keeper to process them
+    #[serde(default)]
+    pub pending: PendingRequests,
+}
+
+/// Follow the chain head, indexing user actions, staking records, veFyde locks and fees
+/// once they have `confirmations` blocks on top of them, and retracting them if a deeper
+/// reorg happens.
+pub struct Indexer<M: Middleware> {
+    client: Arc<M>,
+    history: ProtocolHistory<M>,
+    block_cache: BlockMetaCache<M>,
+    confirmations: u64,
+    reorg_window: u64,
+    state: IndexerState,
+}
+
+impl<M: Middleware> Indexer<M> {
+    pub fn new(client: Arc<M>, chain: Chain) -> Self {
+        let block_cache = BlockMetaCache::new(client.clone());
+        Self {
+            client: client.clone(),
+            history: ProtocolHistory::new(client, chain).with_block_cache(block_cache.clone()),
+            block_cache,
+            confirmations: DEFAULT_CONFIRMATIONS,
+            reorg_window: DEFAULT_REORG_WINDOW,
+            state: IndexerState::default(),
+        }
+    }
+
+    /// Use a custom block cache, shared with the history queries
+    pub fn with_block_cache(mut self, block_cache: BlockMetaCache<M>) -> Self {
+        self.history = self.history.with_block_cache(block_cache.clone());
+        self.block_cache = block_cache;
+        self
+    }
+
+    pub fn confirmations(mut self, confirmations: u64) -> Self {
+        self.confirmations = confirmations;
+        self
+    }
+
+    pub fn reorg_window(mut self, reorg_window: u64) -> Self {
+        self.reorg_window = reorg_window;
+        self
+    }
+
+    pub fn from_block(mut self, from_block: u64) -> Self {
+        self.state = IndexerState {
+            next_block: from_block,
+            ..Default::default()
+        };
+        self
+    }
+
+    /// Resume from a persisted state
+    pub fn with_state(mut self, state: IndexerState) -> Self {
+        self.state = state;
+        self
+    }
+
+    pub fn state(&self) -> &IndexerState {
+        &self.state
+    }
+
+    /// Check for reorgs then index the blocks confirmed since the last poll.
+    /// Retractions come first, in descending block order. The state is only updated once
+    /// every step succeeded, so a failed poll is retried whole by the next one.
+    pub async fn poll(&mut self) -> Result<Vec<IndexerEvent>, FydeError<M>> {
+        let mut state = self.state.clone();
+        let events = self.poll_state(&mut state).await?;
+        self.state = state;
+        Ok(events)
+    }
+
+    async fn poll_state(
+        &self,
+        state: &mut IndexerState,
+    ) -> Result<Vec<IndexerEvent>, FydeError<M>> {
+        let mut events = self.check_reorg(state).await?;
+
+        let head = self
+            .client
+            .get_block_number()
+            .await
+            .map_err(FydeError::MiddlewareError)?
+            .as_u64();
+        let to_block = match head.checked_sub(self.confirmations) {
+            Some(to_block) if to_block >= state.next_block => to_block,
+            _ => return Ok(events),
+        };
+        let from_block = state.next_block;
+
+        let (user_actions, _) = self
+            .history
+            .get_data_with_pending(&mut state.pending, from_block, Some(to_block))
+            .await?;
+        for (action, processed) in user_actions {
+            let block = state.block_ref(action.block_number(), action.block_hash());
+            let processed = state.block_ref(processed.block_number.as_u64(), processed.block_hash);
+            events.push(IndexerEvent::UserAction {
+                block,
+                processed,
+                action,
+            });
+        }
+
+        let (records, _) = self
+            .history
+            .get_staking_unstaking_history_with_cursor(from_block, Some(to_block))
+            .await?;
+        for record in records {
+            let block = state.block_ref(record.block_number(), record.block_hash());
+            events.push(IndexerEvent::StakingUnstaking { block, record });
+        }
+
+        let (locks, _) = self
+            .history
+            .get_ve_fyde_locks_with_cursor(from_block, Some(to_block))
+            .await?;
+        for lock in locks {
+            let block = state.block_ref(lock.block_number, lock.block_hash);
+            events.push(IndexerEvent::VeFydeLock { block, lock });
+        }
+
+        let (fees, _) = self
+            .history
+            .get_fee_events_with_cursor(from_block, Some(to_block))
+            .await?;
+        for fee in fees {
+            let block = state.block_ref(fee.block_number, fee.block_hash);
+            events.push(IndexerEvent::Fee { block, fee });
+        }
+
+        let tip_hash = self.canonical_hash(to_block).await?.ok_or_else(|| {
+            DataError::missing("block", EventContext::default().with_block(to_block))
+        })?;
+        state.recent_blocks.insert(to_block, tip_hash);
+        let oldest = to_block.saturating_sub(self.reorg_window);
+        state.recent_blocks = state.recent_blocks.split_off(&oldest);
+        state.pending.prune(oldest);
+        state.next_block = to_block + 1;
+
+        Ok(events)
+    }
+
+    async fn canonical_hash(&self, number: u64) -> Result<Option<H256>, FydeError<M>> {
+        Ok(self
+            .client
+            .get_block(number)
+            .await
+            .map_err(FydeError::MiddlewareError)?
+            .and_then(|block| block.hash))
+    }
+
+    /// Walk the recent blocks down from the last indexed one until a block is still
+    /// canonical, retracting the others and rewinding `state` after the fork point.
+    /// Requests whose processing block is retracted wait for their LiquidVault event again.
+    async fn check_reorg(
+        &self,
+        state: &mut IndexerState,
+    ) -> Result<Vec<IndexerEvent>, FydeError<M>> {
+        let mut retracted = vec![];
+        let mut fork_point = None;
+        for (number, hash) in state.recent_blocks.iter().rev() {
+            if self.canonical_hash(*number).await? == Some(*hash) {
+                fork_point = Some(*number);
+                break;
+            }
+            retracted.push(BlockRef {
+                number: *number,
+                hash: *hash,
+            });
+        }
+
+        let oldest_retracted = match retracted.last() {
+            Some(block) => block.number,
+            None => return Ok(vec![]),
+        };
+        for block in &retracted {
+            state.recent_blocks.remove(&block.number);
+            self.block_cache.invalidate(block.number).await?;
+        }
+        // Without a canonical block left the fork is below the window, rewind as far as known
+        state.next_block = fork_point.map_or(oldest_retracted, |number| number + 1);
+        state.pending.rewind(state.next_block);
+
+        Ok(retracted.into_iter().map(IndexerEvent::Retracted).collect())
+    }
+}
+
+impl IndexerState {
+    /// Block of a record, with the hash of the log it was decoded from
+    fn block_ref(&mut self, number: u64, hash: H256) -> BlockRef {
+        self.recent_blocks.insert(number, hash);
+        BlockRef { number, hash }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        providers::Provider,
+        types::{Block, U64},
+    };
+
+    fn block(number: u64, hash: H256) -> Block<H256> {
+        Block {
+            number: Some(U64::from(number)),
+            hash: Some(hash),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_reorg_rewinds_after_fork_point() {
+        let (provider, mock) = Provider::mocked();
+        // Responses are served last in, first out
+        mock.push(block(90, H256::from_low_u64_be(90))).unwrap();
+        mock.push(block(95, H256::from_low_u64_be(0xbad))).unwrap();
+        mock.push(block(100, H256::from_low_u64_be(0xbad))).unwrap();
+
+        let state = IndexerState {
+            next_block: 101,
+            recent_blocks: BTreeMap::from([
+                (90, H256::from_low_u64_be(90)),
+                (95, H256::from_low_u64_be(95)),
+                (100, H256::from_low_u64_be(100)),
+            ]),
+            ..Default::default()
+        };
+        let provider = Arc::new(provider);
+        let indexer = Indexer::new(provider.clone(), Chain::Mainnet)
+            .with_block_cache(BlockMetaCache::new(provider).chain_id(1))
+            .with_state(state);
+        let mut state = indexer.state().clone();
+        let events = indexer.check_reorg(&mut state).await.unwrap();
+
+        let retracted: Vec<u64> = events
+            .iter()
+            .filter_map(|event| match event {
+                IndexerEvent::Retracted(block) => Some(block.number),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(retracted, vec![100, 95]);
+        assert_eq!(state.next_block, 91);
+        assert_eq!(state.recent_blocks.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_check_reorg_without_reorg() {
+        let (provider, mock) = Provider::mocked();
+        mock.push(block(100, H256::from_low_u64_be(100))).unwrap();
+
+        let state = IndexerState {
+            next_block: 101,
+            recent_blocks: BTreeMap::from([
+                (90, H256::from_low_u64_be(90)),
+                (100, H256::from_low_u64_be(100)),
+            ]),
+            ..Default::default()
+        };
+        let provider = Arc::new(provider);
+        let indexer = Indexer::new(provider.clone(), Chain::Mainnet)
+            .with_block_cache(BlockMetaCache::new(provider).chain_id(1))
+            .with_state(state);
+
+        let mut state = indexer.state().clone();
+        assert!(indexer.check_reorg(&mut state).await.unwrap().is_empty());
+        assert_eq!(state.next_block, 101);
+    }
+
+    #[tokio::test]
+    async fn test_failed_poll_keeps_retractions() {
+        let (provider, mock) = Provider::mocked();
+        let state = IndexerState {
+            next_block: 101,
+            recent_blocks: BTreeMap::from([
+                (90, H256::from_low_u64_be(90)),
+                (100, H256::from_low_u64_be(100)),
+            ]),
+            ..Default::default()
+        };
+        let provider = Arc::new(provider);
+        let mut indexer = Indexer::new(provider.clone(), Chain::Mainnet)
+            .with_block_cache(BlockMetaCache::new(provider).chain_id(1))
+            .with_state(state.clone());
+
+        // The head cannot be read after the reorg check
+        mock.push(block(90, H256::from_low_u64_be(90))).unwrap();
+        mock.push(block(100, H256::from_low_u64_be(0xbad))).unwrap();
+        assert!(indexer.poll().await.is_err());
+        assert_eq!(indexer.state(), &state);
+
+        // Head below the confirmations, the retry only retracts
+        mock.push(U64::from(95)).unwrap();
+        mock.push(block(90, H256::from_low_u64_be(90))).unwrap();
+        mock.push(block(100, H256::from_low_u64_be(0xbad))).unwrap();
+        let events = indexer.poll().await.unwrap();
+        assert!(matches!(
+            events.as_slice(),
+            [IndexerEvent::Retracted(BlockRef { number: 100, .. })]
+        ));
+        assert_eq!(indexer.state().next_block, 91);
+    }
+}
@@ -1,12 +1,39 @@
 use ethers::prelude::abigen;
-use ethers::types::Address;
+use ethers::providers::Middleware;
+use ethers::types::{Address, Bytes, TransactionRequest};
+use serde::{Deserialize, Serialize};
+use std::{path::Path, sync::Arc};
+
+use crate::errors::FydeError;
 
 pub mod asset;
+pub mod block_cache;
+pub mod chainlink;
 pub mod errors;
 pub mod governance;
+pub mod indexer;
 pub mod liquid_vault;
+pub mod log_scanner;
+pub mod lrt_rewards;
+pub mod lrt_staking;
+pub mod merkle;
+pub mod oracle;
 pub mod protocol_history;
+pub mod quarantine;
+pub mod quoter;
+pub mod relayer;
+pub mod relayer_status;
+pub mod revenue_distributor;
+pub mod revenue_share;
+#[cfg(feature = "server")]
+pub mod server;
 pub mod snapshot;
+pub mod st_trsy;
+#[cfg(feature = "storage")]
+pub mod storage;
+pub mod tax_model;
+pub mod trsy_staking;
+pub mod uniswap;
 pub mod user;
 pub mod utils;
 pub mod ve_fyde;
@@ -17,6 +44,8 @@ abigen!(GovernanceModuleContract, "./src/abis/GovernanceModule.json");
 abigen!(OracleModuleContract, "./src/abis/OracleModule.json");
 abigen!(RelayerContract, "./src/abis/RelayerV2.json");
 abigen!(StakingTRSY, "./src/abis/StakingTRSY.json");
+abigen!(StakingLRT, "./src/abis/StakingLRT.json");
+abigen!(RewardLRT, "./src/abis/RewardLRT.json");
 abigen!(
     RevenueVeFydeDistributorContract,
     "./src/abis/RevenueVeFydeDistributor.json"
@@ -32,9 +61,26 @@ abigen!(
         function allowance(address,address) external view returns (uint256)
         ]"#,
 );
+abigen!(
+    UniswapV3Pool,
+    r#"[
+        function slot0() external view returns (uint160 sqrtPriceX96, int24 tick, uint16 observationIndex, uint16 observationCardinality, uint16 observationCardinalityNext, uint8 feeProtocol, bool unlocked)
+        function observe(uint32[] secondsAgos) external view returns (int56[] tickCumulatives, uint160[] secondsPerLiquidityCumulativeX128s)
+        function token0() external view returns (address)
+        function token1() external view returns (address)
+        ]"#,
+);
+abigen!(
+    ChainlinkFeedRegistry,
+    r#"[
+        function latestRoundData(address base, address quote) external view returns (uint80 roundId, int256 answer, uint256 startedAt, uint256 updatedAt, uint80 answeredInRound)
+        function decimals(address base, address quote) external view returns (uint8)
+        function getFeed(address base, address quote) external view returns (address)
+        ]"#,
+);
 abigen!(Strsy, "./src/abis/Strsy.json");
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 // List of useful address for the Fyde protocol
 pub struct AddressList {
     pub liquid_vault: Address,
@@ -56,6 +102,8 @@ pub struct AddressList {
 pub enum Chain {
     Mainnet,
     Sepolia,
+    /// Local fork or staging deployment
+    Custom(Box<AddressList>),
 }
 
 impl std::str::FromStr for Chain {
@@ -70,14 +118,121 @@ impl std::str::FromStr for Chain {
     }
 }
 
+fn is_toml(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "toml")
+}
+
 impl AddressList {
     pub fn new(chain: &Chain) -> Self {
         match chain {
             Chain::Mainnet => Self::mainnet(),
             Chain::Sepolia => Self::sepolia(),
+            Chain::Custom(address_list) => address_list.as_ref().clone(),
         }
     }
 
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn from_toml(toml: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(toml)
+    }
+
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        toml::to_string(self)
+    }
+
+    /// Load an address list from a `.toml` file, or a JSON file for any other extension.
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+        let invalid_data = |e: String| std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+        match is_toml(path) {
+            true => Self::from_toml(&content).map_err(|e| invalid_data(e.to_string())),
+            false => Self::from_json(&content).map_err(|e| invalid_data(e.to_string())),
+        }
+    }
+
+    /// Save the address list to a `.toml` file, or a JSON file for any other extension.
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let path = path.as_ref();
+        let invalid_data = |e: String| std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+        let content = match is_toml(path) {
+            true => self.to_toml().map_err(|e| invalid_data(e.to_string()))?,
+            false => self.to_json().map_err(|e| invalid_data(e.to_string()))?,
+        };
+        std::fs::write(path, content)
+    }
+
+    /// Address of each contract along with a view function it is expected to answer
+    fn expected_selectors(&self) -> Vec<(&'static str, Address, &'static str)> {
+        vec![
+            ("liquid_vault", self.liquid_vault, "computeProtocolAUM()"),
+            ("relayer", self.relayer, "nonce()"),
+            ("tax_module", self.tax_module, "taxFactorDeposit()"),
+            (
+                "governance_module",
+                self.governance_module,
+                "getAllGovUsers()",
+            ),
+            ("oracle_module", self.oracle_module, "stalePeriod()"),
+            ("staking_trsy", self.staking_trsy, "REWARD_RATE()"),
+            ("staking_lrt", self.staking_lrt, "FEE_RATE()"),
+            (
+                "lrt_reward_distribution",
+                self.lrt_reward_distribution,
+                "owner()",
+            ),
+            ("weth", self.weth, "decimals()"),
+            ("fyde_token", self.fyde_token, "decimals()"),
+            ("vote_escrow", self.vote_escrow, "MAX_LOCK_TIME()"),
+            (
+                "vefyde_fee_distributor",
+                self.vefyde_fee_distributor,
+                "cumulativeFees()",
+            ),
+            ("strsy", self.strsy, "totalAssets()"),
+        ]
+    }
+
+    /// Check that each configured address has code and answers its expected selector.
+    pub async fn validate<M: Middleware>(&self, client: Arc<M>) -> Result<(), FydeError<M>> {
+        for (name, address, signature) in self.expected_selectors() {
+            let invalid_address = |reason: String| FydeError::InvalidAddress {
+                name: name.to_string(),
+                address,
+                reason,
+            };
+
+            let code = client
+                .get_code(address, None)
+                .await
+                .map_err(FydeError::MiddlewareError)?;
+            if code.is_empty() {
+                return Err(invalid_address(String::from("No code at address")));
+            }
+
+            let selector = ethers::utils::id(signature);
+            let tx = TransactionRequest::new()
+                .to(address)
+                .data(Bytes::from(selector.to_vec()));
+            let output = client
+                .call(&tx.into(), None)
+                .await
+                .map_err(|e| invalid_address(format!("{} reverted: {}", signature, e)))?;
+            if output.is_empty() {
+                return Err(invalid_address(format!("{} returned no data", signature)));
+            }
+        }
+        Ok(())
+    }
+
     pub fn mainnet() -> Self {
         Self {
             liquid_vault: String::from("0x87Cc45fFF5c0933bb6aF6bAe7Fc013b7eC7df2Ee")
@@ -166,3 +321,27 @@ impl AddressList {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_address_list_roundtrip() {
+        let address_list = AddressList::mainnet();
+
+        let json = address_list.to_json().expect("Failed to serialize to JSON");
+        assert_eq!(AddressList::from_json(&json).unwrap(), address_list);
+
+        let toml = address_list.to_toml().expect("Failed to serialize to TOML");
+        assert_eq!(AddressList::from_toml(&toml).unwrap(), address_list);
+
+        let path = std::env::temp_dir().join("fyde_address_list_roundtrip.toml");
+        address_list
+            .save(&path)
+            .expect("Failed to save address list");
+        let chain = Chain::Custom(Box::new(AddressList::load(&path).unwrap()));
+        std::fs::remove_file(&path).ok();
+        assert_eq!(AddressList::new(&chain), address_list);
+    }
+}
@@ -1,123 +1,116 @@
 use crate::{
-    errors::FydeError, AddressList, Chain, LiquidVaultContract, LiquidVaultContractEvents,
-    StakingTRSY,
+    errors::{DataError, EventContext, FydeError},
+    log_scanner::{LogScanner, WithScanner},
+    AddressList, Chain, LiquidVaultContract, LiquidVaultContractEvents, StakingTRSY,
 };
 use ethers::{
+    contract::LogMeta,
     contract::Multicall,
-    providers::{Http, Provider},
-    types::{Address, U256},
+    providers::Middleware,
+    types::{Address, Filter, U256},
 };
 use std::sync::Arc;
 
-pub struct LiquidVault {
-    contract: LiquidVaultContract<Provider<Http>>,
-    staking_trsy: StakingTRSY<Provider<Http>>,
-    multicall: Multicall<Provider<Http>>,
+pub struct LiquidVault<M: Middleware> {
+    contract: LiquidVaultContract<M>,
+    staking_trsy: StakingTRSY<M>,
+    multicall: Multicall<M>,
     address: Address,
+    scanner: LogScanner<M>,
 }
 
-impl LiquidVault {
-    pub async fn new(provider: Arc<Provider<Http>>, chain: Chain) -> Self {
+impl<M: Middleware> WithScanner<M> for LiquidVault<M> {
+    fn scanner_mut(&mut self) -> &mut LogScanner<M> {
+        &mut self.scanner
+    }
+}
+
+impl<M: Middleware> LiquidVault<M> {
+    pub async fn new(provider: Arc<M>, chain: Chain) -> Result<Self, FydeError<M>> {
         let address_list: AddressList = AddressList::new(&chain);
         let contract = LiquidVaultContract::new(address_list.liquid_vault, provider.clone());
         let staking_trsy = StakingTRSY::new(address_list.staking_trsy, provider.clone());
-        let multicall = Multicall::new(provider, None)
-            .await
-            .expect("Failed to create Multicall");
+        let multicall = Multicall::new(provider.clone(), None).await?;
 
-        Self {
+        Ok(Self {
             contract,
             staking_trsy,
             multicall,
             address: address_list.liquid_vault,
-        }
+            scanner: LogScanner::new(provider),
+        })
     }
 
-    pub async fn get_tvl(&self) -> Result<U256, FydeError> {
+    async fn get_events(&self) -> Result<Vec<LiquidVaultContractEvents>, FydeError<M>> {
+        let filter = Filter::new().address(self.address);
+        let scan = self.scanner.scan(&filter, 0, None).await?;
+        let events: Vec<(LiquidVaultContractEvents, LogMeta)> = scan.decode();
+        Ok(events.into_iter().map(|(event, _)| event).collect())
+    }
+
+    pub async fn get_tvl(&self) -> Result<U256, FydeError<M>> {
         Ok(self.contract.compute_protocol_aum().call().await?)
     }
 
-    pub async fn get_trsy_supply(&self) -> Result<U256, FydeError> {
+    pub async fn get_trsy_supply(&self) -> Result<U256, FydeError<M>> {
         Ok(self.contract.total_supply().call().await?)
     }
 
-    pub async fn get_trsy_staked(&self) -> Result<U256, FydeError> {
+    pub async fn get_trsy_staked(&self) -> Result<U256, FydeError<M>> {
         Ok(self.staking_trsy.total_supply().call().await?)
     }
 
-    pub async fn get_trsy_value(&self) -> Result<U256, FydeError> {
+    pub async fn get_trsy_value(&self) -> Result<U256, FydeError<M>> {
         let tvl = self.contract.compute_protocol_aum().call().await?;
         let trsy_supply = self.contract.total_supply().call().await?;
-        Ok(tvl / trsy_supply)
+        Ok(tvl.checked_div(trsy_supply).ok_or_else(|| {
+            DataError::inconsistent("TRSY supply is zero", EventContext::default())
+        })?)
+    }
+
+    pub async fn get_total_fees(&self) -> Result<U256, FydeError<M>> {
+        Ok(self.total_fees(&self.get_events().await?))
     }
 
-    pub async fn get_total_fees(&self) -> Result<U256, FydeError> {
-        let events = self
-            .contract
-            .events()
-            .from_block(0)
-            .query()
-            .await
-            .expect("Failed to get events");
+    pub async fn get_management_fees(&self) -> Result<U256, FydeError<M>> {
+        Ok(management_fees(&self.get_events().await?))
+    }
+
+    pub async fn get_tax_fees(&self) -> Result<U256, FydeError<M>> {
+        Ok(self.tax_fees(&self.get_events().await?)?)
+    }
 
+    /// TRSY minted to the vault, management fees included
+    fn total_fees(&self, events: &[LiquidVaultContractEvents]) -> U256 {
         let mut tax = U256::from(0);
         for event in events.iter() {
             if let LiquidVaultContractEvents::TransferFilter(ev) = event {
-                if ev.from
-                    == "0x0000000000000000000000000000000000000000"
-                        .parse()
-                        .expect("Failed to parse zero address")
-                    && ev.to == self.address
-                {
+                if ev.from == Address::zero() && ev.to == self.address {
                     tax += ev.amount;
                 }
             }
         }
-        Ok(tax)
-    }
-
-    pub async fn get_management_fees(&self) -> Result<U256, FydeError> {
-        let events = self
-            .contract
-            .events()
-            .from_block(0)
-            .query()
-            .await
-            .expect("Failed to get events");
-
-        let mut fees = U256::from(0);
-
-        for event in events.iter() {
-            if let LiquidVaultContractEvents::ManagementFeeCollectedFilter(ev) = event {
-                fees += ev.fee_to_mint;
-            }
-        }
-
-        Ok(fees)
+        tax
     }
 
-    pub async fn get_tax_fees(&self) -> Result<U256, FydeError> {
-        let tax_fees = self.get_total_fees().await? - self.get_management_fees().await?;
-        Ok(tax_fees)
+    /// TRSY minted to the vault as taxes, management fees excluded
+    fn tax_fees(&self, events: &[LiquidVaultContractEvents]) -> Result<U256, DataError> {
+        self.total_fees(events)
+            .checked_sub(management_fees(events))
+            .ok_or_else(|| {
+                DataError::inconsistent(
+                    "Management fees exceed the TRSY minted to the vault",
+                    EventContext::default(),
+                )
+            })
     }
 
-    pub async fn get_burned_trsy_by_swap(&self) -> Result<U256, FydeError> {
-        let events = self
-            .contract
-            .events()
-            .from_block(0)
-            .query()
-            .await
-            .expect("Failed to get events");
+    pub async fn get_burned_trsy_by_swap(&self) -> Result<U256, FydeError<M>> {
+        let events = self.get_events().await?;
         let mut burned = U256::from(0);
         for event in events.iter() {
             if let LiquidVaultContractEvents::TransferFilter(ev) = event {
-                if ev.to
-                    == "0x0000000000000000000000000000000000000000"
-                        .parse()
-                        .expect("Failed to parse zero address")
-                    && ev.from == self.address
-                {
+                if ev.to == Address::zero() && ev.from == self.address {
                     burned += ev.amount;
                 }
             }
@@ -125,21 +118,101 @@ impl LiquidVault {
         Ok(burned)
     }
 
-    pub async fn get_assets_list(&mut self) -> Result<Vec<Address>, FydeError> {
-        let n_assets = self
-            .contract
-            .get_assets_list_length()
-            .call()
-            .await?
-            .as_u128() as usize;
+    pub async fn get_assets_list(&self) -> Result<Vec<Address>, FydeError<M>> {
+        let n_assets = usize::try_from(self.contract.get_assets_list_length().call().await?)
+            .map_err(|_| DataError::out_of_range("assets list length", EventContext::default()))?;
 
-        self.multicall.clear_calls();
+        let mut multicall = self.multicall.clone();
+        multicall.clear_calls();
         for n in 0..n_assets {
-            self.multicall
-                .add_call(self.contract.assets_list(n.into()), false);
+            multicall.add_call(self.contract.assets_list(n.into()), false);
         }
-        let assets_list: Vec<Address> = self.multicall.call_array().await?;
+        let assets_list: Vec<Address> = multicall.call_array().await?;
 
         Ok(assets_list)
     }
 }
+
+fn management_fees(events: &[LiquidVaultContractEvents]) -> U256 {
+    let mut fees = U256::from(0);
+    for event in events.iter() {
+        if let LiquidVaultContractEvents::ManagementFeeCollectedFilter(ev) = event {
+            fees += ev.fee_to_mint;
+        }
+    }
+    fees
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::liquid_vault_contract::{ManagementFeeCollectedFilter, TransferFilter};
+    use ethers::{
+        abi::Token,
+        providers::{MockProvider, Provider},
+        types::Bytes,
+    };
+
+    async fn mocked_vault() -> (LiquidVault<Provider<MockProvider>>, MockProvider) {
+        let (provider, mock) = Provider::mocked();
+        let client = Arc::new(provider);
+        let address = Address::random();
+        let vault = LiquidVault {
+            contract: LiquidVaultContract::new(address, client.clone()),
+            staking_trsy: StakingTRSY::new(Address::random(), client.clone()),
+            multicall: Multicall::new(client.clone(), Some(Address::random()))
+                .await
+                .unwrap(),
+            address,
+            scanner: LogScanner::new(client),
+        };
+        (vault, mock)
+    }
+
+    fn push_uint(mock: &MockProvider, value: u64) {
+        let encoded = ethers::abi::encode(&[Token::Uint(value.into())]);
+        mock.push::<Bytes, _>(Bytes::from(encoded)).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_trsy_value_with_zero_supply() {
+        let (vault, mock) = mocked_vault().await;
+        // Responses are served last in, first out: TVL, then TRSY supply
+        push_uint(&mock, 0);
+        push_uint(&mock, 1_000);
+        assert!(matches!(
+            vault.get_trsy_value().await,
+            Err(FydeError::DataError(DataError::InconsistentEvent { .. }))
+        ));
+
+        push_uint(&mock, 4);
+        push_uint(&mock, 1_000);
+        assert_eq!(vault.get_trsy_value().await.unwrap(), U256::from(250));
+    }
+
+    #[tokio::test]
+    async fn test_fees() {
+        let (vault, _) = mocked_vault().await;
+        let mint = |amount: u64| {
+            LiquidVaultContractEvents::TransferFilter(TransferFilter {
+                from: Address::zero(),
+                to: vault.address,
+                amount: amount.into(),
+            })
+        };
+        let management = |fee: u64| {
+            LiquidVaultContractEvents::ManagementFeeCollectedFilter(ManagementFeeCollectedFilter {
+                fee_to_mint: fee.into(),
+            })
+        };
+        let events = [mint(30), management(10), mint(10)];
+        assert_eq!(vault.total_fees(&events), U256::from(40));
+        assert_eq!(management_fees(&events), U256::from(10));
+        assert_eq!(vault.tax_fees(&events).unwrap(), U256::from(30));
+
+        assert!(matches!(
+            vault.tax_fees(&[mint(30), management(50)]),
+            Err(DataError::InconsistentEvent { .. })
+        ));
+    }
+}
@@ -0,0 +1,305 @@
+use ethers::{
+    contract::{parse_log, EthLogDecode, LogMeta},
+    providers::Middleware,
+    types::{Address, Filter, Log},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::errors::FydeError;
+
+pub const DEFAULT_WINDOW: u64 = 10_000;
+pub const DEFAULT_MIN_WINDOW: u64 = 10;
+pub const DEFAULT_MAX_WINDOW: u64 = 1_000_000;
+
+/// Errors returned by providers when a `eth_getLogs` range is too large
+const TOO_MANY_RESULTS_ERRORS: [&str; 8] = [
+    "more than",
+    "too many",
+    "limit exceeded",
+    "response size",
+    "range is too",
+    "range too",
+    "block range",
+    "query timeout",
+];
+
+/// Position of a scan, persist it to resume the scan later
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanCursor {
+    /// First block not scanned yet
+    pub next_block: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ScanProgress {
+    pub from_block: u64,
+    pub to_block: u64,
+    /// Last block scanned
+    pub scanned_to: u64,
+    pub logs_found: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanResult {
+    pub logs: Vec<Log>,
+    pub cursor: ScanCursor,
+}
+
+impl ScanResult {
+    /// Decode the logs as `D`, skipping the ones belonging to other events
+    pub fn decode<D: EthLogDecode>(&self) -> Vec<(D, LogMeta)> {
+        self.logs
+            .iter()
+            .filter_map(|log| {
+                parse_log::<D>(log.clone())
+                    .ok()
+                    .map(|event| (event, LogMeta::from(log)))
+            })
+            .collect()
+    }
+
+    /// Decode the logs emitted by `address` as `D`, skipping the ones belonging to other events
+    pub fn decode_from<D: EthLogDecode>(&self, address: Address) -> Vec<(D, LogMeta)> {
+        self.logs
+            .iter()
+            .filter(|log| log.address == address)
+            .filter_map(|log| {
+                parse_log::<D>(log.clone())
+                    .ok()
+                    .map(|event| (event, LogMeta::from(log)))
+            })
+            .collect()
+    }
+}
+
+type ProgressCallback = Box<dyn Fn(&ScanProgress) + Send + Sync>;
+
+/// Fetch logs over a block range in windows, shrinking the window when the
+/// provider rejects a query for returning too many results and growing it
+/// back while queries succeed.
+pub struct LogScanner<M: Middleware> {
+    client: Arc<M>,
+    window: u64,
+    min_window: u64,
+    max_window: u64,
+    on_progress: Option<ProgressCallback>,
+}
+
+impl<M: Middleware> LogScanner<M> {
+    pub fn new(client: Arc<M>) -> Self {
+        Self {
+            client,
+            window: DEFAULT_WINDOW,
+            min_window: DEFAULT_MIN_WINDOW,
+            max_window: DEFAULT_MAX_WINDOW,
+            on_progress: None,
+        }
+    }
+
+    /// Initial number of blocks queried at once
+    pub fn window(mut self, window: u64) -> Self {
+        self.window = window.max(1);
+        self
+    }
+
+    pub fn min_window(mut self, min_window: u64) -> Self {
+        self.min_window = min_window.max(1);
+        self
+    }
+
+    pub fn max_window(mut self, max_window: u64) -> Self {
+        self.max_window = max_window.max(1);
+        self
+    }
+
+    /// Called after each window is scanned
+    pub fn on_progress(mut self, callback: impl Fn(&ScanProgress) + Send + Sync + 'static) -> Self {
+        self.on_progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Scan `filter` from `from_block` to `to_block` (the latest block by default).
+    /// A failed query returns [`FydeError::ScanInterrupted`] with the logs found so far
+    /// and the cursor to resume from.
+    pub async fn scan(
+        &self,
+        filter: &Filter,
+        from_block: u64,
+        to_block: Option<u64>,
+    ) -> Result<ScanResult, FydeError<M>> {
+        let to_block = match to_block {
+            Some(to_block) => to_block,
+            None => self
+                .client
+                .get_block_number()
+                .await
+                .map_err(FydeError::MiddlewareError)?
+                .as_u64(),
+        };
+
+        let mut logs = vec![];
+        let mut window = self.window.clamp(self.min_window, self.max_window);
+        let mut next_block = from_block;
+        while next_block <= to_block {
+            let end_block = next_block.saturating_add(window - 1).min(to_block);
+            let chunk_filter = filter.clone().from_block(next_block).to_block(end_block);
+            match self.client.get_logs(&chunk_filter).await {
+                Ok(chunk) => {
+                    logs.extend(chunk);
+                    next_block = end_block.saturating_add(1);
+                    window = window.saturating_mul(2).min(self.max_window);
+                    if let Some(on_progress) = &self.on_progress {
+                        on_progress(&ScanProgress {
+                            from_block,
+                            to_block,
+                            scanned_to: end_block,
+                            logs_found: logs.len(),
+                        });
+                    }
+                    // `next_block` saturates when `to_block` is the last representable block
+                    if end_block == to_block {
+                        break;
+                    }
+                }
+                Err(e) if window > self.min_window && is_too_many_results(&e.to_string()) => {
+                    window = (window / 2).max(self.min_window);
+                }
+                Err(error) => {
+                    return Err(FydeError::ScanInterrupted {
+                        partial: Box::new(ScanResult {
+                            logs,
+                            cursor: ScanCursor { next_block },
+                        }),
+                        error,
+                    })
+                }
+            }
+        }
+
+        Ok(ScanResult {
+            logs,
+            cursor: ScanCursor { next_block },
+        })
+    }
+
+    /// Continue a scan from a persisted cursor.
+    pub async fn resume(
+        &self,
+        filter: &Filter,
+        cursor: ScanCursor,
+        to_block: Option<u64>,
+    ) -> Result<ScanResult, FydeError<M>> {
+        self.scan(filter, cursor.next_block, to_block).await
+    }
+}
+
+/// Clients reading event history through a [`LogScanner`]
+pub trait WithScanner<M: Middleware>: Sized {
+    fn scanner_mut(&mut self) -> &mut LogScanner<M>;
+
+    /// Use a custom log scanner (window sizes, progress reporting)
+    fn with_scanner(mut self, scanner: LogScanner<M>) -> Self {
+        *self.scanner_mut() = scanner;
+        self
+    }
+}
+
+fn is_too_many_results(error: &str) -> bool {
+    let error = error.to_lowercase();
+    TOO_MANY_RESULTS_ERRORS
+        .iter()
+        .any(|pattern| error.contains(pattern))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::providers::{JsonRpcError, MockResponse, Provider};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_is_too_many_results() {
+        assert!(is_too_many_results(
+            "(code: -32005, message: query returned more than 10000 results"
+        ));
+        assert!(is_too_many_results("Log response size exceeded."));
+        assert!(is_too_many_results("block range is too wide"));
+        assert!(!is_too_many_results("execution reverted"));
+    }
+
+    #[tokio::test]
+    async fn test_scan_shrinks_window() {
+        let (provider, mock) = Provider::mocked();
+        // Responses are served last in, first out
+        mock.push::<Vec<Log>, _>(vec![]).unwrap();
+        mock.push::<Vec<Log>, _>(vec![]).unwrap();
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: -32005,
+            message: String::from("query returned more than 10000 results"),
+            data: None,
+        }));
+
+        let windows_scanned = Arc::new(AtomicUsize::new(0));
+        let counter = windows_scanned.clone();
+        let scanner = LogScanner::new(Arc::new(provider))
+            .window(100)
+            .min_window(10)
+            .on_progress(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            });
+        let result = scanner.scan(&Filter::new(), 0, Some(99)).await.unwrap();
+
+        assert_eq!(result.cursor, ScanCursor { next_block: 100 });
+        assert_eq!(windows_scanned.load(Ordering::SeqCst), 2);
+    }
+
+    fn log(block: u64) -> Log {
+        Log {
+            block_number: Some(block.into()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scan_error_keeps_partial_logs() {
+        let (provider, mock) = Provider::mocked();
+        // Responses are served last in, first out
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: -32000,
+            message: String::from("header not found"),
+            data: None,
+        }));
+        mock.push::<Vec<Log>, _>(vec![log(5)]).unwrap();
+
+        let scanner = LogScanner::new(Arc::new(provider))
+            .window(10)
+            .min_window(10);
+        match scanner.scan(&Filter::new(), 0, Some(99)).await {
+            Err(FydeError::ScanInterrupted { partial, .. }) => {
+                assert_eq!(partial.logs, vec![log(5)]);
+                assert_eq!(partial.cursor, ScanCursor { next_block: 10 });
+            }
+            other => panic!("expected an interrupted scan, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scan_up_to_the_last_block() {
+        let (provider, mock) = Provider::mocked();
+        mock.push::<Vec<Log>, _>(vec![log(u64::MAX)]).unwrap();
+
+        let scanner = LogScanner::new(Arc::new(provider));
+        let result = scanner
+            .scan(&Filter::new(), u64::MAX - 5, Some(u64::MAX))
+            .await
+            .unwrap();
+        assert_eq!(result.logs, vec![log(u64::MAX)]);
+        assert_eq!(
+            result.cursor,
+            ScanCursor {
+                next_block: u64::MAX
+            }
+        );
+    }
+}
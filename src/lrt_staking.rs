@@ -0,0 +1,301 @@
+use ethers::{
+    contract::{LogMeta, Multicall},
+    providers::Middleware,
+    types::{Address, Filter, H256, U256},
+};
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::{
+    block_cache::{BlockMetaCache, WithBlockCache},
+    errors::FydeError,
+    log_scanner::{LogScanner, ScanCursor, WithScanner},
+    staking_lrt::StakedFilter,
+    utils::FydeAmount,
+    AddressList, Chain, StakingLRT,
+};
+
+pub struct LrtStaking<M: Middleware> {
+    contract: StakingLRT<M>,
+    multicall: Multicall<M>,
+    scanner: LogScanner<M>,
+    block_cache: BlockMetaCache<M>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct LrtStakingUser {
+    pub balance: FydeAmount,
+    /// ETH rewards accrued at the last user checkpoint
+    pub rewards: FydeAmount,
+    /// ETH rewards claimable now
+    pub earned: FydeAmount,
+    /// FYDE rewards accrued at the last user checkpoint
+    pub rewards_fyde: FydeAmount,
+    /// FYDE rewards claimable now
+    pub earned_fyde: FydeAmount,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct LrtStakingInfo {
+    /// Fee rate as stored in the contract
+    pub fee_rate: U256,
+    /// ETH rewards distributed per second
+    pub reward_rate: FydeAmount,
+    /// FYDE rewards distributed per second
+    pub reward_rate_fyde: FydeAmount,
+    pub start_date: u64,
+    pub period_finish: u64,
+    pub total_staked: FydeAmount,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct BoostPeriod {
+    /// Start of the period
+    pub timestamp: u64,
+    /// Multiplier as stored in the contract
+    pub multiplier: u16,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct LrtStakedEvent {
+    pub user: Address,
+    pub amount: U256,
+    pub tx_hash: H256,
+    pub block_number: u64,
+    pub log_index: u64,
+    pub timestamp: u64,
+}
+
+impl<M: Middleware> WithScanner<M> for LrtStaking<M> {
+    fn scanner_mut(&mut self) -> &mut LogScanner<M> {
+        &mut self.scanner
+    }
+}
+
+impl<M: Middleware> WithBlockCache<M> for LrtStaking<M> {
+    fn block_cache_mut(&mut self) -> &mut BlockMetaCache<M> {
+        &mut self.block_cache
+    }
+}
+
+impl<M: Middleware> LrtStaking<M> {
+    pub async fn new(client: Arc<M>, chain: Chain) -> Result<Self, FydeError<M>> {
+        let address_list: AddressList = AddressList::new(&chain);
+        Ok(Self {
+            contract: StakingLRT::new(address_list.staking_lrt, client.clone()),
+            multicall: Multicall::new(client.clone(), None).await?,
+            scanner: LogScanner::new(client.clone()),
+            block_cache: BlockMetaCache::new(client),
+        })
+    }
+
+    pub async fn get_user(&self, user: Address) -> Result<LrtStakingUser, FydeError<M>> {
+        let mut multicall = self.multicall.clone();
+        multicall.clear_calls();
+        multicall.add_call(self.contract.balance_of(user), false);
+        multicall.add_call(self.contract.rewards(user), false);
+        multicall.add_call(self.contract.rewards_fyde(user), false);
+        multicall.add_call(self.contract.reward_per_token(), false);
+        multicall.add_call(self.contract.reward_per_token_fyde(), false);
+        let (balance, rewards, rewards_fyde, reward_per_token, reward_per_token_fyde): (
+            U256,
+            U256,
+            U256,
+            U256,
+            U256,
+        ) = multicall.call().await?;
+
+        multicall.clear_calls();
+        multicall.add_call(
+            self.contract
+                .earned(user, balance, reward_per_token, rewards),
+            false,
+        );
+        multicall.add_call(
+            self.contract
+                .earned_fyde(user, balance, reward_per_token_fyde, rewards_fyde),
+            false,
+        );
+        let (earned, earned_fyde): (U256, U256) = multicall.call().await?;
+
+        Ok(LrtStakingUser {
+            balance: FydeAmount::new(balance, 18),
+            rewards: FydeAmount::new(rewards, 18),
+            earned: FydeAmount::new(earned, 18),
+            rewards_fyde: FydeAmount::new(rewards_fyde, 18),
+            earned_fyde: FydeAmount::new(earned_fyde, 18),
+        })
+    }
+
+    pub async fn get_staking_info(&self) -> Result<LrtStakingInfo, FydeError<M>> {
+        let mut multicall = self.multicall.clone();
+        multicall.clear_calls();
+        multicall.add_call(self.contract.fee_rate(), false);
+        multicall.add_call(self.contract.reward_rate(), false);
+        multicall.add_call(self.contract.reward_rate_fyde(), false);
+        multicall.add_call(self.contract.start_date(), false);
+        multicall.add_call(self.contract.period_finish(), false);
+        multicall.add_call(self.contract.total_supply(), false);
+        let (fee_rate, reward_rate, reward_rate_fyde, start_date, period_finish, total_staked): (
+            U256,
+            U256,
+            U256,
+            u64,
+            u64,
+            U256,
+        ) = multicall.call().await?;
+
+        Ok(LrtStakingInfo {
+            fee_rate,
+            reward_rate: FydeAmount::new(reward_rate, 18),
+            reward_rate_fyde: FydeAmount::new(reward_rate_fyde, 18),
+            start_date,
+            period_finish,
+            total_staked: FydeAmount::new(total_staked, 18),
+        })
+    }
+
+    /// All boost periods. The contract has no length getter, the array is read until the
+    /// out of bounds revert.
+    pub async fn get_boost_periods(&self) -> Result<Vec<BoostPeriod>, FydeError<M>> {
+        let mut periods = vec![];
+        loop {
+            match self
+                .contract
+                .boost_periods(U256::from(periods.len()))
+                .call()
+                .await
+            {
+                Ok((timestamp, multiplier)) => periods.push(BoostPeriod {
+                    timestamp: timestamp as u64,
+                    multiplier,
+                }),
+                Err(err) if err.is_revert() => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(periods)
+    }
+
+    /// Staked events between two blocks, with the cursor to resume the scan from.
+    pub async fn get_staked_history(
+        &self,
+        from_block: u64,
+        to_block: Option<u64>,
+    ) -> Result<(Vec<LrtStakedEvent>, ScanCursor), FydeError<M>> {
+        let filter = Filter::new()
+            .address(self.contract.address())
+            .event("Staked(address,uint256)");
+        let scan = self.scanner.scan(&filter, from_block, to_block).await?;
+        let events: Vec<(StakedFilter, LogMeta)> = scan.decode();
+        self.block_cache
+            .prefetch(events.iter().map(|(_, meta)| meta.block_number.as_u64()))
+            .await?;
+
+        let mut history = vec![];
+        for (event, meta) in events {
+            history.push(LrtStakedEvent {
+                user: event.user,
+                amount: event.amount,
+                tx_hash: meta.transaction_hash,
+                block_number: meta.block_number.as_u64(),
+                log_index: meta.log_index.as_u64(),
+                timestamp: self.block_cache.get_for_log(&meta).await?.timestamp,
+            });
+        }
+
+        Ok((history, scan.cursor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        abi::Token,
+        contract::EthEvent,
+        providers::{JsonRpcError, MockProvider, MockResponse, Provider},
+        types::{Block, Bytes, Log, Transaction, U64},
+    };
+
+    async fn mocked_staking() -> (LrtStaking<Provider<MockProvider>>, MockProvider) {
+        let (provider, mock) = Provider::mocked();
+        let client = Arc::new(provider);
+        let staking = LrtStaking {
+            contract: StakingLRT::new(Address::random(), client.clone()),
+            multicall: Multicall::new(client.clone(), Some(Address::random()))
+                .await
+                .unwrap(),
+            scanner: LogScanner::new(client.clone()),
+            block_cache: BlockMetaCache::new(client).chain_id(1),
+        };
+        (staking, mock)
+    }
+
+    #[tokio::test]
+    async fn test_get_boost_periods_stops_at_revert() {
+        let (staking, mock) = mocked_staking().await;
+
+        // Responses are served last in, first out
+        mock.push_response(MockResponse::Error(JsonRpcError {
+            code: 3,
+            message: String::from("execution reverted"),
+            data: Some(serde_json::json!("0x")),
+        }));
+        for (timestamp, multiplier) in [(2_000u32, 150u16), (1_000, 200)] {
+            let encoded = ethers::abi::encode(&[
+                Token::Uint(timestamp.into()),
+                Token::Uint(multiplier.into()),
+            ]);
+            mock.push::<Bytes, _>(Bytes::from(encoded)).unwrap();
+        }
+
+        let periods = staking.get_boost_periods().await.unwrap();
+        assert_eq!(
+            periods,
+            vec![
+                BoostPeriod {
+                    timestamp: 1_000,
+                    multiplier: 200
+                },
+                BoostPeriod {
+                    timestamp: 2_000,
+                    multiplier: 150
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_staked_events_keep_log_indexes() {
+        let (staking, mock) = mocked_staking().await;
+        let user = Address::from_low_u64_be(7);
+        let staked = |amount: u64, log_index: u64| Log {
+            topics: vec![StakedFilter::signature(), H256::from(user)],
+            data: ethers::abi::encode(&[Token::Uint(amount.into())]).into(),
+            block_hash: Some(H256::from_low_u64_be(101)),
+            block_number: Some(U64::from(101)),
+            transaction_hash: Some(H256::from_low_u64_be(1_000 + log_index)),
+            transaction_index: Some(U64::zero()),
+            log_index: Some(U256::from(log_index)),
+            ..Default::default()
+        };
+        // Responses are served last in, first out: logs, then their block
+        mock.push::<Block<Transaction>, _>(Block {
+            number: Some(U64::from(101)),
+            hash: Some(H256::from_low_u64_be(101)),
+            timestamp: U256::from(1_700_000_101),
+            ..Default::default()
+        })
+        .unwrap();
+        mock.push::<Vec<Log>, _>(vec![staked(10, 1), staked(20, 6)])
+            .unwrap();
+
+        let (history, _) = staking.get_staked_history(100, Some(110)).await.unwrap();
+        let log_indexes: Vec<(u64, U256)> = history
+            .iter()
+            .map(|event| (event.log_index, event.amount))
+            .collect();
+        assert_eq!(log_indexes, vec![(1, U256::from(10)), (6, U256::from(20))]);
+    }
+}
@@ -0,0 +1,140 @@
+use ethers::{
+    types::{Address, H256},
+    utils::keccak256,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::path::Path;
+
+use crate::errors::DataError;
+
+/// Keccak merkle tree with sorted pair hashing, as verified by OpenZeppelin's `MerkleProof`.
+/// A node without sibling is carried up to the next layer.
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    layers: Vec<Vec<H256>>,
+}
+
+impl MerkleTree {
+    pub fn new(leaves: Vec<H256>) -> Self {
+        let mut layers = vec![leaves];
+        while layers.last().is_some_and(|layer| layer.len() > 1) {
+            let next = layers
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => hash_pair(left, right),
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
+            layers.push(next);
+        }
+        Self { layers }
+    }
+
+    /// Root of the tree, zero when empty
+    pub fn root(&self) -> H256 {
+        self.layers
+            .last()
+            .and_then(|layer| layer.first())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn leaves(&self) -> &[H256] {
+        &self.layers[0]
+    }
+
+    /// Proof of the leaf at `index`, none when out of bounds
+    pub fn proof(&self, mut index: usize) -> Option<Vec<H256>> {
+        if index >= self.leaves().len() {
+            return None;
+        }
+        let mut proof = vec![];
+        for layer in &self.layers[..self.layers.len() - 1] {
+            if let Some(sibling) = layer.get(index ^ 1) {
+                proof.push(*sibling);
+            }
+            index /= 2;
+        }
+        Some(proof)
+    }
+}
+
+pub fn hash_pair(a: &H256, b: &H256) -> H256 {
+    let (first, second) = if a <= b { (a, b) } else { (b, a) };
+    H256(keccak256([first.as_bytes(), second.as_bytes()].concat()))
+}
+
+pub fn verify_proof(leaf: H256, proof: &[H256], root: H256) -> bool {
+    proof
+        .iter()
+        .fold(leaf, |hash, sibling| hash_pair(&hash, sibling))
+        == root
+}
+
+/// Claim of one account in a merkle distribution
+pub trait MerkleClaim {
+    fn account(&self) -> Address;
+    fn leaf(&self) -> H256;
+    fn proof(&self) -> &[H256];
+
+    fn verify(&self, root: H256) -> bool {
+        verify_proof(self.leaf(), self.proof(), root)
+    }
+}
+
+/// Merkle distribution with its claims, as stored in a JSON tree file
+pub trait ClaimTree: Serialize + DeserializeOwned {
+    type Claim: MerkleClaim;
+
+    fn claims(&self) -> &[Self::Claim];
+
+    fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    fn load(path: impl AsRef<Path>) -> Result<Self, DataError> {
+        Ok(Self::from_json(&std::fs::read_to_string(path)?)?)
+    }
+
+    fn save(&self, path: impl AsRef<Path>) -> Result<(), DataError> {
+        Ok(std::fs::write(path, self.to_json()?)?)
+    }
+
+    fn claim_of(&self, account: Address) -> Option<&Self::Claim> {
+        self.claims()
+            .iter()
+            .find(|claim| claim.account() == account)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proofs() {
+        for size in 1..=9u64 {
+            let leaves: Vec<H256> = (0..size)
+                .map(|i| H256(keccak256(i.to_be_bytes())))
+                .collect();
+            let tree = MerkleTree::new(leaves.clone());
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = tree.proof(index).unwrap();
+                assert!(verify_proof(*leaf, &proof, tree.root()));
+                assert!(!verify_proof(H256::zero(), &proof, tree.root()));
+            }
+            assert!(tree.proof(size as usize).is_none());
+        }
+
+        let (a, b) = (H256::repeat_byte(1), H256::repeat_byte(2));
+        assert_eq!(MerkleTree::new(vec![a, b]).root(), hash_pair(&b, &a));
+        assert_eq!(MerkleTree::new(vec![]).root(), H256::zero());
+    }
+}
@@ -0,0 +1,421 @@
+use ethers::{
+    contract::Multicall,
+    providers::Middleware,
+    types::{Address, U256},
+};
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::{
+    chainlink::{ETH_DENOMINATION, USD_DENOMINATION},
+    errors::{DataError, EventContext, FydeError},
+    liquid_vault::LiquidVault,
+    oracle_module_contract::AssetInfo,
+    uniswap::{mean_tick, tick_to_price},
+    utils::{latest_timestamp, FydeAmount},
+    AddressList, Chain, ChainlinkFeedRegistry, LiquidVaultContract, OracleModuleContract,
+    UniswapV3Pool,
+};
+
+pub struct Oracle<M: Middleware> {
+    contract: OracleModuleContract<M>,
+    liquid_vault: LiquidVaultContract<M>,
+    vault: LiquidVault<M>,
+    multicall: Multicall<M>,
+    client: Arc<M>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct OracleConfig {
+    /// Maximum age of a market price, in seconds
+    pub stale_period: u32,
+    /// Maximum deviation from the reference price, in basis points
+    pub deviation_threshold: u16,
+    pub twap_period_short: u16,
+    pub twap_period_long: u16,
+    /// Chainlink feed registry
+    pub cl_registry: Address,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ManualPrice {
+    pub price: FydeAmount,
+    pub valid_until: u64,
+    pub min_price: FydeAmount,
+    pub max_price: FydeAmount,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+pub enum PriceSource {
+    /// Price cached by `useCache` for the current transaction
+    Cache,
+    /// Keeper price within its validity
+    Manual,
+    /// Uniswap TWAP, converted with Chainlink for ETH pairs
+    Market,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct AssetPriceReport {
+    pub asset: Address,
+    /// None when `getPriceInUSD` reverts, e.g. on a price outside of the bounds
+    pub price_in_usd: Option<FydeAmount>,
+    pub cached_price: FydeAmount,
+    pub manual_price: ManualPrice,
+    pub use_chainlink_eth_pair: bool,
+    pub active_source: PriceSource,
+    /// Age in seconds of the Chainlink ETH/USD round converting the market price of ETH
+    /// pairs, none for assets priced from their pool only
+    pub market_age: Option<u64>,
+    /// The active source is older than the stale period. None for a cached price, whose
+    /// age is unknown, and false for a manual price, only used within its validity.
+    pub stale: Option<bool>,
+    /// Deviation of the short TWAP from the long TWAP of the asset pool, in basis points
+    pub twap_deviation_bps: Option<u64>,
+    /// The price is outside of the manual bounds, or the market price is read while the
+    /// short and long TWAPs deviate more than the threshold
+    pub outside_deviation_band: bool,
+}
+
+/// Market source of an asset: its pool TWAPs and the Chainlink round converting ETH pairs
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct MarketSource {
+    pub twap_short_price: Option<FydeAmount>,
+    pub twap_long_price: Option<FydeAmount>,
+    /// `updatedAt` of the Chainlink ETH/USD round, for ETH pairs
+    pub chainlink_updated_at: Option<u64>,
+}
+
+impl ManualPrice {
+    /// From the `manualPrice` getter output
+    pub fn from_stored(
+        (price, valid_until, min_price, max_price): (u128, u128, u128, u128),
+    ) -> Self {
+        ManualPrice {
+            price: FydeAmount::new(U256::from(price), 18),
+            valid_until: valid_until as u64,
+            min_price: FydeAmount::new(U256::from(min_price), 18),
+            max_price: FydeAmount::new(U256::from(max_price), 18),
+        }
+    }
+}
+
+/// Source `getPriceInUSD` reads from at `timestamp`
+pub fn active_source(
+    cached_price: &FydeAmount,
+    manual: &ManualPrice,
+    timestamp: u64,
+) -> PriceSource {
+    if !cached_price.is_zero() {
+        PriceSource::Cache
+    } else if !manual.price.is_zero() && manual.valid_until >= timestamp {
+        PriceSource::Manual
+    } else {
+        PriceSource::Market
+    }
+}
+
+/// Whether the price read from `source` is older than `stale_period` at `timestamp`, none
+/// when its age is unknown
+pub fn is_stale(
+    source: PriceSource,
+    market: &MarketSource,
+    stale_period: u32,
+    timestamp: u64,
+) -> Option<bool> {
+    match source {
+        PriceSource::Cache => None,
+        PriceSource::Manual => Some(false),
+        PriceSource::Market => {
+            Some(market.chainlink_updated_at.is_some_and(|updated_at| {
+                timestamp.saturating_sub(updated_at) > stale_period as u64
+            }))
+        }
+    }
+}
+
+/// Absolute deviation of `price` from `reference`, in basis points
+pub fn deviation_bps(price: &FydeAmount, reference: &FydeAmount) -> Option<u64> {
+    if reference.is_zero() {
+        return None;
+    }
+    let difference = match price.value() >= reference.value() {
+        true => price.value() - reference.value(),
+        false => reference.value() - price.value(),
+    };
+    let bps = difference.checked_mul(U256::from(10_000))? / reference.value();
+    Some(bps.try_into().unwrap_or(u64::MAX))
+}
+
+/// Whether `price` is outside of the manual bounds, unset bounds being ignored
+pub fn is_outside_bounds(price: &FydeAmount, manual: &ManualPrice) -> bool {
+    (!manual.min_price.is_zero() && price.value() < manual.min_price.value())
+        || (!manual.max_price.is_zero() && price.value() > manual.max_price.value())
+}
+
+impl<M: Middleware> Oracle<M> {
+    pub async fn new(client: Arc<M>, chain: Chain) -> Result<Self, FydeError<M>> {
+        let address_list: AddressList = AddressList::new(&chain);
+        Ok(Self {
+            contract: OracleModuleContract::new(address_list.oracle_module, client.clone()),
+            liquid_vault: LiquidVaultContract::new(address_list.liquid_vault, client.clone()),
+            vault: LiquidVault::new(client.clone(), chain).await?,
+            multicall: Multicall::new(client.clone(), None).await?,
+            client,
+        })
+    }
+
+    pub async fn get_config(&self) -> Result<OracleConfig, FydeError<M>> {
+        let mut multicall = self.multicall.clone();
+        multicall.clear_calls();
+        multicall.add_call(self.contract.stale_period(), false);
+        multicall.add_call(self.contract.deviation_threshold(), false);
+        multicall.add_call(self.contract.twap_period_short(), false);
+        multicall.add_call(self.contract.twap_period_long(), false);
+        multicall.add_call(self.contract.cl_registry(), false);
+        let (stale_period, deviation_threshold, twap_period_short, twap_period_long, cl_registry): (
+            u32,
+            u16,
+            u16,
+            u16,
+            Address,
+        ) = multicall.call().await?;
+
+        Ok(OracleConfig {
+            stale_period,
+            deviation_threshold,
+            twap_period_short,
+            twap_period_long,
+            cl_registry,
+        })
+    }
+
+    /// Asset info as stored in the liquid vault, the input of `getPriceInUSD`
+    pub async fn get_asset_info(&self, asset: Address) -> Result<AssetInfo, FydeError<M>> {
+        let (
+            target_concentration,
+            uniswap_pool,
+            incentive_factor,
+            asset_decimals,
+            quote_token_decimals,
+            uniswap_quote_token,
+            is_supported,
+        ) = self.liquid_vault.asset_info(asset).call().await?;
+        Ok(AssetInfo {
+            target_concentration,
+            uniswap_pool,
+            incentive_factor,
+            asset_decimals,
+            quote_token_decimals,
+            uniswap_quote_token,
+            is_supported,
+        })
+    }
+
+    pub async fn get_price_in_usd(&self, asset: Address) -> Result<FydeAmount, FydeError<M>> {
+        let asset_info = self.get_asset_info(asset).await?;
+        let price = self
+            .contract
+            .get_price_in_usd(asset, asset_info)
+            .call()
+            .await?;
+        Ok(FydeAmount::new(price, 18))
+    }
+
+    pub async fn get_manual_price(&self, asset: Address) -> Result<ManualPrice, FydeError<M>> {
+        let manual_price = self.contract.manual_price(asset).call().await?;
+        Ok(ManualPrice::from_stored(manual_price))
+    }
+
+    pub async fn get_cached_price(&self, asset: Address) -> Result<FydeAmount, FydeError<M>> {
+        let cached = self.contract.cache(asset).call().await?;
+        Ok(FydeAmount::new(cached, 18))
+    }
+
+    /// TWAPs of the asset pool over the oracle periods, and the Chainlink ETH/USD round
+    /// when the asset is an ETH pair
+    pub async fn get_market_source(
+        &self,
+        asset: Address,
+        config: &OracleConfig,
+        use_chainlink_eth_pair: bool,
+    ) -> Result<MarketSource, FydeError<M>> {
+        let asset_info = self.get_asset_info(asset).await?;
+        let (mut twap_short_price, mut twap_long_price) = (None, None);
+        if !asset_info.uniswap_pool.is_zero()
+            && config.twap_period_short != 0
+            && config.twap_period_long != 0
+        {
+            let pool = UniswapV3Pool::new(asset_info.uniswap_pool, self.client.clone());
+            let short = config.twap_period_short as u32;
+            let long = config.twap_period_long as u32;
+            let (tick_cumulatives, _) = pool.observe(vec![long, short, 0]).call().await?;
+            let asset_is_token0 = pool.token_0().call().await? == asset;
+            let price = |start: i64, period| {
+                tick_to_price(
+                    mean_tick(start, tick_cumulatives[2], period),
+                    asset_is_token0,
+                    asset_info.asset_decimals,
+                    asset_info.quote_token_decimals,
+                )
+            };
+            twap_short_price = price(tick_cumulatives[1], short);
+            twap_long_price = price(tick_cumulatives[0], long);
+        }
+
+        let chainlink_updated_at = match use_chainlink_eth_pair {
+            true => {
+                let registry = ChainlinkFeedRegistry::new(config.cl_registry, self.client.clone());
+                let (_, _, _, updated_at, _) = registry
+                    .latest_round_data(ETH_DENOMINATION, USD_DENOMINATION)
+                    .call()
+                    .await?;
+                Some(u64::try_from(updated_at).map_err(|_| {
+                    DataError::out_of_range("Chainlink ETH/USD updatedAt", EventContext::default())
+                })?)
+            }
+            false => None,
+        };
+
+        Ok(MarketSource {
+            twap_short_price,
+            twap_long_price,
+            chainlink_updated_at,
+        })
+    }
+
+    pub async fn get_asset_report(
+        &self,
+        asset: Address,
+        config: &OracleConfig,
+    ) -> Result<AssetPriceReport, FydeError<M>> {
+        let mut multicall = self.multicall.clone();
+        multicall.clear_calls();
+        multicall.add_call(self.contract.cache(asset), false);
+        multicall.add_call(self.contract.manual_price(asset), false);
+        multicall.add_call(self.contract.use_chainlink_eth_pair(asset), false);
+        let (cached_price, manual_price, use_chainlink_eth_pair): (
+            U256,
+            (u128, u128, u128, u128),
+            bool,
+        ) = multicall.call().await?;
+        let cached_price = FydeAmount::new(cached_price, 18);
+        let manual_price = ManualPrice::from_stored(manual_price);
+
+        let price_in_usd = match self.get_price_in_usd(asset).await {
+            Ok(price) => Some(price),
+            Err(FydeError::ContractError(err)) if err.is_revert() => None,
+            Err(err) => return Err(err),
+        };
+        let now = latest_timestamp(self.client.as_ref()).await?;
+        let market = self
+            .get_market_source(asset, config, use_chainlink_eth_pair)
+            .await?;
+
+        let active_source = active_source(&cached_price, &manual_price, now);
+        let twap_deviation_bps = market
+            .twap_short_price
+            .as_ref()
+            .zip(market.twap_long_price.as_ref())
+            .and_then(|(short, long)| deviation_bps(short, long));
+        let outside_deviation_band = price_in_usd
+            .as_ref()
+            .is_some_and(|price| is_outside_bounds(price, &manual_price))
+            || (active_source == PriceSource::Market
+                && twap_deviation_bps.is_some_and(|bps| bps > config.deviation_threshold as u64));
+
+        Ok(AssetPriceReport {
+            asset,
+            price_in_usd,
+            cached_price,
+            manual_price,
+            use_chainlink_eth_pair,
+            active_source,
+            market_age: market
+                .chainlink_updated_at
+                .map(|updated_at| now.saturating_sub(updated_at)),
+            stale: is_stale(active_source, &market, config.stale_period, now),
+            twap_deviation_bps,
+            outside_deviation_band,
+        })
+    }
+
+    /// Whether the Chainlink price of `asset` is read from its ETH pair
+    pub async fn uses_chainlink_eth_pair(&self, asset: Address) -> Result<bool, FydeError<M>> {
+        Ok(self.contract.use_chainlink_eth_pair(asset).call().await?)
+    }
+
+    /// Report of every asset of the protocol
+    pub async fn get_reports(&self) -> Result<Vec<AssetPriceReport>, FydeError<M>> {
+        let config = self.get_config().await?;
+        let mut reports = vec![];
+        for asset in self.vault.get_assets_list().await? {
+            reports.push(self.get_asset_report(asset, &config).await?);
+        }
+        Ok(reports)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_price_diagnostics() {
+        let manual = ManualPrice {
+            price: "2000".parse().unwrap(),
+            valid_until: 1_000,
+            min_price: "1500".parse().unwrap(),
+            max_price: "2500".parse().unwrap(),
+        };
+        let zero = FydeAmount::zero(18);
+
+        assert_eq!(active_source(&zero, &manual, 999), PriceSource::Manual);
+        assert_eq!(active_source(&zero, &manual, 1_001), PriceSource::Market);
+        assert_eq!(
+            active_source(&"1".parse().unwrap(), &manual, 999),
+            PriceSource::Cache
+        );
+
+        assert_eq!(
+            deviation_bps(&"2100".parse().unwrap(), &manual.price),
+            Some(500)
+        );
+        assert_eq!(
+            deviation_bps(&"1900".parse().unwrap(), &manual.price),
+            Some(500)
+        );
+        assert_eq!(deviation_bps(&manual.price, &zero), None);
+
+        assert!(is_outside_bounds(&"2600".parse().unwrap(), &manual));
+        assert!(is_outside_bounds(&"1400".parse().unwrap(), &manual));
+        assert!(!is_outside_bounds(&"2000".parse().unwrap(), &manual));
+
+        let market = MarketSource {
+            twap_short_price: Some("2000".parse().unwrap()),
+            twap_long_price: Some("2000".parse().unwrap()),
+            chainlink_updated_at: Some(1_000),
+        };
+        assert_eq!(is_stale(PriceSource::Cache, &market, 3_600, 5_000), None);
+        assert_eq!(
+            is_stale(PriceSource::Manual, &market, 3_600, 5_000),
+            Some(false)
+        );
+        assert_eq!(
+            is_stale(PriceSource::Market, &market, 3_600, 4_600),
+            Some(false)
+        );
+        assert_eq!(
+            is_stale(PriceSource::Market, &market, 3_600, 4_601),
+            Some(true)
+        );
+        let pool_only = MarketSource {
+            chainlink_updated_at: None,
+            ..market
+        };
+        assert_eq!(
+            is_stale(PriceSource::Market, &pool_only, 3_600, 4_601),
+            Some(false)
+        );
+    }
+}
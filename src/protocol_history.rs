@@ -1,28 +1,58 @@
-use std::{collections::HashMap, sync::Arc, vec};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+    vec,
+};
 
 use ethers::{
+    contract::parse_log,
     prelude::LogMeta,
-    providers::{Http, Middleware, Provider},
-    types::{Address, H256, U256},
+    providers::{Middleware, PubsubClient},
+    types::{Address, Filter, Log, H256, U256},
 };
-use serde::Serialize;
+use futures::{stream, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    errors::FydeError, AddressList, Chain, LiquidVaultContract, LiquidVaultContractEvents,
-    RelayerContract, RelayerContractEvents, Strsy, StrsyEvents,
+    block_cache::{BlockMetaCache, WithBlockCache},
+    errors::{DataError, EventContext, FydeError},
+    log_scanner::{LogScanner, ScanCursor, WithScanner},
+    AddressList, Chain, LiquidVaultContract, LiquidVaultContractEvents, RelayerContract,
+    RelayerContractEvents, Strsy, StrsyEvents, VoteEscrowContract, VoteEscrowContractEvents,
 };
 
-pub struct ProtocolHistory {
-    client: Arc<Provider<Http>>,
-    liquid_vault: LiquidVaultContract<Provider<Http>>,
-    relayer: RelayerContract<Provider<Http>>,
-    strsy: Strsy<Provider<Http>>,
+/// Pending requests kept by a subscription before the earliest is dropped
+pub const MAX_PENDING_REQUESTS: usize = 10_000;
+/// Attempts to read a request of a subscription before it is dropped
+pub const MAX_REQUEST_RETRIES: usize = 3;
+/// Blocks during which a subscription can reopen a processed request on a reorg
+pub const SUBSCRIPTION_REORG_WINDOW: u64 = 128;
+
+pub struct ProtocolHistory<M: Middleware> {
+    client: Arc<M>,
+    liquid_vault: LiquidVaultContract<M>,
+    relayer: RelayerContract<M>,
+    strsy: Strsy<M>,
+    vote_escrow: VoteEscrowContract<M>,
+    scanner: LogScanner<M>,
+    block_cache: BlockMetaCache<M>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum RequestKind {
+    Deposit,
+    Withdraw,
+    Swap,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 struct RequestData {
+    kind: RequestKind,
     tx_hash: H256,
     block_number: u32,
+    #[serde(default)]
+    block_hash: H256,
+    log_index: u64,
     timestamp: u64,
     request_id: u32,
     requestor: Address,
@@ -33,9 +63,118 @@ struct RequestData {
     keep_gov_rights: bool,
 }
 
+/// Relayer requests not processed by the LiquidVault yet, carried from one scan to the next.
+/// Requests processed recently are kept too, so a reorg of their processing block can
+/// reopen them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingRequests {
+    pending: BTreeMap<u32, RequestData>,
+    /// Requests joined with their LiquidVault event, by processing block
+    processed: BTreeMap<u64, Vec<RequestData>>,
+}
+
+impl PendingRequests {
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn request_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.pending.keys().copied()
+    }
+
+    /// Block of the earliest pending request
+    pub fn earliest_block(&self) -> Option<u64> {
+        self.pending
+            .values()
+            .map(|request| request.block_number as u64)
+            .min()
+    }
+
+    /// Roll back to before `block`: requests made from it are dropped, to be scanned
+    /// again, and requests processed from it are pending again.
+    pub fn rewind(&mut self, block: u64) {
+        self.pending
+            .retain(|_, request| (request.block_number as u64) < block);
+        for (_, requests) in self.processed.split_off(&block) {
+            for request in requests {
+                if (request.block_number as u64) < block {
+                    self.pending.insert(request.request_id, request);
+                }
+            }
+        }
+    }
+
+    /// Forget the requests processed before `block`, out of reach of reorgs
+    pub fn prune(&mut self, block: u64) {
+        self.processed = self.processed.split_off(&block);
+    }
+
+    fn insert(&mut self, request: RequestData) {
+        self.pending.insert(request.request_id, request);
+    }
+
+    /// Drop a pending request whose log was removed by a reorg
+    fn remove(&mut self, request_id: u32, tx_hash: H256) {
+        if self
+            .pending
+            .get(&request_id)
+            .is_some_and(|request| request.tx_hash == tx_hash)
+        {
+            self.pending.remove(&request_id);
+        }
+    }
+
+    /// Make a request pending again when its processing log at `block` was removed by a reorg
+    fn reopen(&mut self, request_id: u32, block: u64) {
+        if let Some(requests) = self.processed.get_mut(&block) {
+            if let Some(position) = requests
+                .iter()
+                .position(|request| request.request_id == request_id)
+            {
+                let request = requests.remove(position);
+                self.pending.insert(request.request_id, request);
+            }
+        }
+    }
+
+    /// Drop the earliest pending request
+    fn pop_earliest(&mut self) -> Option<RequestData> {
+        let request_id = self
+            .pending
+            .values()
+            .min_by_key(|request| (request.block_number, request.log_index))?
+            .request_id;
+        self.pending.remove(&request_id)
+    }
+
+    /// Join the pending request of a LiquidVault event processed at `block`
+    fn join(
+        &mut self,
+        request_id: u32,
+        fyde_event: FydeEvents,
+        block: u64,
+    ) -> Result<Option<UserAction>, DataError> {
+        let request = match self.pending.remove(&request_id) {
+            Some(request) => request,
+            None => return Ok(None),
+        };
+        self.processed
+            .entry(block)
+            .or_default()
+            .push(request.clone());
+        Ok(Some(request.join(fyde_event)?))
+    }
+}
+
 struct MetaFromBlock {
     tx_hash: H256,
     block_number: u32,
+    block_hash: H256,
+    log_index: u64,
     timestamp: u64,
     from: Address,
 }
@@ -43,8 +182,11 @@ struct MetaFromBlock {
 impl From<(crate::relayer_contract::DepositFilter, MetaFromBlock)> for RequestData {
     fn from(event: (crate::relayer_contract::DepositFilter, MetaFromBlock)) -> Self {
         Self {
+            kind: RequestKind::Deposit,
             tx_hash: event.1.tx_hash,
             block_number: event.1.block_number,
+            block_hash: event.1.block_hash,
+            log_index: event.1.log_index,
             timestamp: event.1.timestamp,
             request_id: event.0.request_id,
             requestor: event.1.from,
@@ -60,8 +202,11 @@ impl From<(crate::relayer_contract::DepositFilter, MetaFromBlock)> for RequestDa
 impl From<(crate::relayer_contract::WithdrawFilter, MetaFromBlock)> for RequestData {
     fn from(event: (crate::relayer_contract::WithdrawFilter, MetaFromBlock)) -> Self {
         Self {
+            kind: RequestKind::Withdraw,
             tx_hash: event.1.tx_hash,
             block_number: event.1.block_number,
+            block_hash: event.1.block_hash,
+            log_index: event.1.log_index,
             timestamp: event.1.timestamp,
             request_id: event.0.request_id,
             requestor: event.1.from,
@@ -77,8 +222,11 @@ impl From<(crate::relayer_contract::WithdrawFilter, MetaFromBlock)> for RequestD
 impl From<(crate::relayer_contract::SwapFilter, MetaFromBlock)> for RequestData {
     fn from(event: (crate::relayer_contract::SwapFilter, MetaFromBlock)) -> Self {
         Self {
+            kind: RequestKind::Swap,
             tx_hash: event.1.tx_hash,
             block_number: event.1.block_number,
+            block_hash: event.1.block_hash,
+            log_index: event.1.log_index,
             timestamp: event.1.timestamp,
             request_id: event.0.request_id,
             requestor: event.1.from,
@@ -91,10 +239,89 @@ impl From<(crate::relayer_contract::SwapFilter, MetaFromBlock)> for RequestData
     }
 }
 
+/// Request id of a Relayer request event
+fn relayer_request_id(event: &RelayerContractEvents) -> Option<u32> {
+    match event {
+        RelayerContractEvents::DepositFilter(ev) => Some(ev.request_id),
+        RelayerContractEvents::WithdrawFilter(ev) => Some(ev.request_id),
+        RelayerContractEvents::SwapFilter(ev) => Some(ev.request_id),
+        _ => None,
+    }
+}
+
+/// State of a [`ProtocolHistory::subscribe_user_actions`] stream
+struct SubscriptionState<M: Middleware> {
+    pending: PendingRequests,
+    /// Relayer logs whose request could not be read yet, with the attempts made
+    failed: VecDeque<(Log, usize)>,
+    /// Items to yield before reading the next log
+    ready: VecDeque<Result<UserAction, FydeError<M>>>,
+}
+
+impl<M: Middleware> SubscriptionState<M> {
+    fn new() -> Self {
+        Self {
+            pending: PendingRequests::default(),
+            failed: VecDeque::new(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    fn insert(&mut self, request: RequestData) {
+        self.pending.insert(request);
+        if self.pending.len() > MAX_PENDING_REQUESTS {
+            if let Some(request) = self.pending.pop_earliest() {
+                self.ready.push_back(Err(DataError::inconsistent(
+                    format!(
+                        "request dropped, still not processed after {} newer requests",
+                        MAX_PENDING_REQUESTS
+                    ),
+                    request.context(),
+                )
+                .into()));
+            }
+        }
+    }
+}
+
+impl RequestData {
+    fn context(&self) -> EventContext {
+        EventContext::request(self.request_id)
+            .with_tx(self.tx_hash)
+            .with_block(self.block_number as u64)
+    }
+
+    fn check_request_id(&self, request_id: u32) -> Result<(), DataError> {
+        if self.request_id != request_id {
+            return Err(DataError::inconsistent(
+                format!("LiquidVault event for request {}", request_id),
+                self.context(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Asset in, asset out and amount in of a swap request
+    fn swap_params(&self) -> Result<(Address, Address, U256), DataError> {
+        match (
+            self.asset_in.first(),
+            self.asset_out.first(),
+            self.amount_in.first(),
+        ) {
+            (Some(asset_in), Some(asset_out), Some(amount_in)) => {
+                Ok((*asset_in, *asset_out, *amount_in))
+            }
+            _ => Err(DataError::missing("swap assets", self.context())),
+        }
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct Deposit {
     pub tx_hash: H256,
     pub block_number: u32,
+    pub block_hash: H256,
+    pub log_index: u64,
     pub timestamp: u64,
     pub request_id: u32,
     pub user: Address,
@@ -106,14 +333,18 @@ pub struct Deposit {
     pub trsy_minted: U256,
 }
 
-impl From<(RequestData, crate::liquid_vault_contract::DepositFilter)> for Deposit {
-    fn from(data: (RequestData, crate::liquid_vault_contract::DepositFilter)) -> Self {
-        if data.0.request_id != data.1.request_id {
-            panic!("Request id mismatch");
-        }
-        Self {
+impl TryFrom<(RequestData, crate::liquid_vault_contract::DepositFilter)> for Deposit {
+    type Error = DataError;
+
+    fn try_from(
+        data: (RequestData, crate::liquid_vault_contract::DepositFilter),
+    ) -> Result<Self, Self::Error> {
+        data.0.check_request_id(data.1.request_id)?;
+        Ok(Self {
             tx_hash: data.0.tx_hash,
             block_number: data.0.block_number,
+            block_hash: data.0.block_hash,
+            log_index: data.0.log_index,
             timestamp: data.0.timestamp,
             request_id: data.0.request_id,
             user: data.0.requestor,
@@ -123,7 +354,7 @@ impl From<(RequestData, crate::liquid_vault_contract::DepositFilter)> for Deposi
             minted_at_trsy_price: data.1.trsy_price,
             usd_value_deposited: data.1.usd_deposit_value,
             trsy_minted: data.1.trsy_minted,
-        }
+        })
     }
 }
 
@@ -131,6 +362,8 @@ impl From<(RequestData, crate::liquid_vault_contract::DepositFilter)> for Deposi
 pub struct Withdraw {
     pub tx_hash: H256,
     pub block_number: u32,
+    pub block_hash: H256,
+    pub log_index: u64,
     pub timestamp: u64,
     pub request_id: u32,
     pub user: Address,
@@ -141,14 +374,18 @@ pub struct Withdraw {
     pub trsy_burned: U256,
 }
 
-impl From<(RequestData, crate::liquid_vault_contract::WithdrawFilter)> for Withdraw {
-    fn from(data: (RequestData, crate::liquid_vault_contract::WithdrawFilter)) -> Self {
-        if data.0.request_id != data.1.request_id {
-            panic!("Request id mismatch");
-        }
-        Self {
+impl TryFrom<(RequestData, crate::liquid_vault_contract::WithdrawFilter)> for Withdraw {
+    type Error = DataError;
+
+    fn try_from(
+        data: (RequestData, crate::liquid_vault_contract::WithdrawFilter),
+    ) -> Result<Self, Self::Error> {
+        data.0.check_request_id(data.1.request_id)?;
+        Ok(Self {
             tx_hash: data.0.tx_hash,
             block_number: data.0.block_number,
+            block_hash: data.0.block_hash,
+            log_index: data.0.log_index,
             timestamp: data.0.timestamp,
             request_id: data.0.request_id,
             user: data.0.requestor,
@@ -157,7 +394,7 @@ impl From<(RequestData, crate::liquid_vault_contract::WithdrawFilter)> for Withd
             burned_at_trsy_price: data.1.trsy_price,
             usd_value_withdrawn: data.1.usd_withdraw_value,
             trsy_burned: data.1.trsy_burned,
-        }
+        })
     }
 }
 
@@ -165,6 +402,8 @@ impl From<(RequestData, crate::liquid_vault_contract::WithdrawFilter)> for Withd
 pub struct Swap {
     pub tx_hash: H256,
     pub block_number: u32,
+    pub block_hash: H256,
+    pub log_index: u64,
     pub timestamp: u64,
     pub request_id: u32,
     pub user: Address,
@@ -174,22 +413,27 @@ pub struct Swap {
     pub amount_out: U256,
 }
 
-impl From<(RequestData, crate::liquid_vault_contract::SwapFilter)> for Swap {
-    fn from(data: (RequestData, crate::liquid_vault_contract::SwapFilter)) -> Self {
-        if data.0.request_id != data.1.request_id {
-            panic!("Request id mismatch");
-        }
-        Self {
+impl TryFrom<(RequestData, crate::liquid_vault_contract::SwapFilter)> for Swap {
+    type Error = DataError;
+
+    fn try_from(
+        data: (RequestData, crate::liquid_vault_contract::SwapFilter),
+    ) -> Result<Self, Self::Error> {
+        data.0.check_request_id(data.1.request_id)?;
+        let (asset_in, asset_out, amount_in) = data.0.swap_params()?;
+        Ok(Self {
             tx_hash: data.0.tx_hash,
             block_number: data.0.block_number,
+            block_hash: data.0.block_hash,
+            log_index: data.0.log_index,
             timestamp: data.0.timestamp,
             request_id: data.0.request_id,
             user: data.0.requestor,
-            asset_in: data.0.asset_in[0],
-            asset_out: data.0.asset_out[0],
-            amount_in: data.0.amount_in[0],
+            asset_in,
+            asset_out,
+            amount_in,
             amount_out: data.1.amount_out,
-        }
+        })
     }
 }
 
@@ -198,6 +442,8 @@ pub enum UserAction {
     Deposit {
         tx_hash: H256,
         block_number: u32,
+        block_hash: H256,
+        log_index: u64,
         timestamp: u64,
         request_id: u32,
         user: Address,
@@ -211,6 +457,8 @@ pub enum UserAction {
     Withdraw {
         tx_hash: H256,
         block_number: u32,
+        block_hash: H256,
+        log_index: u64,
         timestamp: u64,
         request_id: u32,
         user: Address,
@@ -223,6 +471,8 @@ pub enum UserAction {
     Swap {
         tx_hash: H256,
         block_number: u32,
+        block_hash: H256,
+        log_index: u64,
         timestamp: u64,
         request_id: u32,
         user: Address,
@@ -233,6 +483,97 @@ pub enum UserAction {
     },
 }
 
+impl From<Deposit> for UserAction {
+    fn from(deposit: Deposit) -> Self {
+        UserAction::Deposit {
+            tx_hash: deposit.tx_hash,
+            block_number: deposit.block_number,
+            block_hash: deposit.block_hash,
+            log_index: deposit.log_index,
+            timestamp: deposit.timestamp,
+            request_id: deposit.request_id,
+            user: deposit.user,
+            asset_in: deposit.asset_in,
+            amount_in: deposit.amount_in,
+            keep_gov_rights: deposit.keep_gov_rights,
+            minted_at_trsy_price: deposit.minted_at_trsy_price,
+            usd_value_deposited: deposit.usd_value_deposited,
+            trsy_minted: deposit.trsy_minted,
+        }
+    }
+}
+
+impl From<Withdraw> for UserAction {
+    fn from(withdraw: Withdraw) -> Self {
+        UserAction::Withdraw {
+            tx_hash: withdraw.tx_hash,
+            block_number: withdraw.block_number,
+            block_hash: withdraw.block_hash,
+            log_index: withdraw.log_index,
+            timestamp: withdraw.timestamp,
+            request_id: withdraw.request_id,
+            user: withdraw.user,
+            asset_out: withdraw.asset_out,
+            amount_out: withdraw.amount_out,
+            burned_at_trsy_price: withdraw.burned_at_trsy_price,
+            usd_value_withdrawn: withdraw.usd_value_withdrawn,
+            trsy_burned: withdraw.trsy_burned,
+        }
+    }
+}
+
+impl From<Swap> for UserAction {
+    fn from(swap: Swap) -> Self {
+        UserAction::Swap {
+            tx_hash: swap.tx_hash,
+            block_number: swap.block_number,
+            block_hash: swap.block_hash,
+            log_index: swap.log_index,
+            timestamp: swap.timestamp,
+            request_id: swap.request_id,
+            user: swap.user,
+            asset_in: swap.asset_in,
+            asset_out: swap.asset_out,
+            amount_in: swap.amount_in,
+            amount_out: swap.amount_out,
+        }
+    }
+}
+
+impl UserAction {
+    pub fn tx_hash(&self) -> H256 {
+        match self {
+            UserAction::Deposit { tx_hash, .. }
+            | UserAction::Withdraw { tx_hash, .. }
+            | UserAction::Swap { tx_hash, .. } => *tx_hash,
+        }
+    }
+
+    pub fn log_index(&self) -> u64 {
+        match self {
+            UserAction::Deposit { log_index, .. }
+            | UserAction::Withdraw { log_index, .. }
+            | UserAction::Swap { log_index, .. } => *log_index,
+        }
+    }
+
+    pub fn block_number(&self) -> u64 {
+        match self {
+            UserAction::Deposit { block_number, .. }
+            | UserAction::Withdraw { block_number, .. }
+            | UserAction::Swap { block_number, .. } => *block_number as u64,
+        }
+    }
+
+    pub fn block_hash(&self) -> H256 {
+        match self {
+            UserAction::Deposit { block_hash, .. }
+            | UserAction::Withdraw { block_hash, .. }
+            | UserAction::Swap { block_hash, .. } => *block_hash,
+        }
+    }
+}
+
 #[derive(Clone)]
 enum FydeEvents {
     Deposit(crate::liquid_vault_contract::DepositFilter),
@@ -240,8 +581,59 @@ enum FydeEvents {
     Swap(crate::liquid_vault_contract::SwapFilter),
 }
 
-impl ProtocolHistory {
-    pub fn new(client: Arc<Provider<Http>>, chain: Chain) -> Self {
+impl FydeEvents {
+    /// Request id and LiquidVault event processing a request, if the event is one
+    fn from_event(event: LiquidVaultContractEvents) -> Option<(u32, Self)> {
+        match event {
+            LiquidVaultContractEvents::DepositFilter(ev) => {
+                Some((ev.request_id, FydeEvents::Deposit(ev)))
+            }
+            LiquidVaultContractEvents::WithdrawFilter(ev) => {
+                Some((ev.request_id, FydeEvents::Withdraw(ev)))
+            }
+            LiquidVaultContractEvents::SwapFilter(ev) => {
+                Some((ev.request_id, FydeEvents::Swap(ev)))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl RequestData {
+    /// Combine the request with the LiquidVault event that processed it
+    fn join(self, fyde_event: FydeEvents) -> Result<UserAction, DataError> {
+        match (self.kind, fyde_event) {
+            (RequestKind::Deposit, FydeEvents::Deposit(ev)) => {
+                Ok(UserAction::from(Deposit::try_from((self, ev))?))
+            }
+            (RequestKind::Withdraw, FydeEvents::Withdraw(ev)) => {
+                Ok(UserAction::from(Withdraw::try_from((self, ev))?))
+            }
+            (RequestKind::Swap, FydeEvents::Swap(ev)) => {
+                Ok(UserAction::from(Swap::try_from((self, ev))?))
+            }
+            (kind, _) => Err(DataError::inconsistent(
+                format!("{:?} request processed as another action", kind),
+                self.context(),
+            )),
+        }
+    }
+}
+
+impl<M: Middleware> WithScanner<M> for ProtocolHistory<M> {
+    fn scanner_mut(&mut self) -> &mut LogScanner<M> {
+        &mut self.scanner
+    }
+}
+
+impl<M: Middleware> WithBlockCache<M> for ProtocolHistory<M> {
+    fn block_cache_mut(&mut self) -> &mut BlockMetaCache<M> {
+        &mut self.block_cache
+    }
+}
+
+impl<M: Middleware> ProtocolHistory<M> {
+    pub fn new(client: Arc<M>, chain: Chain) -> Self {
         let address_list: AddressList = AddressList::new(&chain);
 
         Self {
@@ -249,151 +641,278 @@ impl ProtocolHistory {
             liquid_vault: LiquidVaultContract::new(address_list.liquid_vault, client.clone()),
             relayer: RelayerContract::new(address_list.relayer, client.clone()),
             strsy: Strsy::new(address_list.strsy, client.clone()),
+            vote_escrow: VoteEscrowContract::new(address_list.vote_escrow, client.clone()),
+            scanner: LogScanner::new(client.clone()),
+            block_cache: BlockMetaCache::new(client.clone()),
         }
     }
 
+    async fn request_from_event(
+        &self,
+        event: RelayerContractEvents,
+        meta: &LogMeta,
+    ) -> Result<Option<RequestData>, FydeError<M>> {
+        let request = match event {
+            RelayerContractEvents::DepositFilter(ev) => {
+                RequestData::from((ev, self.get_meta_from_log(meta).await?))
+            }
+            RelayerContractEvents::WithdrawFilter(ev) => {
+                RequestData::from((ev, self.get_meta_from_log(meta).await?))
+            }
+            RelayerContractEvents::SwapFilter(ev) => {
+                RequestData::from((ev, self.get_meta_from_log(meta).await?))
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(request))
+    }
+
+    async fn get_meta_from_log(&self, meta: &LogMeta) -> Result<MetaFromBlock, FydeError<M>> {
+        let tx_meta = self.block_cache.tx_meta(meta).await?;
+
+        Ok(MetaFromBlock {
+            tx_hash: tx_meta.tx_hash,
+            block_number: tx_meta.block_number as u32,
+            block_hash: meta.block_hash,
+            log_index: meta.log_index.as_u64(),
+            timestamp: tx_meta.timestamp,
+            from: tx_meta.from,
+        })
+    }
+
     pub async fn get_data(
         &self,
         from_block: Option<u64>,
         to_block: Option<u64>,
-    ) -> Result<Vec<UserAction>, FydeError> {
-        let mut event_query = self.relayer.events();
+    ) -> Result<Vec<UserAction>, FydeError<M>> {
+        let (user_actions, _) = self
+            .get_data_with_cursor(from_block.unwrap_or(0), to_block)
+            .await?;
+        Ok(user_actions)
+    }
+
+    /// Same as [`ProtocolHistory::get_data`], also returning the cursor to resume the scan from.
+    ///
+    /// Requests not processed by `to_block` are left out, and the cursor stops at the block
+    /// of the earliest of them so a resumed scan joins them. Actions after that block are
+    /// then returned again, they are keyed by (tx_hash, log_index).
+    pub async fn get_data_with_cursor(
+        &self,
+        from_block: u64,
+        to_block: Option<u64>,
+    ) -> Result<(Vec<UserAction>, ScanCursor), FydeError<M>> {
+        let mut pending = PendingRequests::default();
+        let (user_actions, mut cursor) = self
+            .get_data_with_pending(&mut pending, from_block, to_block)
+            .await?;
+        if let Some(block) = pending.earliest_block() {
+            cursor.next_block = cursor.next_block.min(block);
+        }
+        Ok((
+            user_actions
+                .into_iter()
+                .map(|(user_action, _)| user_action)
+                .collect(),
+            cursor,
+        ))
+    }
 
-        if let Some(from) = from_block {
-            event_query = event_query.from_block(from);
+    /// User actions processed between two blocks, with the log of the LiquidVault event
+    /// processing each of them. Requests of the range are added to `pending`, and LiquidVault
+    /// events are joined with the pending requests, so requests processed after `to_block`
+    /// are returned by a later call with the same `pending`.
+    pub async fn get_data_with_pending(
+        &self,
+        pending: &mut PendingRequests,
+        from_block: u64,
+        to_block: Option<u64>,
+    ) -> Result<(Vec<(UserAction, LogMeta)>, ScanCursor), FydeError<M>> {
+        let filter =
+            Filter::new().address(vec![self.relayer.address(), self.liquid_vault.address()]);
+        let scan = self.scanner.scan(&filter, from_block, to_block).await?;
+
+        let events: Vec<(RelayerContractEvents, LogMeta)> =
+            scan.decode_from(self.relayer.address());
+        self.block_cache
+            .prefetch(events.iter().map(|(_, meta)| meta.block_number.as_u64()))
+            .await?;
+        let mut requests = vec![];
+        for (event, meta) in events {
+            if let Some(request) = self.request_from_event(event, &meta).await? {
+                requests.push((request, meta));
+            }
         }
 
-        if let Some(to) = to_block {
-            event_query = event_query.to_block(to);
+        let events: Vec<(LiquidVaultContractEvents, LogMeta)> =
+            scan.decode_from(self.liquid_vault.address());
+        let mut fyde_events: Vec<(u32, FydeEvents, LogMeta)> = events
+            .into_iter()
+            .filter_map(|(event, meta)| {
+                FydeEvents::from_event(event).map(|(request_id, ev)| (request_id, ev, meta))
+            })
+            .collect();
+
+        // Requests are added before the LiquidVault events of the same log position or later
+        let mut user_actions = vec![];
+        let mut requests = requests.into_iter().peekable();
+        fyde_events.sort_by_key(|(_, _, meta)| (meta.block_number, meta.log_index));
+        for (request_id, fyde_event, meta) in fyde_events {
+            while let Some((request, _)) = requests.next_if(|(_, request_meta)| {
+                (request_meta.block_number, request_meta.log_index)
+                    < (meta.block_number, meta.log_index)
+            }) {
+                pending.insert(request);
+            }
+            let block = meta.block_number.as_u64();
+            if let Some(user_action) = pending.join(request_id, fyde_event, block)? {
+                user_actions.push((user_action, meta));
+            }
         }
+        for (request, _) in requests {
+            pending.insert(request);
+        }
+        user_actions
+            .sort_by_key(|(user_action, _)| (user_action.block_number(), user_action.log_index()));
 
-        let events: Vec<(RelayerContractEvents, LogMeta)> = event_query.query_with_meta().await?;
+        Ok((user_actions, scan.cursor))
+    }
 
-        let mut requests_data = vec![];
+    /// Stream user actions as requests are processed, from the latest block on.
+    ///
+    /// A request is held until the LiquidVault event processing it, which the keeper
+    /// usually emits a few blocks later. LiquidVault events of requests made before the
+    /// subscription are skipped. A request whose log is removed by a reorg is dropped, and
+    /// one whose processing log is removed waits for its event again, so its action is
+    /// streamed again once processed.
+    ///
+    /// A request that cannot be read (e.g. a transient RPC error) is streamed as an error
+    /// and retried on the next logs. After [`MAX_REQUEST_RETRIES`] attempts, or when its
+    /// LiquidVault event comes first, it is dropped with a last error.
+    pub async fn subscribe_user_actions(
+        &self,
+    ) -> Result<impl Stream<Item = Result<UserAction, FydeError<M>>> + '_, FydeError<M>>
+    where
+        M::Provider: PubsubClient,
+    {
+        let filter =
+            Filter::new().address(vec![self.relayer.address(), self.liquid_vault.address()]);
+        let logs = self
+            .client
+            .subscribe_logs(&filter)
+            .await
+            .map_err(FydeError::MiddlewareError)?;
 
-        for event in events {
-            match event {
-                (RelayerContractEvents::DepositFilter(ev), meta) => {
-                    let tx = meta.transaction_hash;
-                    let tx_data = self.client.get_transaction(tx).await?.unwrap();
-                    let block = self
-                        .client
-                        .get_block(tx_data.block_number.unwrap())
-                        .await?
-                        .unwrap();
-                    let block_meta = MetaFromBlock {
-                        tx_hash: tx,
-                        block_number: block.number.unwrap().as_u32(),
-                        timestamp: block.timestamp.as_u64(),
-                        from: tx_data.from,
-                    };
-                    requests_data.push(RequestData::from((ev, block_meta)))
-                }
-                (RelayerContractEvents::WithdrawFilter(ev), meta) => {
-                    let tx = meta.transaction_hash;
-                    let tx_data = self.client.get_transaction(tx).await?.unwrap();
-                    let block = self
-                        .client
-                        .get_block(tx_data.block_number.unwrap())
-                        .await?
-                        .unwrap();
-                    let block_meta = MetaFromBlock {
-                        tx_hash: tx,
-                        block_number: block.number.unwrap().as_u32(),
-                        timestamp: block.timestamp.as_u64(),
-                        from: tx_data.from,
-                    };
-                    requests_data.push(RequestData::from((ev, block_meta)))
+        Ok(self.user_actions_from_logs(logs))
+    }
+
+    fn user_actions_from_logs<'a, S>(
+        &'a self,
+        logs: S,
+    ) -> impl Stream<Item = Result<UserAction, FydeError<M>>> + 'a
+    where
+        S: Stream<Item = Log> + Unpin + 'a,
+    {
+        stream::unfold(
+            (logs, SubscriptionState::new()),
+            move |(mut logs, mut state)| async move {
+                loop {
+                    if let Some(item) = state.ready.pop_front() {
+                        return Some((item, (logs, state)));
+                    }
+                    let log = logs.next().await?;
+                    self.retry_failed(&mut state).await;
+                    self.on_log(log, &mut state).await;
                 }
-                (RelayerContractEvents::SwapFilter(ev), meta) => {
-                    let tx = meta.transaction_hash;
-                    let tx_data = self.client.get_transaction(tx).await?.unwrap();
-                    let block = self
-                        .client
-                        .get_block(tx_data.block_number.unwrap())
-                        .await?
-                        .unwrap();
-                    let block_meta = MetaFromBlock {
-                        tx_hash: tx,
-                        block_number: block.number.unwrap().as_u32(),
-                        timestamp: block.timestamp.as_u64(),
-                        from: tx_data.from,
-                    };
-                    requests_data.push(RequestData::from((ev, block_meta)))
+            },
+        )
+    }
+
+    async fn retry_failed(&self, state: &mut SubscriptionState<M>) {
+        for _ in 0..state.failed.len() {
+            let Some((log, attempts)) = state.failed.pop_front() else {
+                break;
+            };
+            let meta = LogMeta::from(&log);
+            let Ok(event) = parse_log::<RelayerContractEvents>(log.clone()) else {
+                continue;
+            };
+            match self.request_from_event(event, &meta).await {
+                Ok(Some(request)) => state.insert(request),
+                Ok(None) => {}
+                Err(_) if attempts < MAX_REQUEST_RETRIES => {
+                    state.failed.push_back((log, attempts + 1))
                 }
-                _ => {}
+                Err(e) => state.ready.push_back(Err(e)),
             }
         }
+    }
 
-        let mut event_query = self.liquid_vault.events();
-        if let Some(from) = from_block {
-            event_query = event_query.from_block(from);
-        }
-        if let Some(to) = to_block {
-            event_query = event_query.to_block(to);
-        }
-        let events = event_query.query().await?;
+    async fn on_log(&self, log: Log, state: &mut SubscriptionState<M>) {
+        let meta = LogMeta::from(&log);
+        let block = meta.block_number.as_u64();
 
-        let mut fyde_events = HashMap::new();
-        for event in events {
-            match event {
-                LiquidVaultContractEvents::DepositFilter(ev) => {
-                    fyde_events.insert(ev.request_id, FydeEvents::Deposit(ev));
-                }
-                LiquidVaultContractEvents::WithdrawFilter(ev) => {
-                    fyde_events.insert(ev.request_id, FydeEvents::Withdraw(ev));
+        if log.address == self.relayer.address() {
+            let Ok(event) = parse_log::<RelayerContractEvents>(log.clone()) else {
+                return;
+            };
+            if log.removed == Some(true) {
+                if let Some(request_id) = relayer_request_id(&event) {
+                    state.pending.remove(request_id, meta.transaction_hash);
                 }
-                LiquidVaultContractEvents::SwapFilter(ev) => {
-                    fyde_events.insert(ev.request_id, FydeEvents::Swap(ev));
+                state.failed.retain(|(failed, _)| {
+                    (failed.transaction_hash, failed.log_index)
+                        != (log.transaction_hash, log.log_index)
+                });
+                return;
+            }
+            match self.request_from_event(event, &meta).await {
+                Ok(Some(request)) => state.insert(request),
+                Ok(None) => {}
+                Err(e) => {
+                    state.failed.push_back((log, 1));
+                    state.ready.push_back(Err(e));
                 }
-                _ => {}
             }
+            return;
         }
 
-        let mut user_actions = vec![];
-        for request in requests_data {
-            let fyde_event = fyde_events.get(&request.request_id).unwrap().clone();
-            match fyde_event {
-                FydeEvents::Deposit(ev) => user_actions.push(UserAction::Deposit {
-                    tx_hash: request.tx_hash,
-                    block_number: request.block_number,
-                    timestamp: request.timestamp,
-                    request_id: request.request_id,
-                    user: request.requestor,
-                    asset_in: request.asset_in,
-                    amount_in: request.amount_in,
-                    keep_gov_rights: request.keep_gov_rights,
-                    minted_at_trsy_price: ev.trsy_price,
-                    usd_value_deposited: ev.usd_deposit_value,
-                    trsy_minted: ev.trsy_minted,
-                }),
-                FydeEvents::Withdraw(ev) => user_actions.push(UserAction::Withdraw {
-                    tx_hash: request.tx_hash,
-                    block_number: request.block_number,
-                    timestamp: request.timestamp,
-                    request_id: request.request_id,
-                    user: request.requestor,
-                    asset_out: request.asset_out,
-                    amount_out: request.amount_out,
-                    burned_at_trsy_price: ev.trsy_price,
-                    usd_value_withdrawn: ev.usd_withdraw_value,
-                    trsy_burned: ev.trsy_burned,
-                }),
-                FydeEvents::Swap(ev) => user_actions.push(UserAction::Swap {
-                    tx_hash: request.tx_hash,
-                    block_number: request.block_number,
-                    timestamp: request.timestamp,
-                    request_id: request.request_id,
-                    user: request.requestor,
-                    asset_in: request.asset_in[0],
-                    asset_out: request.asset_out[0],
-                    amount_in: request.amount_in[0],
-                    amount_out: ev.amount_out,
-                }),
-            }
+        let Some((request_id, fyde_event)) = parse_log::<LiquidVaultContractEvents>(log.clone())
+            .ok()
+            .and_then(FydeEvents::from_event)
+        else {
+            return;
+        };
+        if log.removed == Some(true) {
+            state.pending.reopen(request_id, block);
+            return;
         }
 
-        Ok(user_actions)
+        let failed_len = state.failed.len();
+        state.failed.retain(|(failed, _)| {
+            parse_log::<RelayerContractEvents>(failed.clone())
+                .ok()
+                .and_then(|event| relayer_request_id(&event))
+                != Some(request_id)
+        });
+        if state.failed.len() < failed_len {
+            state.ready.push_back(Err(DataError::missing(
+                "unreadable request of a LiquidVault event",
+                EventContext::request(request_id)
+                    .with_tx(meta.transaction_hash)
+                    .with_block(block),
+            )
+            .into()));
+            return;
+        }
+
+        match state.pending.join(request_id, fyde_event, block) {
+            Ok(Some(user_action)) => state.ready.push_back(Ok(user_action)),
+            Ok(None) => {}
+            Err(e) => state.ready.push_back(Err(e.into())),
+        }
+        state
+            .pending
+            .prune(block.saturating_sub(SUBSCRIPTION_REORG_WINDOW));
     }
 }
 
@@ -405,6 +924,9 @@ pub enum StakingUnstaking {
         assets: U256,
         shares: U256,
         block_number: u64,
+        block_hash: H256,
+        tx_hash: H256,
+        log_index: u64,
         timestamp: u64,
     },
     Unstaking {
@@ -414,51 +936,162 @@ pub enum StakingUnstaking {
         assets: U256,
         shares: U256,
         block_number: u64,
+        block_hash: H256,
+        tx_hash: H256,
+        log_index: u64,
         timestamp: u64,
     },
 }
 
-impl ProtocolHistory {
-    pub async fn get_staking_unstaking_history(&self) -> Result<Vec<StakingUnstaking>, FydeError> {
+impl StakingUnstaking {
+    pub fn tx_hash(&self) -> H256 {
+        match self {
+            StakingUnstaking::Staking { tx_hash, .. }
+            | StakingUnstaking::Unstaking { tx_hash, .. } => *tx_hash,
+        }
+    }
+
+    pub fn log_index(&self) -> u64 {
+        match self {
+            StakingUnstaking::Staking { log_index, .. }
+            | StakingUnstaking::Unstaking { log_index, .. } => *log_index,
+        }
+    }
+
+    pub fn block_number(&self) -> u64 {
+        match self {
+            StakingUnstaking::Staking { block_number, .. }
+            | StakingUnstaking::Unstaking { block_number, .. } => *block_number,
+        }
+    }
+
+    pub fn block_hash(&self) -> H256 {
+        match self {
+            StakingUnstaking::Staking { block_hash, .. }
+            | StakingUnstaking::Unstaking { block_hash, .. } => *block_hash,
+        }
+    }
+}
+
+/// Lock update or withdraw on the VoteEscrow
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct VeFydeLock {
+    pub tx_hash: H256,
+    pub log_index: u64,
+    pub block_number: u64,
+    pub block_hash: H256,
+    pub user: Address,
+    pub amount: u128,
+    /// None for a withdraw
+    pub expiry: Option<u64>,
+}
+
+impl VeFydeLock {
+    pub fn from_event(event: &VoteEscrowContractEvents, meta: &LogMeta) -> Self {
+        let (user, amount, expiry) = match event {
+            VoteEscrowContractEvents::UpdateLockFilter(ev) => {
+                (ev.user, ev.amount, Some(ev.expiry as u64))
+            }
+            VoteEscrowContractEvents::WithdrawFilter(ev) => (ev.user, ev.amount, None),
+        };
+        Self {
+            tx_hash: meta.transaction_hash,
+            log_index: meta.log_index.as_u64(),
+            block_number: meta.block_number.as_u64(),
+            block_hash: meta.block_hash,
+            user,
+            amount,
+            expiry,
+        }
+    }
+}
+
+/// Protocol fees collected, or management fee minted, by the LiquidVault
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct FeeEvent {
+    pub tx_hash: H256,
+    pub log_index: u64,
+    pub block_number: u64,
+    pub block_hash: H256,
+    /// None for the management fee
+    pub recipient: Option<Address>,
+    pub trsy_amount: U256,
+}
+
+impl FeeEvent {
+    pub fn from_event(event: &LiquidVaultContractEvents, meta: &LogMeta) -> Option<Self> {
+        let (recipient, trsy_amount) = match event {
+            LiquidVaultContractEvents::FeesCollectedFilter(ev) => {
+                (Some(ev.recipient), ev.trsy_fees_collected)
+            }
+            LiquidVaultContractEvents::ManagementFeeCollectedFilter(ev) => (None, ev.fee_to_mint),
+            _ => return None,
+        };
+        Some(Self {
+            tx_hash: meta.transaction_hash,
+            log_index: meta.log_index.as_u64(),
+            block_number: meta.block_number.as_u64(),
+            block_hash: meta.block_hash,
+            recipient,
+            trsy_amount,
+        })
+    }
+}
+
+impl<M: Middleware> ProtocolHistory<M> {
+    pub async fn get_staking_unstaking_history(
+        &self,
+    ) -> Result<Vec<StakingUnstaking>, FydeError<M>> {
+        let (staking_unstaking, _) = self
+            .get_staking_unstaking_history_with_cursor(0, None)
+            .await?;
+        Ok(staking_unstaking)
+    }
+
+    /// Staking history between two blocks, with the cursor to resume the scan from.
+    pub async fn get_staking_unstaking_history_with_cursor(
+        &self,
+        from_block: u64,
+        to_block: Option<u64>,
+    ) -> Result<(Vec<StakingUnstaking>, ScanCursor), FydeError<M>> {
         let mut staking_unstaking = vec![];
 
-        let events = self.strsy.events().from_block(0).query_with_meta().await?;
+        let filter = Filter::new().address(self.strsy.address());
+        let scan = self.scanner.scan(&filter, from_block, to_block).await?;
+        let events: Vec<(StrsyEvents, LogMeta)> = scan.decode();
+        self.block_cache
+            .prefetch(events.iter().map(|(_, meta)| meta.block_number.as_u64()))
+            .await?;
         for event in events {
             match event {
                 (StrsyEvents::DepositFilter(ev), meta) => {
-                    let tx = meta.transaction_hash;
-                    let tx_data = self.client.get_transaction(tx).await?.unwrap();
-                    let block = self
-                        .client
-                        .get_block(tx_data.block_number.unwrap())
-                        .await?
-                        .unwrap();
+                    let block_meta = self.get_meta_from_log(&meta).await?;
                     let staking = StakingUnstaking::Staking {
                         caller: ev.caller,
                         receiver: ev.owner,
                         assets: ev.assets,
                         shares: ev.shares,
-                        block_number: block.number.unwrap().as_u64(),
-                        timestamp: block.timestamp.as_u64(),
+                        block_number: block_meta.block_number as u64,
+                        block_hash: block_meta.block_hash,
+                        tx_hash: block_meta.tx_hash,
+                        log_index: block_meta.log_index,
+                        timestamp: block_meta.timestamp,
                     };
                     staking_unstaking.push(staking);
                 }
                 (StrsyEvents::WithdrawFilter(ev), meta) => {
-                    let tx = meta.transaction_hash;
-                    let tx_data = self.client.get_transaction(tx).await?.unwrap();
-                    let block = self
-                        .client
-                        .get_block(tx_data.block_number.unwrap())
-                        .await?
-                        .unwrap();
+                    let block_meta = self.get_meta_from_log(&meta).await?;
                     let unstaking = StakingUnstaking::Unstaking {
                         caller: ev.caller,
                         receiver: ev.receiver,
                         owner: ev.owner,
                         assets: ev.assets,
                         shares: ev.shares,
-                        block_number: block.number.unwrap().as_u64(),
-                        timestamp: block.timestamp.as_u64(),
+                        block_number: block_meta.block_number as u64,
+                        block_hash: block_meta.block_hash,
+                        tx_hash: block_meta.tx_hash,
+                        log_index: block_meta.log_index,
+                        timestamp: block_meta.timestamp,
                     };
                     staking_unstaking.push(unstaking);
                 }
@@ -466,6 +1099,281 @@ impl ProtocolHistory {
             }
         }
 
-        Ok(staking_unstaking)
+        Ok((staking_unstaking, scan.cursor))
+    }
+
+    /// VoteEscrow lock updates and withdraws between two blocks
+    pub async fn get_ve_fyde_locks_with_cursor(
+        &self,
+        from_block: u64,
+        to_block: Option<u64>,
+    ) -> Result<(Vec<VeFydeLock>, ScanCursor), FydeError<M>> {
+        let filter = Filter::new().address(self.vote_escrow.address());
+        let scan = self.scanner.scan(&filter, from_block, to_block).await?;
+        let events: Vec<(VoteEscrowContractEvents, LogMeta)> = scan.decode();
+        let locks = events
+            .iter()
+            .map(|(event, meta)| VeFydeLock::from_event(event, meta))
+            .collect();
+        Ok((locks, scan.cursor))
+    }
+
+    /// Protocol and management fees collected by the LiquidVault between two blocks
+    pub async fn get_fee_events_with_cursor(
+        &self,
+        from_block: u64,
+        to_block: Option<u64>,
+    ) -> Result<(Vec<FeeEvent>, ScanCursor), FydeError<M>> {
+        let filter = Filter::new().address(self.liquid_vault.address());
+        let scan = self.scanner.scan(&filter, from_block, to_block).await?;
+        let events: Vec<(LiquidVaultContractEvents, LogMeta)> = scan.decode();
+        let fees = events
+            .iter()
+            .filter_map(|(event, meta)| FeeEvent::from_event(event, meta))
+            .collect();
+        Ok((fees, scan.cursor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        abi::Tokenize,
+        contract::EthEvent,
+        providers::{MockProvider, Provider},
+        types::{Block, Transaction, U64},
+    };
+
+    fn swap_request(request_id: u32) -> RequestData {
+        RequestData {
+            kind: RequestKind::Swap,
+            tx_hash: H256::from_low_u64_be(1),
+            block_number: 100,
+            block_hash: H256::from_low_u64_be(100),
+            log_index: 2,
+            timestamp: 1_700_000_000,
+            request_id,
+            requestor: Address::from_low_u64_be(3),
+            asset_in: vec![Address::from_low_u64_be(4)],
+            asset_out: vec![Address::from_low_u64_be(5)],
+            amount_in: vec![U256::from(1_000)],
+            amount_out: vec![],
+            keep_gov_rights: false,
+        }
+    }
+
+    fn swap_event(request_id: u32) -> LiquidVaultContractEvents {
+        LiquidVaultContractEvents::SwapFilter(crate::liquid_vault_contract::SwapFilter {
+            request_id,
+            asset_out: Address::from_low_u64_be(5),
+            amount_out: U256::from(990),
+        })
+    }
+
+    #[test]
+    fn test_join_request_with_event() {
+        let (request_id, fyde_event) = FydeEvents::from_event(swap_event(7)).unwrap();
+        assert_eq!(request_id, 7);
+
+        match swap_request(7).join(fyde_event).unwrap() {
+            UserAction::Swap {
+                block_number,
+                amount_in,
+                amount_out,
+                ..
+            } => {
+                assert_eq!(block_number, 100);
+                assert_eq!(amount_in, U256::from(1_000));
+                assert_eq!(amount_out, U256::from(990));
+            }
+            _ => panic!("expected a swap"),
+        }
+    }
+
+    #[test]
+    fn test_pending_requests_carry_over() {
+        let mut pending = PendingRequests::default();
+        pending.insert(swap_request(7));
+        assert_eq!(pending.earliest_block(), Some(100));
+
+        // Processed before the scan range, or not a request of ours
+        let (_, fyde_event) = FydeEvents::from_event(swap_event(8)).unwrap();
+        assert!(pending.join(8, fyde_event, 105).unwrap().is_none());
+
+        let (_, fyde_event) = FydeEvents::from_event(swap_event(7)).unwrap();
+        assert!(pending.join(7, fyde_event, 105).unwrap().is_some());
+        assert!(pending.is_empty());
+
+        // The processing block is reorged out, the request waits for its event again
+        pending.rewind(103);
+        assert_eq!(pending.request_ids().collect::<Vec<_>>(), vec![7]);
+        // The request block itself is reorged out, it will be scanned again
+        pending.rewind(100);
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn test_join_rejects_mismatched_events() {
+        let (_, fyde_event) = FydeEvents::from_event(swap_event(8)).unwrap();
+        assert!(swap_request(7).join(fyde_event).is_err());
+
+        let (_, fyde_event) = FydeEvents::from_event(swap_event(7)).unwrap();
+        let mut request = swap_request(7);
+        request.kind = RequestKind::Deposit;
+        assert!(request.join(fyde_event).is_err());
+    }
+
+    fn log(
+        address: Address,
+        data: Vec<u8>,
+        topic: H256,
+        block: u64,
+        tx: u64,
+        removed: bool,
+    ) -> Log {
+        Log {
+            address,
+            topics: vec![topic],
+            data: data.into(),
+            block_hash: Some(H256::from_low_u64_be(block)),
+            block_number: Some(U64::from(block)),
+            transaction_hash: Some(H256::from_low_u64_be(tx)),
+            transaction_index: Some(U64::zero()),
+            log_index: Some(U256::zero()),
+            removed: Some(removed),
+            ..Default::default()
+        }
+    }
+
+    fn request_log(
+        history: &ProtocolHistory<Provider<MockProvider>>,
+        request_id: u32,
+        block: u64,
+        removed: bool,
+    ) -> Log {
+        let event = crate::relayer_contract::SwapFilter {
+            request_id,
+            request: crate::relayer_contract::RequestData {
+                id: request_id,
+                requestor: Address::from_low_u64_be(3),
+                asset_in: vec![Address::from_low_u64_be(4)],
+                amount_in: vec![U256::from(1_000)],
+                asset_out: vec![Address::from_low_u64_be(5)],
+                amount_out: vec![],
+                keep_gov_rights: false,
+                slippage_checker: U256::zero(),
+            },
+        };
+        log(
+            history.relayer.address(),
+            ethers::abi::encode(&event.into_tokens()),
+            crate::relayer_contract::SwapFilter::signature(),
+            block,
+            1_000 + request_id as u64,
+            removed,
+        )
+    }
+
+    fn processing_log(
+        history: &ProtocolHistory<Provider<MockProvider>>,
+        request_id: u32,
+        block: u64,
+        removed: bool,
+    ) -> Log {
+        let LiquidVaultContractEvents::SwapFilter(event) = swap_event(request_id) else {
+            unreachable!()
+        };
+        log(
+            history.liquid_vault.address(),
+            ethers::abi::encode(&event.into_tokens()),
+            crate::liquid_vault_contract::SwapFilter::signature(),
+            block,
+            2_000 + request_id as u64,
+            removed,
+        )
+    }
+
+    fn block(number: u64, request_id: u32) -> Block<Transaction> {
+        Block {
+            number: Some(U64::from(number)),
+            hash: Some(H256::from_low_u64_be(number)),
+            timestamp: U256::from(1_700_000_000 + number),
+            transactions: vec![Transaction {
+                hash: H256::from_low_u64_be(1_000 + request_id as u64),
+                from: Address::from_low_u64_be(3),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn mocked_history() -> (ProtocolHistory<Provider<MockProvider>>, MockProvider) {
+        let (provider, mock) = Provider::mocked();
+        let provider = Arc::new(provider);
+        let history = ProtocolHistory::new(provider.clone(), Chain::Mainnet)
+            .with_block_cache(BlockMetaCache::new(provider).chain_id(1));
+        (history, mock)
+    }
+
+    #[tokio::test]
+    async fn test_subscription_buffers_requests_across_blocks() {
+        let (history, mock) = mocked_history();
+        let logs = vec![
+            // The first read of request 7 fails, it is retried on the next log
+            request_log(&history, 7, 100, false),
+            request_log(&history, 8, 101, false),
+            processing_log(&history, 7, 103, false),
+            // Reorg of the processing block, request 7 waits for its event again
+            processing_log(&history, 7, 103, true),
+            processing_log(&history, 7, 104, false),
+            // Reorg of a request block, its later event is not joined
+            request_log(&history, 8, 101, true),
+            processing_log(&history, 8, 105, false),
+        ];
+        let stream = history.user_actions_from_logs(stream::iter(logs));
+        futures::pin_mut!(stream);
+
+        assert!(stream.next().await.unwrap().is_err());
+        // Responses are served last in, first out
+        mock.push(block(101, 8)).unwrap();
+        mock.push(block(100, 7)).unwrap();
+
+        let user_actions: Vec<UserAction> = stream.map(|item| item.unwrap()).collect().await;
+        assert_eq!(user_actions.len(), 2);
+        for user_action in user_actions {
+            match user_action {
+                UserAction::Swap {
+                    block_number,
+                    user,
+                    amount_out,
+                    ..
+                } => {
+                    assert_eq!(block_number, 100);
+                    assert_eq!(user, Address::from_low_u64_be(3));
+                    assert_eq!(amount_out, U256::from(990));
+                }
+                _ => panic!("expected a swap"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscription_surfaces_unreadable_requests() {
+        let (history, _mock) = mocked_history();
+        let logs = vec![
+            request_log(&history, 9, 100, false),
+            processing_log(&history, 9, 101, false),
+        ];
+        let items: Vec<_> = history
+            .user_actions_from_logs(stream::iter(logs))
+            .collect()
+            .await;
+
+        assert_eq!(items.len(), 2);
+        assert!(matches!(
+            items[1],
+            Err(FydeError::DataError(DataError::MissingData { .. }))
+        ));
     }
 }
@@ -0,0 +1,644 @@
+use ethers::{
+    contract::{parse_log, LogMeta},
+    providers::{Middleware, PubsubClient},
+    types::{Address, BlockNumber, Filter, Log, H256},
+};
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
+
+use crate::{
+    block_cache::{BlockMetaCache, WithBlockCache},
+    errors::{DataError, EventContext, FydeError},
+    log_scanner::{LogScanner, ScanCursor, ScanResult, WithScanner},
+    utils::block_timestamp,
+    AddressList, Chain, LiquidVaultContract, LiquidVaultContractEvents, RelayerContract,
+    RelayerContractEvents,
+};
+
+/// Quarantine history of the relayer assets, and alerts on new quarantines
+pub struct Quarantine<M: Middleware> {
+    client: Arc<M>,
+    relayer: RelayerContract<M>,
+    liquid_vault: LiquidVaultContract<M>,
+    scanner: LogScanner<M>,
+    block_cache: BlockMetaCache<M>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct QuarantinePeriod {
+    pub asset: Address,
+    pub entered_at: u64,
+    pub entered_block: u64,
+    /// Latest expiration set, a new AddedToQuarantine during the period extends it
+    pub expiration_time: u64,
+    /// Time of the RemovedFromQuarantine event, or the expiration once passed
+    pub left_at: Option<u64>,
+    /// Block of the RemovedFromQuarantine event
+    pub left_block: Option<u64>,
+    /// In seconds, up to the end of the history while ongoing
+    pub duration: u64,
+    /// Requests involving the asset and pending during the period
+    pub blocked_requests: Vec<BlockedRequest>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct BlockedRequest {
+    pub request_id: u32,
+    pub requested_at: u64,
+    pub block_number: u64,
+    /// Time of the LiquidVault event processing the request
+    pub processed_at: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct QuarantineAlert {
+    pub asset: Address,
+    pub expiration_time: u64,
+    pub block_number: u64,
+    pub tx_hash: H256,
+    pub timestamp: u64,
+}
+
+/// Relayer and LiquidVault events relevant to the timeline
+#[derive(Debug, Clone, PartialEq)]
+enum TimelineEvent {
+    Added {
+        asset: Address,
+        expiration_time: u64,
+    },
+    Removed {
+        asset: Address,
+    },
+    Requested {
+        request_id: u32,
+        assets: BTreeSet<Address>,
+    },
+    Processed {
+        request_id: u32,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct TimelineLog {
+    block_number: u64,
+    timestamp: u64,
+    event: TimelineEvent,
+}
+
+/// Expiration of an AddedToQuarantine event as a timestamp
+fn expiration_time(expiration_time: u128, meta: &LogMeta) -> Result<u64, DataError> {
+    u64::try_from(expiration_time).map_err(|_| {
+        DataError::out_of_range(
+            "quarantine expiration time",
+            EventContext::tx(meta.transaction_hash).with_block(meta.block_number.as_u64()),
+        )
+    })
+}
+
+impl TimelineEvent {
+    fn from_relayer(
+        event: RelayerContractEvents,
+        meta: &LogMeta,
+    ) -> Result<Option<Self>, DataError> {
+        let request = match event {
+            RelayerContractEvents::AddedToQuarantineFilter(ev) => {
+                return Ok(Some(TimelineEvent::Added {
+                    asset: ev.asset,
+                    expiration_time: expiration_time(ev.expiration_time, meta)?,
+                }))
+            }
+            RelayerContractEvents::RemovedFromQuarantineFilter(ev) => {
+                return Ok(Some(TimelineEvent::Removed { asset: ev.asset }))
+            }
+            RelayerContractEvents::DepositFilter(ev) => (ev.request_id, ev.request),
+            RelayerContractEvents::WithdrawFilter(ev) => (ev.request_id, ev.request),
+            RelayerContractEvents::SwapFilter(ev) => (ev.request_id, ev.request),
+            _ => return Ok(None),
+        };
+        let (request_id, request) = request;
+        Ok(Some(TimelineEvent::Requested {
+            request_id,
+            assets: request
+                .asset_in
+                .into_iter()
+                .chain(request.asset_out)
+                .collect(),
+        }))
+    }
+
+    fn from_liquid_vault(event: LiquidVaultContractEvents) -> Option<Self> {
+        let request_id = match event {
+            LiquidVaultContractEvents::DepositFilter(ev) => ev.request_id,
+            LiquidVaultContractEvents::WithdrawFilter(ev) => ev.request_id,
+            LiquidVaultContractEvents::SwapFilter(ev) => ev.request_id,
+            _ => return None,
+        };
+        Some(TimelineEvent::Processed { request_id })
+    }
+}
+
+/// Build the quarantine periods from chronological logs, ongoing periods running until `now`
+fn build_timeline(logs: Vec<TimelineLog>, now: u64) -> Vec<QuarantinePeriod> {
+    let mut periods: Vec<QuarantinePeriod> = vec![];
+    // Index in `periods` of the ongoing quarantine of each asset
+    let mut open: HashMap<Address, usize> = HashMap::new();
+    let mut pending: HashMap<u32, (BTreeSet<Address>, u64, u64)> = HashMap::new();
+
+    fn close_expired(
+        periods: &mut [QuarantinePeriod],
+        open: &mut HashMap<Address, usize>,
+        timestamp: u64,
+    ) {
+        open.retain(|_, index| {
+            let period = &mut periods[*index];
+            if period.expiration_time > timestamp {
+                return true;
+            }
+            period.left_at = Some(period.expiration_time);
+            false
+        });
+    }
+
+    for log in logs {
+        close_expired(&mut periods, &mut open, log.timestamp);
+        match log.event {
+            TimelineEvent::Added {
+                asset,
+                expiration_time,
+            } => match open.get(&asset) {
+                Some(index) => periods[*index].expiration_time = expiration_time,
+                None => {
+                    let blocked_requests = pending
+                        .iter()
+                        .filter(|(_, (assets, _, _))| assets.contains(&asset))
+                        .map(
+                            |(request_id, (_, requested_at, block_number))| BlockedRequest {
+                                request_id: *request_id,
+                                requested_at: *requested_at,
+                                block_number: *block_number,
+                                processed_at: None,
+                            },
+                        )
+                        .collect::<Vec<_>>();
+                    open.insert(asset, periods.len());
+                    periods.push(QuarantinePeriod {
+                        asset,
+                        entered_at: log.timestamp,
+                        entered_block: log.block_number,
+                        expiration_time,
+                        left_at: None,
+                        left_block: None,
+                        duration: 0,
+                        blocked_requests,
+                    });
+                }
+            },
+            TimelineEvent::Removed { asset } => {
+                if let Some(index) = open.remove(&asset) {
+                    periods[index].left_at = Some(log.timestamp);
+                    periods[index].left_block = Some(log.block_number);
+                }
+            }
+            TimelineEvent::Requested { request_id, assets } => {
+                for index in assets.iter().filter_map(|asset| open.get(asset)) {
+                    periods[*index].blocked_requests.push(BlockedRequest {
+                        request_id,
+                        requested_at: log.timestamp,
+                        block_number: log.block_number,
+                        processed_at: None,
+                    });
+                }
+                pending.insert(request_id, (assets, log.timestamp, log.block_number));
+            }
+            TimelineEvent::Processed { request_id } => {
+                if pending.remove(&request_id).is_none() {
+                    continue;
+                }
+                periods
+                    .iter_mut()
+                    .flat_map(|period| period.blocked_requests.iter_mut())
+                    .filter(|blocked| blocked.request_id == request_id)
+                    .for_each(|blocked| blocked.processed_at = Some(log.timestamp));
+            }
+        }
+    }
+    close_expired(&mut periods, &mut open, now);
+
+    for period in periods.iter_mut() {
+        period.duration = period
+            .left_at
+            .unwrap_or(now)
+            .saturating_sub(period.entered_at);
+        period
+            .blocked_requests
+            .sort_by_key(|blocked| blocked.request_id);
+    }
+    periods
+}
+
+impl<M: Middleware> WithScanner<M> for Quarantine<M> {
+    fn scanner_mut(&mut self) -> &mut LogScanner<M> {
+        &mut self.scanner
+    }
+}
+
+impl<M: Middleware> WithBlockCache<M> for Quarantine<M> {
+    fn block_cache_mut(&mut self) -> &mut BlockMetaCache<M> {
+        &mut self.block_cache
+    }
+}
+
+impl<M: Middleware> Quarantine<M> {
+    pub async fn new(client: Arc<M>, chain: Chain) -> Result<Self, FydeError<M>> {
+        let address_list: AddressList = AddressList::new(&chain);
+        Ok(Self {
+            relayer: RelayerContract::new(address_list.relayer, client.clone()),
+            liquid_vault: LiquidVaultContract::new(address_list.liquid_vault, client.clone()),
+            scanner: LogScanner::new(client.clone()),
+            block_cache: BlockMetaCache::new(client.clone()),
+            client,
+        })
+    }
+
+    /// Timeline events of the relayer and LiquidVault logs of a scan, in chronological order
+    fn timeline_events(
+        &self,
+        scan: &ScanResult,
+    ) -> Result<Vec<(TimelineEvent, LogMeta)>, DataError> {
+        let mut events = vec![];
+        for (event, meta) in scan.decode_from::<RelayerContractEvents>(self.relayer.address()) {
+            if let Some(event) = TimelineEvent::from_relayer(event, &meta)? {
+                events.push((event, meta));
+            }
+        }
+        for (event, meta) in
+            scan.decode_from::<LiquidVaultContractEvents>(self.liquid_vault.address())
+        {
+            if let Some(event) = TimelineEvent::from_liquid_vault(event) {
+                events.push((event, meta));
+            }
+        }
+        events.sort_by_key(|(_, meta)| (meta.block_number, meta.log_index));
+        Ok(events)
+    }
+
+    /// Quarantine periods of every asset from the events between two blocks, in order of
+    /// entry. Periods still ongoing at `to_block` have no `left_at`.
+    pub async fn get_timeline(
+        &self,
+        from_block: u64,
+        to_block: Option<u64>,
+    ) -> Result<(Vec<QuarantinePeriod>, ScanCursor), FydeError<M>> {
+        let filter =
+            Filter::new().address(vec![self.relayer.address(), self.liquid_vault.address()]);
+        let scan = self.scanner.scan(&filter, from_block, to_block).await?;
+
+        let events = self.timeline_events(&scan)?;
+        self.block_cache
+            .prefetch(events.iter().map(|(_, meta)| meta.block_number.as_u64()))
+            .await?;
+
+        let mut logs = vec![];
+        for (event, meta) in events {
+            let block_number = meta.block_number.as_u64();
+            logs.push(TimelineLog {
+                block_number,
+                timestamp: self.block_cache.get_for_log(&meta).await?.timestamp,
+                event,
+            });
+        }
+
+        let end_block = match to_block {
+            Some(to_block) => BlockNumber::Number(to_block.into()),
+            None => BlockNumber::Latest,
+        };
+        let now = block_timestamp(self.client.as_ref(), end_block).await?;
+
+        Ok((build_timeline(logs, now), scan.cursor))
+    }
+
+    /// Quarantine periods of `asset` between two blocks
+    pub async fn get_asset_timeline(
+        &self,
+        asset: Address,
+        from_block: u64,
+        to_block: Option<u64>,
+    ) -> Result<Vec<QuarantinePeriod>, FydeError<M>> {
+        let (periods, _) = self.get_timeline(from_block, to_block).await?;
+        Ok(periods
+            .into_iter()
+            .filter(|period| period.asset == asset)
+            .collect())
+    }
+
+    /// Call `on_quarantine` for every AddedToQuarantine event, from the latest block on.
+    /// An event that cannot be read is passed as an error and the watch goes on. Returns
+    /// when the subscription ends; logs removed by a reorg are skipped.
+    pub async fn watch(
+        &self,
+        on_quarantine: impl FnMut(Result<QuarantineAlert, FydeError<M>>),
+    ) -> Result<(), FydeError<M>>
+    where
+        M::Provider: PubsubClient,
+    {
+        let filter = Filter::new().address(self.relayer.address());
+        let logs = self
+            .client
+            .subscribe_logs(&filter)
+            .await
+            .map_err(FydeError::MiddlewareError)?;
+
+        self.alerts_from_logs(logs, on_quarantine).await;
+        Ok(())
+    }
+
+    async fn alerts_from_logs<S>(
+        &self,
+        mut logs: S,
+        mut on_quarantine: impl FnMut(Result<QuarantineAlert, FydeError<M>>),
+    ) where
+        S: Stream<Item = Log> + Unpin,
+    {
+        while let Some(log) = logs.next().await {
+            if let Some(alert) = self.alert_from_log(log).await.transpose() {
+                on_quarantine(alert);
+            }
+        }
+    }
+
+    async fn alert_from_log(&self, log: Log) -> Result<Option<QuarantineAlert>, FydeError<M>> {
+        if log.removed == Some(true) {
+            return Ok(None);
+        }
+        let meta = LogMeta::from(&log);
+        let Ok(RelayerContractEvents::AddedToQuarantineFilter(ev)) = parse_log(log) else {
+            return Ok(None);
+        };
+        Ok(Some(QuarantineAlert {
+            asset: ev.asset,
+            expiration_time: expiration_time(ev.expiration_time, &meta)?,
+            block_number: meta.block_number.as_u64(),
+            tx_hash: meta.transaction_hash,
+            timestamp: self.block_cache.get_for_log(&meta).await?.timestamp,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        liquid_vault_contract,
+        relayer_contract::{self, AddedToQuarantineFilter},
+    };
+    use ethers::{
+        abi::Tokenize,
+        contract::EthEvent,
+        providers::{MockProvider, Provider},
+        types::{Block, Transaction, U256, U64},
+    };
+    use futures::stream;
+
+    fn log(block_number: u64, event: TimelineEvent) -> TimelineLog {
+        TimelineLog {
+            block_number,
+            timestamp: block_number * 10,
+            event,
+        }
+    }
+
+    #[test]
+    fn test_build_timeline() {
+        let (weth, usdc) = (Address::random(), Address::random());
+        let requested = |request_id, assets: &[Address]| TimelineEvent::Requested {
+            request_id,
+            assets: assets.iter().copied().collect(),
+        };
+        let logs = vec![
+            log(1, requested(1, &[weth, usdc])),
+            log(2, requested(2, &[usdc])),
+            log(
+                3,
+                TimelineEvent::Added {
+                    asset: weth,
+                    expiration_time: 1_000,
+                },
+            ),
+            log(4, requested(3, &[weth])),
+            log(5, TimelineEvent::Processed { request_id: 2 }),
+            log(6, TimelineEvent::Removed { asset: weth }),
+            log(7, TimelineEvent::Processed { request_id: 1 }),
+            log(
+                8,
+                TimelineEvent::Added {
+                    asset: usdc,
+                    expiration_time: 100,
+                },
+            ),
+            log(
+                20,
+                TimelineEvent::Added {
+                    asset: weth,
+                    expiration_time: 300,
+                },
+            ),
+        ];
+
+        let periods = build_timeline(logs, 250);
+        assert_eq!(periods.len(), 3);
+
+        let weth_period = &periods[0];
+        assert_eq!((weth_period.entered_at, weth_period.entered_block), (30, 3));
+        assert_eq!(
+            (weth_period.left_at, weth_period.left_block),
+            (Some(60), Some(6))
+        );
+        assert_eq!(weth_period.duration, 30);
+        let blocked: Vec<(u32, Option<u64>)> = weth_period
+            .blocked_requests
+            .iter()
+            .map(|blocked| (blocked.request_id, blocked.processed_at))
+            .collect();
+        // Request 2 does not involve WETH, request 3 is still pending
+        assert_eq!(blocked, vec![(1, Some(70)), (3, None)]);
+
+        // Expired without a RemovedFromQuarantine event
+        let usdc_period = &periods[1];
+        assert_eq!(
+            (usdc_period.left_at, usdc_period.left_block),
+            (Some(100), None)
+        );
+        assert_eq!(usdc_period.duration, 20);
+        assert!(usdc_period.blocked_requests.is_empty());
+
+        // Ongoing
+        assert_eq!(periods[2].left_at, None);
+        assert_eq!(periods[2].duration, 50);
+    }
+
+    async fn mocked_quarantine() -> (Quarantine<Provider<MockProvider>>, MockProvider) {
+        let (provider, mock) = Provider::mocked();
+        let provider = Arc::new(provider);
+        let quarantine = Quarantine::new(provider.clone(), Chain::Mainnet)
+            .await
+            .unwrap()
+            .with_block_cache(BlockMetaCache::new(provider).chain_id(1));
+        (quarantine, mock)
+    }
+
+    fn event_log<E: EthEvent + Tokenize>(
+        address: Address,
+        event: E,
+        block: u64,
+        log_index: u64,
+        removed: bool,
+    ) -> Log {
+        Log {
+            address,
+            topics: vec![E::signature()],
+            data: ethers::abi::encode(&event.into_tokens()).into(),
+            block_hash: Some(H256::from_low_u64_be(block)),
+            block_number: Some(U64::from(block)),
+            transaction_hash: Some(H256::from_low_u64_be(1_000 + log_index)),
+            transaction_index: Some(U64::zero()),
+            log_index: Some(U256::from(log_index)),
+            removed: Some(removed),
+            ..Default::default()
+        }
+    }
+
+    fn block(number: u64) -> Block<Transaction> {
+        Block {
+            number: Some(U64::from(number)),
+            hash: Some(H256::from_low_u64_be(number)),
+            timestamp: U256::from(1_700_000_000 + number),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_watch_reports_errors_and_goes_on() {
+        let (quarantine, mock) = mocked_quarantine().await;
+        let relayer = quarantine.relayer.address();
+        let added = |asset: u64, expiration_time: u128| AddedToQuarantineFilter {
+            asset: Address::from_low_u64_be(asset),
+            expiration_time,
+        };
+        let logs = vec![
+            event_log(relayer, added(1, 2_000_000_000), 101, 0, false),
+            // Expiration beyond a u64 timestamp
+            event_log(relayer, added(2, u128::MAX), 102, 1, false),
+            // Reorged out
+            event_log(relayer, added(3, 2_000_000_000), 102, 2, true),
+            // Block read fails, the mock has no response left
+            event_log(relayer, added(4, 2_000_000_000), 103, 3, false),
+            // Block 101 is cached
+            event_log(relayer, added(5, 2_100_000_000), 101, 4, false),
+        ];
+        mock.push(block(101)).unwrap();
+
+        let mut alerts = vec![];
+        quarantine
+            .alerts_from_logs(stream::iter(logs), |alert| alerts.push(alert))
+            .await;
+
+        assert_eq!(alerts.len(), 4);
+        let first = alerts[0].as_ref().unwrap();
+        assert_eq!(first.asset, Address::from_low_u64_be(1));
+        assert_eq!(first.expiration_time, 2_000_000_000);
+        assert_eq!((first.block_number, first.timestamp), (101, 1_700_000_101));
+        assert!(matches!(
+            alerts[1],
+            Err(FydeError::DataError(DataError::OutOfRange { .. }))
+        ));
+        assert!(alerts[2].is_err());
+        let last = alerts[3].as_ref().unwrap();
+        assert_eq!(last.asset, Address::from_low_u64_be(5));
+        assert_eq!(last.tx_hash, H256::from_low_u64_be(1_004));
+        assert_eq!(last.timestamp, 1_700_000_101);
+    }
+
+    #[tokio::test]
+    async fn test_timeline_events_decode_liquid_vault_logs() {
+        let (quarantine, _mock) = mocked_quarantine().await;
+        let relayer = quarantine.relayer.address();
+        let liquid_vault = quarantine.liquid_vault.address();
+        let asset = Address::from_low_u64_be(4);
+        let request = |request_id| relayer_contract::SwapFilter {
+            request_id,
+            request: relayer_contract::RequestData {
+                id: request_id,
+                requestor: Address::from_low_u64_be(3),
+                asset_in: vec![asset],
+                amount_in: vec![U256::from(1_000)],
+                asset_out: vec![Address::from_low_u64_be(5)],
+                amount_out: vec![],
+                keep_gov_rights: false,
+                slippage_checker: U256::zero(),
+            },
+        };
+        let swap = liquid_vault_contract::SwapFilter {
+            request_id: 7,
+            asset_out: Address::from_low_u64_be(5),
+            amount_out: U256::from(990),
+        };
+        let deposit = liquid_vault_contract::DepositFilter {
+            request_id: 8,
+            trsy_price: U256::exp10(18),
+            usd_deposit_value: U256::exp10(21),
+            trsy_minted: U256::exp10(21),
+        };
+        let scan = ScanResult {
+            logs: vec![
+                event_log(liquid_vault, deposit, 12, 4, false),
+                event_log(liquid_vault, swap.clone(), 11, 3, false),
+                // Same event emitted by another contract
+                event_log(Address::random(), swap, 11, 2, false),
+                event_log(relayer, request(7), 10, 1, false),
+                event_log(
+                    relayer,
+                    AddedToQuarantineFilter {
+                        asset,
+                        expiration_time: 2_000_000_000,
+                    },
+                    10,
+                    0,
+                    false,
+                ),
+            ],
+            cursor: ScanCursor { next_block: 13 },
+        };
+
+        let events: Vec<(TimelineEvent, u64)> = quarantine
+            .timeline_events(&scan)
+            .unwrap()
+            .into_iter()
+            .map(|(event, meta)| (event, meta.block_number.as_u64()))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (
+                    TimelineEvent::Added {
+                        asset,
+                        expiration_time: 2_000_000_000,
+                    },
+                    10
+                ),
+                (
+                    TimelineEvent::Requested {
+                        request_id: 7,
+                        assets: [asset, Address::from_low_u64_be(5)].into_iter().collect(),
+                    },
+                    10
+                ),
+                (TimelineEvent::Processed { request_id: 7 }, 11),
+                (TimelineEvent::Processed { request_id: 8 }, 12),
+            ]
+        );
+    }
+}
@@ -0,0 +1,367 @@
+use ethers::{
+    providers::Middleware,
+    types::{Address, I256, U256},
+};
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::{
+    errors::FydeError,
+    liquid_vault_contract::{ProcessParam, RequestData},
+    AddressList, Chain, LiquidVaultContract,
+};
+
+pub struct Quoter<M: Middleware> {
+    liquid_vault: LiquidVaultContract<M>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct AssetQuote {
+    pub asset: Address,
+    pub amount: U256,
+    pub target_concentration: U256,
+    pub current_concentration: U256,
+    pub usd_value: U256,
+    pub taxable_amount: U256,
+    pub tax_in_usd: U256,
+    pub trsy_before_tax: U256,
+    pub trsy_after_tax: U256,
+}
+
+impl From<(Address, U256, ProcessParam)> for AssetQuote {
+    fn from(data: (Address, U256, ProcessParam)) -> Self {
+        Self {
+            asset: data.0,
+            amount: data.1,
+            target_concentration: data.2.target_conc,
+            current_concentration: data.2.current_conc,
+            usd_value: data.2.usd_value,
+            taxable_amount: data.2.taxable_amount,
+            tax_in_usd: data.2.tax_in_usd,
+            trsy_before_tax: data.2.shares_before_tax,
+            trsy_after_tax: data.2.shares_after_tax,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Quote {
+    /// TRSY minted on deposit or burned on withdraw, tax included
+    pub trsy_amount: U256,
+    pub usd_value: U256,
+    pub tax_in_trsy: U256,
+    /// USD paid per TRSY (18 decimals), tax included
+    pub effective_price: U256,
+    pub assets: Vec<AssetQuote>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SwapQuote {
+    pub asset_in: Address,
+    pub amount_in: U256,
+    pub asset_out: Address,
+    pub amount_out: U256,
+    /// Tax (positive) or incentive (negative) in USD applied to the swap
+    pub tax_in_usd: I256,
+}
+
+impl<M: Middleware> Quoter<M> {
+    pub fn new(provider: Arc<M>, chain: Chain) -> Self {
+        let address_list: AddressList = AddressList::new(&chain);
+
+        Self {
+            liquid_vault: LiquidVaultContract::new(address_list.liquid_vault, provider),
+        }
+    }
+
+    async fn protocol_aum(&self, protocol_aum: Option<U256>) -> Result<U256, FydeError<M>> {
+        match protocol_aum {
+            Some(aum) => Ok(aum),
+            None => Ok(self.liquid_vault.compute_protocol_aum().call().await?),
+        }
+    }
+
+    /// Quote a deposit of `assets` and `amounts`. The protocol AUM defaults to the current one.
+    pub async fn quote_deposit(
+        &self,
+        assets: &[Address],
+        amounts: &[U256],
+        protocol_aum: Option<U256>,
+    ) -> Result<Quote, FydeError<M>> {
+        if assets.len() != amounts.len() {
+            return Err(FydeError::InvalidRequest(String::from(
+                "Assets and amounts length mismatch",
+            )));
+        }
+        let protocol_aum = self.protocol_aum(protocol_aum).await?;
+        let request = RequestData {
+            asset_in: assets.to_vec(),
+            amount_in: amounts.to_vec(),
+            ..Default::default()
+        };
+
+        let (process_param, shares_to_mint, tax_in_trsy, total_usd_deposit) = self
+            .liquid_vault
+            .get_process_param_deposit(request, protocol_aum)
+            .call()
+            .await?;
+
+        Ok(Quote {
+            trsy_amount: shares_to_mint,
+            usd_value: total_usd_deposit,
+            tax_in_trsy,
+            effective_price: effective_price(total_usd_deposit, shares_to_mint),
+            assets: assets
+                .iter()
+                .zip(amounts)
+                .zip(process_param)
+                .map(|((asset, amount), param)| AssetQuote::from((*asset, *amount, param)))
+                .collect(),
+        })
+    }
+
+    /// Quote a withdraw of `assets` and `amounts`. The protocol AUM defaults to the current one.
+    pub async fn quote_withdraw(
+        &self,
+        assets: &[Address],
+        amounts: &[U256],
+        protocol_aum: Option<U256>,
+    ) -> Result<Quote, FydeError<M>> {
+        if assets.len() != amounts.len() {
+            return Err(FydeError::InvalidRequest(String::from(
+                "Assets and amounts length mismatch",
+            )));
+        }
+        let protocol_aum = self.protocol_aum(protocol_aum).await?;
+        let request = RequestData {
+            asset_out: assets.to_vec(),
+            amount_out: amounts.to_vec(),
+            ..Default::default()
+        };
+
+        let (process_param, total_shares_to_burn, _, tax_in_trsy, total_usd_withdraw) = self
+            .liquid_vault
+            .get_process_param_withdraw(request, protocol_aum)
+            .call()
+            .await?;
+
+        Ok(Quote {
+            trsy_amount: total_shares_to_burn,
+            usd_value: total_usd_withdraw,
+            tax_in_trsy,
+            effective_price: effective_price(total_usd_withdraw, total_shares_to_burn),
+            assets: assets
+                .iter()
+                .zip(amounts)
+                .zip(process_param)
+                .map(|((asset, amount), param)| AssetQuote::from((*asset, *amount, param)))
+                .collect(),
+        })
+    }
+
+    pub async fn quote_swap(
+        &self,
+        asset_in: Address,
+        amount_in: U256,
+        asset_out: Address,
+        protocol_aum: Option<U256>,
+    ) -> Result<SwapQuote, FydeError<M>> {
+        let protocol_aum = self.protocol_aum(protocol_aum).await?;
+        let (amount_out, tax_in_usd) = self
+            .liquid_vault
+            .get_swap_amount_out(asset_in, amount_in, asset_out, protocol_aum)
+            .call()
+            .await?;
+
+        Ok(SwapQuote {
+            asset_in,
+            amount_in,
+            asset_out,
+            amount_out,
+            tax_in_usd,
+        })
+    }
+}
+
+pub(crate) fn effective_price(usd_value: U256, trsy_amount: U256) -> U256 {
+    if trsy_amount.is_zero() {
+        return U256::zero();
+    }
+    usd_value * U256::exp10(18) / trsy_amount
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        abi::{Token, Tokenizable},
+        providers::{MockProvider, Provider},
+        types::Bytes,
+    };
+
+    fn mocked_quoter() -> (Quoter<Provider<MockProvider>>, MockProvider) {
+        let (provider, mock) = Provider::mocked();
+        (Quoter::new(Arc::new(provider), Chain::Mainnet), mock)
+    }
+
+    /// Queue the `eth_call` results, in call order
+    fn push_calls(mock: &MockProvider, results: Vec<Vec<Token>>) {
+        // Responses are served last in, first out
+        for result in results.into_iter().rev() {
+            mock.push::<Bytes, _>(Bytes::from(ethers::abi::encode(&result)))
+                .unwrap();
+        }
+    }
+
+    fn process_param(seed: u64) -> ProcessParam {
+        ProcessParam {
+            target_conc: U256::from(seed),
+            current_conc: U256::from(seed + 1),
+            usd_value: U256::from(seed + 2),
+            taxable_amount: U256::from(seed + 3),
+            tax_in_usd: U256::from(seed + 4),
+            shares_before_tax: U256::from(seed + 5),
+            shares_after_tax: U256::from(seed + 6),
+        }
+    }
+
+    fn process_params(params: &[ProcessParam]) -> Token {
+        Token::Array(
+            params
+                .iter()
+                .cloned()
+                .map(Tokenizable::into_token)
+                .collect(),
+        )
+    }
+
+    fn assert_asset_quote(quote: &AssetQuote, asset: Address, amount: U256, param: &ProcessParam) {
+        assert_eq!(quote.asset, asset);
+        assert_eq!(quote.amount, amount);
+        assert_eq!(quote.target_concentration, param.target_conc);
+        assert_eq!(quote.current_concentration, param.current_conc);
+        assert_eq!(quote.usd_value, param.usd_value);
+        assert_eq!(quote.taxable_amount, param.taxable_amount);
+        assert_eq!(quote.tax_in_usd, param.tax_in_usd);
+        assert_eq!(quote.trsy_before_tax, param.shares_before_tax);
+        assert_eq!(quote.trsy_after_tax, param.shares_after_tax);
+    }
+
+    #[tokio::test]
+    async fn test_quote_deposit() {
+        let (quoter, mock) = mocked_quoter();
+        let assets = [Address::from_low_u64_be(1), Address::from_low_u64_be(2)];
+        let amounts = [U256::from(10), U256::from(20)];
+        let params = [process_param(100), process_param(200)];
+        let usd_value = U256::exp10(18) * 3_000;
+        let trsy_minted = U256::exp10(18) * 2_000;
+
+        // Protocol AUM first, as none is given
+        push_calls(
+            &mock,
+            vec![
+                vec![Token::Uint(U256::exp10(24))],
+                vec![
+                    process_params(&params),
+                    Token::Uint(trsy_minted),
+                    Token::Uint(U256::from(5)),
+                    Token::Uint(usd_value),
+                ],
+            ],
+        );
+        let quote = quoter.quote_deposit(&assets, &amounts, None).await.unwrap();
+
+        assert_eq!(quote.trsy_amount, trsy_minted);
+        assert_eq!(quote.usd_value, usd_value);
+        assert_eq!(quote.tax_in_trsy, U256::from(5));
+        assert_eq!(quote.effective_price, U256::exp10(18) * 3 / 2);
+        assert_eq!(quote.assets.len(), 2);
+        for (i, asset_quote) in quote.assets.iter().enumerate() {
+            assert_asset_quote(asset_quote, assets[i], amounts[i], &params[i]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_quote_withdraw() {
+        let (quoter, mock) = mocked_quoter();
+        let assets = [Address::from_low_u64_be(1)];
+        let amounts = [U256::from(10)];
+        let params = [process_param(100)];
+        let usd_value = U256::exp10(18) * 1_000;
+        let trsy_burned = U256::exp10(18) * 1_000;
+
+        // The given protocol AUM saves the AUM call
+        push_calls(
+            &mock,
+            vec![vec![
+                process_params(&params),
+                Token::Uint(trsy_burned),
+                Token::Uint(trsy_burned - 7),
+                Token::Uint(U256::from(7)),
+                Token::Uint(usd_value),
+            ]],
+        );
+        let quote = quoter
+            .quote_withdraw(&assets, &amounts, Some(U256::exp10(24)))
+            .await
+            .unwrap();
+
+        assert_eq!(quote.trsy_amount, trsy_burned);
+        assert_eq!(quote.usd_value, usd_value);
+        assert_eq!(quote.tax_in_trsy, U256::from(7));
+        assert_eq!(quote.effective_price, U256::exp10(18));
+        assert_eq!(quote.assets.len(), 1);
+        assert_asset_quote(&quote.assets[0], assets[0], amounts[0], &params[0]);
+    }
+
+    #[tokio::test]
+    async fn test_quote_swap_incentive() {
+        let (quoter, mock) = mocked_quoter();
+        let (asset_in, asset_out) = (Address::from_low_u64_be(1), Address::from_low_u64_be(2));
+        let incentive = I256::from(-42);
+
+        push_calls(
+            &mock,
+            vec![
+                vec![Token::Uint(U256::exp10(24))],
+                vec![
+                    Token::Uint(U256::from(99)),
+                    Token::Int(incentive.into_raw()),
+                ],
+            ],
+        );
+        let quote = quoter
+            .quote_swap(asset_in, U256::from(100), asset_out, None)
+            .await
+            .unwrap();
+
+        assert_eq!(quote.asset_in, asset_in);
+        assert_eq!(quote.amount_in, U256::from(100));
+        assert_eq!(quote.asset_out, asset_out);
+        assert_eq!(quote.amount_out, U256::from(99));
+        assert_eq!(quote.tax_in_usd, incentive);
+    }
+
+    #[tokio::test]
+    async fn test_quote_length_mismatch() {
+        let (quoter, _) = mocked_quoter();
+        let assets = [Address::from_low_u64_be(1), Address::from_low_u64_be(2)];
+        assert!(matches!(
+            quoter.quote_deposit(&assets, &[U256::one()], None).await,
+            Err(FydeError::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            quoter.quote_withdraw(&assets, &[U256::one()], None).await,
+            Err(FydeError::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_effective_price() {
+        assert_eq!(effective_price(U256::from(10), U256::zero()), U256::zero());
+        assert_eq!(
+            effective_price(U256::from(3), U256::from(2)),
+            U256::exp10(18) * 3 / 2
+        );
+    }
+}
@@ -0,0 +1,637 @@
+use ethers::{
+    providers::Middleware,
+    types::{transaction::eip2718::TypedTransaction, Address, TxHash, U256},
+};
+use std::sync::Arc;
+
+use crate::{
+    errors::FydeError, relayer_contract::UserRequest, AddressList, Chain, GovernanceModuleContract,
+    LiquidVaultContract, OracleModuleContract, RelayerContract,
+};
+
+/// Gas budget the keeper is paid for when processing a request.
+pub const DEFAULT_GAS_TO_FORWARD: u64 = 1_000_000;
+
+#[derive(Debug, Clone)]
+pub enum RelayerAction {
+    Deposit {
+        requests: Vec<UserRequest>,
+        keep_gov_rights: bool,
+        min_trsy_expected: U256,
+    },
+    Withdraw {
+        requests: Vec<UserRequest>,
+        max_trsy_to_pay: U256,
+    },
+    GovernanceWithdraw {
+        request: UserRequest,
+        user: Address,
+        max_trsy_to_pay: U256,
+    },
+    Swap {
+        asset_in: Address,
+        amount_in: U256,
+        asset_out: Address,
+        min_amount_out: U256,
+    },
+}
+
+impl RelayerAction {
+    pub fn assets(&self) -> Vec<Address> {
+        match self {
+            RelayerAction::Deposit { requests, .. } | RelayerAction::Withdraw { requests, .. } => {
+                requests.iter().map(|request| request.asset).collect()
+            }
+            RelayerAction::GovernanceWithdraw { request, .. } => vec![request.asset],
+            RelayerAction::Swap {
+                asset_in,
+                asset_out,
+                ..
+            } => vec![*asset_in, *asset_out],
+        }
+    }
+}
+
+/// Builds relayer requests (deposit, withdraw, swap and governance withdraw).
+///
+/// Every request is checked against the protocol state before the transaction
+/// is built: assets must be supported by the vault and not quarantined, the
+/// protocol must not be paused and swaps must be enabled. The ETH sent along
+/// pays the keeper and is computed from the oracle gas price.
+pub struct RelayerRequestBuilder<M: Middleware> {
+    client: Arc<M>,
+    relayer: RelayerContract<M>,
+    liquid_vault: LiquidVaultContract<M>,
+    oracle_module: OracleModuleContract<M>,
+    governance_module: GovernanceModuleContract<M>,
+    gas_to_forward: U256,
+    from: Option<Address>,
+}
+
+impl<M: Middleware> RelayerRequestBuilder<M> {
+    pub fn new(client: Arc<M>, chain: Chain) -> Self {
+        let address_list: AddressList = AddressList::new(&chain);
+
+        Self {
+            client: client.clone(),
+            relayer: RelayerContract::new(address_list.relayer, client.clone()),
+            liquid_vault: LiquidVaultContract::new(address_list.liquid_vault, client.clone()),
+            oracle_module: OracleModuleContract::new(address_list.oracle_module, client.clone()),
+            governance_module: GovernanceModuleContract::new(
+                address_list.governance_module,
+                client,
+            ),
+            gas_to_forward: U256::from(DEFAULT_GAS_TO_FORWARD),
+            from: None,
+        }
+    }
+
+    /// Override the gas budget used to compute the keeper fee.
+    pub fn gas_to_forward(mut self, gas: U256) -> Self {
+        self.gas_to_forward = gas;
+        self
+    }
+
+    /// Set the sender of the built transactions.
+    pub fn from(mut self, from: Address) -> Self {
+        self.from = Some(from);
+        self
+    }
+
+    /// ETH (in wei) that must be sent with a request to pay the keeper.
+    pub async fn get_keeper_fee(&self) -> Result<U256, FydeError<M>> {
+        let gwei_price = self.oracle_module.get_gwei_price().call().await?;
+        self.gas_to_forward
+            .checked_mul(gwei_price)
+            .and_then(|fee| fee.checked_mul(U256::exp10(9)))
+            .ok_or_else(|| FydeError::ArithmeticError(String::from("Keeper fee overflow")))
+    }
+
+    pub async fn validate(&self, action: &RelayerAction) -> Result<(), FydeError<M>> {
+        let assets = action.assets();
+        if assets.is_empty() {
+            return Err(FydeError::InvalidRequest(String::from(
+                "No asset in request",
+            )));
+        }
+        for (i, asset) in assets.iter().enumerate() {
+            if assets[..i].contains(asset) {
+                return Err(FydeError::InvalidRequest(format!(
+                    "Duplicated asset {:?}",
+                    asset
+                )));
+            }
+        }
+        let amount_is_zero = match action {
+            RelayerAction::Deposit { requests, .. } | RelayerAction::Withdraw { requests, .. } => {
+                requests.iter().any(|request| request.amount.is_zero())
+            }
+            RelayerAction::GovernanceWithdraw { request, .. } => request.amount.is_zero(),
+            RelayerAction::Swap { amount_in, .. } => amount_in.is_zero(),
+        };
+        if amount_is_zero {
+            return Err(FydeError::InvalidRequest(String::from("Zero amount")));
+        }
+
+        if self.relayer.paused().call().await? {
+            return Err(FydeError::ProtocolPaused);
+        }
+
+        let not_supported = self
+            .liquid_vault
+            .is_any_not_supported(assets.clone())
+            .call()
+            .await?;
+        if not_supported != Address::zero() {
+            return Err(FydeError::UnsupportedAsset(not_supported));
+        }
+
+        let quarantined = self
+            .relayer
+            .is_any_quarantined(assets.clone())
+            .call()
+            .await?;
+        if quarantined != Address::zero() {
+            return Err(FydeError::QuarantinedAsset(quarantined));
+        }
+
+        match action {
+            RelayerAction::Swap { .. } => {
+                if self.relayer.swap_paused().call().await? {
+                    return Err(FydeError::SwapPaused);
+                }
+                let not_allowed = self.liquid_vault.is_swap_allowed(assets).call().await?;
+                if not_allowed != Address::zero() {
+                    return Err(FydeError::SwapNotAllowed(not_allowed));
+                }
+            }
+            RelayerAction::Deposit {
+                keep_gov_rights: true,
+                ..
+            } => {
+                let not_on_whitelist = self
+                    .governance_module
+                    .is_any_not_on_gov_whitelist(assets)
+                    .call()
+                    .await?;
+                if not_on_whitelist != Address::zero() {
+                    return Err(FydeError::NotOnGovernanceWhitelist(not_on_whitelist));
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Validate the request and build the unsigned relayer transaction, keeper fee included.
+    pub async fn build(&self, action: RelayerAction) -> Result<TypedTransaction, FydeError<M>> {
+        self.validate(&action).await?;
+        let keeper_fee = self.get_keeper_fee().await?;
+
+        let mut tx = match action {
+            RelayerAction::Deposit {
+                requests,
+                keep_gov_rights,
+                min_trsy_expected,
+            } => {
+                self.relayer
+                    .deposit(requests, keep_gov_rights, min_trsy_expected)
+                    .tx
+            }
+            RelayerAction::Withdraw {
+                requests,
+                max_trsy_to_pay,
+            } => self.relayer.withdraw(requests, max_trsy_to_pay).tx,
+            RelayerAction::GovernanceWithdraw {
+                request,
+                user,
+                max_trsy_to_pay,
+            } => {
+                self.relayer
+                    .governance_withdraw(request, user, max_trsy_to_pay)
+                    .tx
+            }
+            RelayerAction::Swap {
+                asset_in,
+                amount_in,
+                asset_out,
+                min_amount_out,
+            } => {
+                self.relayer
+                    .swap(asset_in, amount_in, asset_out, min_amount_out)
+                    .tx
+            }
+        };
+        tx.set_value(keeper_fee);
+        if let Some(from) = self.from {
+            tx.set_from(from);
+        }
+
+        Ok(tx)
+    }
+
+    pub async fn deposit(
+        &self,
+        requests: Vec<UserRequest>,
+        keep_gov_rights: bool,
+        min_trsy_expected: U256,
+    ) -> Result<TypedTransaction, FydeError<M>> {
+        self.build(RelayerAction::Deposit {
+            requests,
+            keep_gov_rights,
+            min_trsy_expected,
+        })
+        .await
+    }
+
+    pub async fn withdraw(
+        &self,
+        requests: Vec<UserRequest>,
+        max_trsy_to_pay: U256,
+    ) -> Result<TypedTransaction, FydeError<M>> {
+        self.build(RelayerAction::Withdraw {
+            requests,
+            max_trsy_to_pay,
+        })
+        .await
+    }
+
+    pub async fn governance_withdraw(
+        &self,
+        request: UserRequest,
+        user: Address,
+        max_trsy_to_pay: U256,
+    ) -> Result<TypedTransaction, FydeError<M>> {
+        self.build(RelayerAction::GovernanceWithdraw {
+            request,
+            user,
+            max_trsy_to_pay,
+        })
+        .await
+    }
+
+    pub async fn swap(
+        &self,
+        asset_in: Address,
+        amount_in: U256,
+        asset_out: Address,
+        min_amount_out: U256,
+    ) -> Result<TypedTransaction, FydeError<M>> {
+        self.build(RelayerAction::Swap {
+            asset_in,
+            amount_in,
+            asset_out,
+            min_amount_out,
+        })
+        .await
+    }
+
+    /// Send a built transaction through the client. When `M` is a `SignerMiddleware`
+    /// the transaction is signed before being broadcast.
+    pub async fn send(&self, tx: TypedTransaction) -> Result<TxHash, FydeError<M>> {
+        let pending_tx = self
+            .client
+            .send_transaction(tx, None)
+            .await
+            .map_err(FydeError::MiddlewareError)?;
+        Ok(pending_tx.tx_hash())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        abi::Token,
+        providers::{MockProvider, Provider},
+        types::Bytes,
+        utils::id,
+    };
+
+    const GWEI_PRICE: u64 = 20;
+
+    fn mocked_builder() -> (RelayerRequestBuilder<Provider<MockProvider>>, MockProvider) {
+        let (provider, mock) = Provider::mocked();
+        (
+            RelayerRequestBuilder::new(Arc::new(provider), Chain::Mainnet),
+            mock,
+        )
+    }
+
+    /// Queue the `eth_call` results, in call order
+    fn push_calls(mock: &MockProvider, results: Vec<Token>) {
+        // Responses are served last in, first out
+        for result in results.into_iter().rev() {
+            mock.push::<Bytes, _>(Bytes::from(ethers::abi::encode(&[result])))
+                .unwrap();
+        }
+    }
+
+    /// Results of the checks shared by every action: not paused, supported, not quarantined
+    fn common_checks() -> Vec<Token> {
+        vec![
+            Token::Bool(false),
+            Token::Address(Address::zero()),
+            Token::Address(Address::zero()),
+        ]
+    }
+
+    fn request(asset: u64, amount: u64) -> UserRequest {
+        UserRequest {
+            asset: Address::from_low_u64_be(asset),
+            amount: U256::from(amount),
+        }
+    }
+
+    fn request_token(request: &UserRequest) -> Token {
+        Token::Tuple(vec![
+            Token::Address(request.asset),
+            Token::Uint(request.amount),
+        ])
+    }
+
+    fn calldata(signature: &str, arguments: Vec<Token>) -> Bytes {
+        let mut data = id(signature).to_vec();
+        data.extend(ethers::abi::encode(&arguments));
+        Bytes::from(data)
+    }
+
+    fn keeper_fee() -> U256 {
+        U256::from(DEFAULT_GAS_TO_FORWARD) * U256::from(GWEI_PRICE) * U256::exp10(9)
+    }
+
+    fn assert_invalid_request<T: std::fmt::Debug>(
+        result: Result<T, FydeError<Provider<MockProvider>>>,
+    ) {
+        assert!(
+            matches!(result, Err(FydeError::InvalidRequest(_))),
+            "{:?}",
+            result
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_requests_are_rejected_before_any_call() {
+        // The mock has no response queued, so any RPC call would fail differently
+        let (builder, _) = mocked_builder();
+
+        assert_invalid_request(builder.deposit(vec![], false, U256::zero()).await);
+        assert_invalid_request(
+            builder
+                .deposit(vec![request(1, 10), request(1, 20)], false, U256::zero())
+                .await,
+        );
+        assert_invalid_request(
+            builder
+                .swap(
+                    Address::from_low_u64_be(1),
+                    U256::from(10),
+                    Address::from_low_u64_be(1),
+                    U256::zero(),
+                )
+                .await,
+        );
+        assert_invalid_request(
+            builder
+                .withdraw(vec![request(1, 10), request(2, 0)], U256::MAX)
+                .await,
+        );
+        assert_invalid_request(
+            builder
+                .governance_withdraw(request(1, 0), Address::from_low_u64_be(9), U256::MAX)
+                .await,
+        );
+        assert_invalid_request(
+            builder
+                .swap(
+                    Address::from_low_u64_be(1),
+                    U256::zero(),
+                    Address::from_low_u64_be(2),
+                    U256::zero(),
+                )
+                .await,
+        );
+    }
+
+    #[tokio::test]
+    async fn test_protocol_checks() {
+        let (builder, mock) = mocked_builder();
+        let deposit = RelayerAction::Deposit {
+            requests: vec![request(1, 10)],
+            keep_gov_rights: false,
+            min_trsy_expected: U256::zero(),
+        };
+
+        push_calls(&mock, vec![Token::Bool(true)]);
+        assert!(matches!(
+            builder.validate(&deposit).await,
+            Err(FydeError::ProtocolPaused)
+        ));
+
+        let asset = Address::from_low_u64_be(1);
+        push_calls(&mock, vec![Token::Bool(false), Token::Address(asset)]);
+        assert!(matches!(
+            builder.validate(&deposit).await,
+            Err(FydeError::UnsupportedAsset(unsupported)) if unsupported == asset
+        ));
+
+        push_calls(
+            &mock,
+            vec![
+                Token::Bool(false),
+                Token::Address(Address::zero()),
+                Token::Address(asset),
+            ],
+        );
+        assert!(matches!(
+            builder.validate(&deposit).await,
+            Err(FydeError::QuarantinedAsset(quarantined)) if quarantined == asset
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_action_specific_checks() {
+        let (builder, mock) = mocked_builder();
+        let asset_in = Address::from_low_u64_be(1);
+        let asset_out = Address::from_low_u64_be(2);
+        let swap = RelayerAction::Swap {
+            asset_in,
+            amount_in: U256::from(10),
+            asset_out,
+            min_amount_out: U256::zero(),
+        };
+
+        push_calls(&mock, [common_checks(), vec![Token::Bool(true)]].concat());
+        assert!(matches!(
+            builder.validate(&swap).await,
+            Err(FydeError::SwapPaused)
+        ));
+
+        push_calls(
+            &mock,
+            [
+                common_checks(),
+                vec![Token::Bool(false), Token::Address(asset_out)],
+            ]
+            .concat(),
+        );
+        assert!(matches!(
+            builder.validate(&swap).await,
+            Err(FydeError::SwapNotAllowed(asset)) if asset == asset_out
+        ));
+
+        let deposit = RelayerAction::Deposit {
+            requests: vec![request(1, 10)],
+            keep_gov_rights: true,
+            min_trsy_expected: U256::zero(),
+        };
+        push_calls(
+            &mock,
+            [common_checks(), vec![Token::Address(asset_in)]].concat(),
+        );
+        assert!(matches!(
+            builder.validate(&deposit).await,
+            Err(FydeError::NotOnGovernanceWhitelist(asset)) if asset == asset_in
+        ));
+
+        // Without governance rights the whitelist is not checked
+        let deposit = RelayerAction::Deposit {
+            requests: vec![request(1, 10)],
+            keep_gov_rights: false,
+            min_trsy_expected: U256::zero(),
+        };
+        push_calls(&mock, common_checks());
+        builder.validate(&deposit).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_deposit_calldata() {
+        let (builder, mock) = mocked_builder();
+        let from = Address::from_low_u64_be(0xfe);
+        let builder = builder.from(from);
+        let requests = vec![request(1, 10), request(2, 20)];
+
+        push_calls(
+            &mock,
+            [
+                common_checks(),
+                vec![
+                    Token::Address(Address::zero()),
+                    Token::Uint(GWEI_PRICE.into()),
+                ],
+            ]
+            .concat(),
+        );
+        let tx = builder
+            .deposit(requests.clone(), true, U256::from(25))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            tx.data(),
+            Some(&calldata(
+                "deposit((address,uint256)[],bool,uint256)",
+                vec![
+                    Token::Array(requests.iter().map(request_token).collect()),
+                    Token::Bool(true),
+                    Token::Uint(U256::from(25)),
+                ],
+            ))
+        );
+        assert_eq!(
+            tx.to_addr(),
+            Some(&AddressList::new(&Chain::Mainnet).relayer)
+        );
+        assert_eq!(tx.value(), Some(&keeper_fee()));
+        assert_eq!(tx.from(), Some(&from));
+    }
+
+    #[tokio::test]
+    async fn test_withdraw_and_swap_calldata() {
+        let (builder, mock) = mocked_builder();
+        let requests = vec![request(1, 10)];
+        push_calls(
+            &mock,
+            [common_checks(), vec![Token::Uint(GWEI_PRICE.into())]].concat(),
+        );
+        let tx = builder
+            .withdraw(requests.clone(), U256::from(7))
+            .await
+            .unwrap();
+        assert_eq!(
+            tx.data(),
+            Some(&calldata(
+                "withdraw((address,uint256)[],uint256)",
+                vec![
+                    Token::Array(requests.iter().map(request_token).collect()),
+                    Token::Uint(U256::from(7)),
+                ],
+            ))
+        );
+        assert_eq!(tx.value(), Some(&keeper_fee()));
+
+        let user = Address::from_low_u64_be(9);
+        push_calls(
+            &mock,
+            [common_checks(), vec![Token::Uint(GWEI_PRICE.into())]].concat(),
+        );
+        let tx = builder
+            .governance_withdraw(request(1, 10), user, U256::from(7))
+            .await
+            .unwrap();
+        assert_eq!(
+            tx.data(),
+            Some(&calldata(
+                "governanceWithdraw((address,uint256),address,uint256)",
+                vec![
+                    request_token(&request(1, 10)),
+                    Token::Address(user),
+                    Token::Uint(U256::from(7)),
+                ],
+            ))
+        );
+
+        let (asset_in, asset_out) = (Address::from_low_u64_be(1), Address::from_low_u64_be(2));
+        push_calls(
+            &mock,
+            [
+                common_checks(),
+                vec![
+                    Token::Bool(false),
+                    Token::Address(Address::zero()),
+                    Token::Uint(GWEI_PRICE.into()),
+                ],
+            ]
+            .concat(),
+        );
+        let tx = builder
+            .swap(asset_in, U256::from(10), asset_out, U256::from(9))
+            .await
+            .unwrap();
+        assert_eq!(
+            tx.data(),
+            Some(&calldata(
+                "swap(address,uint256,address,uint256)",
+                vec![
+                    Token::Address(asset_in),
+                    Token::Uint(U256::from(10)),
+                    Token::Address(asset_out),
+                    Token::Uint(U256::from(9)),
+                ],
+            ))
+        );
+        assert_eq!(tx.value(), Some(&keeper_fee()));
+    }
+
+    #[tokio::test]
+    async fn test_keeper_fee_overflow() {
+        let (builder, mock) = mocked_builder();
+        let builder = builder.gas_to_forward(U256::MAX / 2);
+        push_calls(&mock, vec![Token::Uint(GWEI_PRICE.into())]);
+        assert!(matches!(
+            builder.get_keeper_fee().await,
+            Err(FydeError::ArithmeticError(_))
+        ));
+    }
+}
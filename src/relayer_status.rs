@@ -0,0 +1,293 @@
+use ethers::{
+    contract::{LogMeta, Multicall},
+    providers::Middleware,
+    types::{Address, Filter, U256},
+};
+use serde::Serialize;
+use std::{collections::BTreeSet, sync::Arc};
+
+use crate::{
+    block_cache::{BlockMetaCache, WithBlockCache},
+    errors::{DataError, EventContext, FydeError},
+    log_scanner::{LogScanner, ScanCursor, WithScanner},
+    AddressList, Chain, RelayerContract, RelayerContractEvents,
+};
+
+/// Protocol status and role audit from the relayer
+pub struct RelayerStatus<M: Middleware> {
+    relayer: RelayerContract<M>,
+    multicall: Multicall<M>,
+    scanner: LogScanner<M>,
+    block_cache: BlockMetaCache<M>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RelayerState {
+    pub paused: bool,
+    pub swap_paused: bool,
+    pub exclusive_user: Address,
+    /// In basis points
+    pub deviation_threshold: u16,
+    /// In seconds
+    pub min_quarantine_duration: u64,
+    /// Id of the next request
+    pub nonce: u32,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct QuarantineEntry {
+    pub asset: Address,
+    /// Timestamp of the last AddedToQuarantine event of the asset
+    pub entered_at: u64,
+    pub block_number: u64,
+    pub expiration_time: u64,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RelayerRole {
+    Keeper,
+    Guard,
+    Swapper,
+    IncentiveManager,
+    User,
+}
+
+/// Role members, rebuilt from the `*Added`/`*Removed` events
+#[derive(Debug, Serialize, Clone, Default, PartialEq)]
+pub struct RelayerRoles {
+    pub keepers: BTreeSet<Address>,
+    pub guards: BTreeSet<Address>,
+    pub swappers: BTreeSet<Address>,
+    pub incentive_managers: BTreeSet<Address>,
+    pub users: BTreeSet<Address>,
+}
+
+impl RelayerRoles {
+    pub fn members(&self, role: RelayerRole) -> &BTreeSet<Address> {
+        match role {
+            RelayerRole::Keeper => &self.keepers,
+            RelayerRole::Guard => &self.guards,
+            RelayerRole::Swapper => &self.swappers,
+            RelayerRole::IncentiveManager => &self.incentive_managers,
+            RelayerRole::User => &self.users,
+        }
+    }
+
+    fn members_mut(&mut self, role: RelayerRole) -> &mut BTreeSet<Address> {
+        match role {
+            RelayerRole::Keeper => &mut self.keepers,
+            RelayerRole::Guard => &mut self.guards,
+            RelayerRole::Swapper => &mut self.swappers,
+            RelayerRole::IncentiveManager => &mut self.incentive_managers,
+            RelayerRole::User => &mut self.users,
+        }
+    }
+
+    /// Apply a role event, returns false for any other event
+    pub fn apply(&mut self, event: &RelayerContractEvents) -> bool {
+        let (role, account, added) = match event {
+            RelayerContractEvents::KeeperAddedFilter(ev) => (RelayerRole::Keeper, ev.0, true),
+            RelayerContractEvents::KeeperRemovedFilter(ev) => (RelayerRole::Keeper, ev.0, false),
+            RelayerContractEvents::GuardAddedFilter(ev) => (RelayerRole::Guard, ev.0, true),
+            RelayerContractEvents::GuardRemovedFilter(ev) => (RelayerRole::Guard, ev.0, false),
+            RelayerContractEvents::SwapperAddedFilter(ev) => (RelayerRole::Swapper, ev.0, true),
+            RelayerContractEvents::SwapperRemovedFilter(ev) => (RelayerRole::Swapper, ev.0, false),
+            RelayerContractEvents::IncentiveManagerAddedFilter(ev) => {
+                (RelayerRole::IncentiveManager, ev.0, true)
+            }
+            RelayerContractEvents::IncentiveManagerRemovedFilter(ev) => {
+                (RelayerRole::IncentiveManager, ev.0, false)
+            }
+            RelayerContractEvents::UserAddedFilter(ev) => (RelayerRole::User, ev.0, true),
+            RelayerContractEvents::UserRemovedFilter(ev) => (RelayerRole::User, ev.0, false),
+            _ => return false,
+        };
+        let members = self.members_mut(role);
+        match added {
+            true => members.insert(account),
+            false => members.remove(&account),
+        };
+        true
+    }
+}
+
+impl<M: Middleware> WithScanner<M> for RelayerStatus<M> {
+    fn scanner_mut(&mut self) -> &mut LogScanner<M> {
+        &mut self.scanner
+    }
+}
+
+impl<M: Middleware> WithBlockCache<M> for RelayerStatus<M> {
+    fn block_cache_mut(&mut self) -> &mut BlockMetaCache<M> {
+        &mut self.block_cache
+    }
+}
+
+impl<M: Middleware> RelayerStatus<M> {
+    pub async fn new(client: Arc<M>, chain: Chain) -> Result<Self, FydeError<M>> {
+        let address_list: AddressList = AddressList::new(&chain);
+        Ok(Self {
+            relayer: RelayerContract::new(address_list.relayer, client.clone()),
+            multicall: Multicall::new(client.clone(), None).await?,
+            scanner: LogScanner::new(client.clone()),
+            block_cache: BlockMetaCache::new(client),
+        })
+    }
+
+    pub async fn get_state(&self) -> Result<RelayerState, FydeError<M>> {
+        let mut multicall = self.multicall.clone();
+        multicall.clear_calls();
+        multicall.add_call(self.relayer.paused(), false);
+        multicall.add_call(self.relayer.swap_paused(), false);
+        multicall.add_call(self.relayer.exclusive_user(), false);
+        multicall.add_call(self.relayer.deviation_threshold(), false);
+        multicall.add_call(self.relayer.min_quarantine_duration(), false);
+        multicall.add_call(self.relayer.nonce(), false);
+        let (
+            paused,
+            swap_paused,
+            exclusive_user,
+            deviation_threshold,
+            min_quarantine_duration,
+            nonce,
+        ): (bool, bool, Address, u16, U256, u32) = multicall.call().await?;
+
+        Ok(RelayerState {
+            paused,
+            swap_paused,
+            exclusive_user,
+            deviation_threshold,
+            min_quarantine_duration: u64::try_from(min_quarantine_duration).map_err(|_| {
+                DataError::out_of_range("min quarantine duration", EventContext::default())
+            })?,
+            nonce,
+        })
+    }
+
+    /// Role members from the events between two blocks
+    pub async fn get_roles(
+        &self,
+        from_block: u64,
+        to_block: Option<u64>,
+    ) -> Result<(RelayerRoles, ScanCursor), FydeError<M>> {
+        let mut roles = RelayerRoles::default();
+        let cursor = self.update_roles(&mut roles, from_block, to_block).await?;
+        Ok((roles, cursor))
+    }
+
+    /// Apply the role events between two blocks to `roles`, e.g. from a previous cursor
+    pub async fn update_roles(
+        &self,
+        roles: &mut RelayerRoles,
+        from_block: u64,
+        to_block: Option<u64>,
+    ) -> Result<ScanCursor, FydeError<M>> {
+        let filter = Filter::new().address(self.relayer.address());
+        let scan = self.scanner.scan(&filter, from_block, to_block).await?;
+        let events: Vec<(RelayerContractEvents, LogMeta)> = scan.decode();
+        for (event, _) in &events {
+            roles.apply(event);
+        }
+        Ok(scan.cursor)
+    }
+
+    /// Assets currently quarantined, with the time they entered quarantine. Events are
+    /// scanned from `from_block`, which must precede the quarantines.
+    pub async fn get_quarantine_list(
+        &self,
+        from_block: u64,
+    ) -> Result<Vec<QuarantineEntry>, FydeError<M>> {
+        let filter = Filter::new().address(self.relayer.address());
+        let scan = self.scanner.scan(&filter, from_block, None).await?;
+        let events: Vec<(RelayerContractEvents, LogMeta)> = scan.decode();
+
+        // Last AddedToQuarantine of each asset, in order of entry
+        let mut additions: Vec<(Address, LogMeta)> = vec![];
+        for (event, meta) in events {
+            if let RelayerContractEvents::AddedToQuarantineFilter(ev) = event {
+                additions.retain(|(asset, _)| *asset != ev.asset);
+                additions.push((ev.asset, meta));
+            }
+        }
+        if additions.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut multicall = self.multicall.clone();
+        multicall.clear_calls();
+        for (asset, _) in &additions {
+            multicall.add_call(self.relayer.is_quarantined(*asset), false);
+        }
+        let quarantined: Vec<bool> = multicall.call_array().await?;
+        multicall.clear_calls();
+        for (asset, _) in &additions {
+            multicall.add_call(self.relayer.quarantine_list(*asset), false);
+        }
+        let expirations: Vec<u128> = multicall.call_array().await?;
+
+        let current: Vec<(Address, LogMeta, u128)> = additions
+            .into_iter()
+            .zip(quarantined.into_iter().zip(expirations))
+            .filter(|(_, (quarantined, _))| *quarantined)
+            .map(|((asset, meta), (_, expiration))| (asset, meta, expiration))
+            .collect();
+        self.block_cache
+            .prefetch(
+                current
+                    .iter()
+                    .map(|(_, meta, _)| meta.block_number.as_u64()),
+            )
+            .await?;
+
+        let mut entries = vec![];
+        for (asset, meta, expiration_time) in current {
+            let block_number = meta.block_number.as_u64();
+            let expiration_time = u64::try_from(expiration_time).map_err(|_| {
+                DataError::out_of_range(
+                    "quarantine expiration time",
+                    EventContext::tx(meta.transaction_hash).with_block(block_number),
+                )
+            })?;
+            entries.push(QuarantineEntry {
+                asset,
+                entered_at: self.block_cache.get_for_log(&meta).await?.timestamp,
+                block_number,
+                expiration_time,
+            });
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::relayer_contract::{
+        GuardAddedFilter, KeeperAddedFilter, KeeperRemovedFilter, PauseFilter,
+    };
+
+    #[test]
+    fn test_roles_from_events() {
+        let (alice, bob) = (Address::random(), Address::random());
+        let events = vec![
+            RelayerContractEvents::KeeperAddedFilter(KeeperAddedFilter(alice)),
+            RelayerContractEvents::KeeperAddedFilter(KeeperAddedFilter(bob)),
+            RelayerContractEvents::GuardAddedFilter(GuardAddedFilter(alice)),
+            RelayerContractEvents::KeeperRemovedFilter(KeeperRemovedFilter(alice)),
+        ];
+
+        let mut roles = RelayerRoles::default();
+        for event in &events {
+            assert!(roles.apply(event));
+        }
+        assert!(
+            !roles.apply(&RelayerContractEvents::PauseFilter(PauseFilter {
+                timestamp: U256::zero()
+            }))
+        );
+
+        assert_eq!(roles.members(RelayerRole::Keeper), &BTreeSet::from([bob]));
+        assert_eq!(roles.members(RelayerRole::Guard), &BTreeSet::from([alice]));
+        assert!(roles.members(RelayerRole::Swapper).is_empty());
+    }
+}
@@ -0,0 +1,429 @@
+use ethers::{
+    abi::{encode_packed, Token},
+    contract::{LogMeta, Multicall},
+    providers::Middleware,
+    types::{transaction::eip2718::TypedTransaction, Address, Filter, H256, U256},
+    utils::keccak256,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{
+    block_cache::{BlockMetaCache, WithBlockCache},
+    errors::FydeError,
+    log_scanner::{LogScanner, ScanCursor, WithScanner},
+    merkle::{ClaimTree, MerkleClaim, MerkleTree},
+    revenue_ve_fyde_distributor_contract::RevenueVeFydeDistributorContractEvents,
+    utils::FydeAmount,
+    AddressList, Chain, RevenueVeFydeDistributorContract,
+};
+
+pub struct RevenueDistributor<M: Middleware> {
+    contract: RevenueVeFydeDistributorContract<M>,
+    multicall: Multicall<M>,
+    scanner: LogScanner<M>,
+    block_cache: BlockMetaCache<M>,
+    from: Option<Address>,
+}
+
+/// Cumulative TRSY allocated to one veFyde holder since the first distribution
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RevenueClaim {
+    pub account: Address,
+    pub cumulative_amount: U256,
+    pub proof: Vec<H256>,
+}
+
+/// Cumulative revenue tree, as posted with `updateRoot`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RevenueTree {
+    pub root: H256,
+    pub claims: Vec<RevenueClaim>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DistributorState {
+    pub root: H256,
+    /// TRSY deposited since the deployment
+    pub cumulative_fees: FydeAmount,
+    pub trsy: Address,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ClaimableRevenue {
+    pub account: Address,
+    pub cumulative_amount: FydeAmount,
+    pub claimed: FydeAmount,
+    pub claimable: FydeAmount,
+    /// The proof verifies against the current on-chain root
+    pub valid_proof: bool,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub enum RevenueDistributorEvent {
+    RewardsClaimed {
+        account: Address,
+        amount: U256,
+        tx_hash: H256,
+        block_number: u64,
+        timestamp: u64,
+    },
+    RootUpdated {
+        root: H256,
+        tx_hash: H256,
+        block_number: u64,
+        timestamp: u64,
+    },
+}
+
+/// Leaf of a claim, `keccak256(abi.encodePacked(account, cumulativeAmount))`
+pub fn revenue_leaf(account: Address, cumulative_amount: U256) -> H256 {
+    let packed = encode_packed(&[Token::Address(account), Token::Uint(cumulative_amount)])
+        .expect("static tokens are always packed");
+    H256(keccak256(packed))
+}
+
+impl MerkleClaim for RevenueClaim {
+    fn account(&self) -> Address {
+        self.account
+    }
+
+    fn leaf(&self) -> H256 {
+        revenue_leaf(self.account, self.cumulative_amount)
+    }
+
+    fn proof(&self) -> &[H256] {
+        &self.proof
+    }
+}
+
+impl ClaimTree for RevenueTree {
+    type Claim = RevenueClaim;
+
+    fn claims(&self) -> &[RevenueClaim] {
+        &self.claims
+    }
+}
+
+impl RevenueTree {
+    /// Tree of cumulative amounts, accounts are sorted and zero amounts left out
+    pub fn new(cumulative_amounts: impl IntoIterator<Item = (Address, U256)>) -> Self {
+        let mut amounts: Vec<(Address, U256)> = cumulative_amounts
+            .into_iter()
+            .filter(|(_, amount)| !amount.is_zero())
+            .collect();
+        amounts.sort_by_key(|(account, _)| *account);
+        let tree = MerkleTree::new(
+            amounts
+                .iter()
+                .map(|(account, amount)| revenue_leaf(*account, *amount))
+                .collect(),
+        );
+        let claims = amounts
+            .into_iter()
+            .enumerate()
+            .map(|(index, (account, cumulative_amount))| RevenueClaim {
+                account,
+                cumulative_amount,
+                proof: tree.proof(index).unwrap_or_default(),
+            })
+            .collect();
+
+        Self {
+            root: tree.root(),
+            claims,
+        }
+    }
+
+    /// Total TRSY allocated by the tree, at most `cumulativeFees` for a valid root
+    pub fn total(&self) -> U256 {
+        self.claims
+            .iter()
+            .fold(U256::zero(), |total, claim| total + claim.cumulative_amount)
+    }
+}
+
+impl<M: Middleware> WithScanner<M> for RevenueDistributor<M> {
+    fn scanner_mut(&mut self) -> &mut LogScanner<M> {
+        &mut self.scanner
+    }
+}
+
+impl<M: Middleware> WithBlockCache<M> for RevenueDistributor<M> {
+    fn block_cache_mut(&mut self) -> &mut BlockMetaCache<M> {
+        &mut self.block_cache
+    }
+}
+
+impl<M: Middleware> RevenueDistributor<M> {
+    pub async fn new(client: Arc<M>, chain: Chain) -> Result<Self, FydeError<M>> {
+        let address_list: AddressList = AddressList::new(&chain);
+        Ok(Self {
+            contract: RevenueVeFydeDistributorContract::new(
+                address_list.vefyde_fee_distributor,
+                client.clone(),
+            ),
+            multicall: Multicall::new(client.clone(), None).await?,
+            scanner: LogScanner::new(client.clone()),
+            block_cache: BlockMetaCache::new(client),
+            from: None,
+        })
+    }
+
+    /// Sender of the built transactions
+    pub fn from(mut self, from: Address) -> Self {
+        self.from = Some(from);
+        self
+    }
+
+    pub async fn get_state(&self) -> Result<DistributorState, FydeError<M>> {
+        let mut multicall = self.multicall.clone();
+        multicall.clear_calls();
+        multicall.add_call(self.contract.root(), false);
+        multicall.add_call(self.contract.cumulative_fees(), false);
+        multicall.add_call(self.contract.trsy(), false);
+        let (root, cumulative_fees, trsy): ([u8; 32], U256, Address) = multicall.call().await?;
+
+        Ok(DistributorState {
+            root: H256(root),
+            cumulative_fees: FydeAmount::new(cumulative_fees, 18),
+            trsy,
+        })
+    }
+
+    pub async fn get_claimed(&self, account: Address) -> Result<FydeAmount, FydeError<M>> {
+        let claimed = self.contract.claimed(account).call().await?;
+        Ok(FydeAmount::new(claimed, 18))
+    }
+
+    /// Claimable TRSY of every account of the tree
+    pub async fn get_claimable(
+        &self,
+        tree: &RevenueTree,
+    ) -> Result<Vec<ClaimableRevenue>, FydeError<M>> {
+        if tree.claims.is_empty() {
+            return Ok(vec![]);
+        }
+        let root = H256(self.contract.root().call().await?);
+        let mut multicall = self.multicall.clone();
+        multicall.clear_calls();
+        for claim in &tree.claims {
+            multicall.add_call(self.contract.claimed(claim.account), false);
+        }
+        let claimed: Vec<U256> = multicall.call_array().await?;
+
+        Ok(tree
+            .claims
+            .iter()
+            .zip(claimed)
+            .map(|(claim, claimed)| ClaimableRevenue {
+                account: claim.account,
+                cumulative_amount: FydeAmount::new(claim.cumulative_amount, 18),
+                claimed: FydeAmount::new(claimed, 18),
+                claimable: FydeAmount::new(claim.cumulative_amount.saturating_sub(claimed), 18),
+                valid_proof: claim.verify(root),
+            })
+            .collect())
+    }
+
+    pub async fn get_claimable_of(
+        &self,
+        tree: &RevenueTree,
+        account: Address,
+    ) -> Result<Option<ClaimableRevenue>, FydeError<M>> {
+        let Some(claim) = tree.claim_of(account) else {
+            return Ok(None);
+        };
+        let mut multicall = self.multicall.clone();
+        multicall.clear_calls();
+        multicall.add_call(self.contract.root(), false);
+        multicall.add_call(self.contract.claimed(account), false);
+        let (root, claimed): ([u8; 32], U256) = multicall.call().await?;
+
+        Ok(Some(ClaimableRevenue {
+            account,
+            cumulative_amount: FydeAmount::new(claim.cumulative_amount, 18),
+            claimed: FydeAmount::new(claimed, 18),
+            claimable: FydeAmount::new(claim.cumulative_amount.saturating_sub(claimed), 18),
+            valid_proof: claim.verify(H256(root)),
+        }))
+    }
+
+    /// Claim the TRSY of `account`, failing early on a stale proof or nothing to claim
+    pub async fn claim(
+        &self,
+        tree: &RevenueTree,
+        account: Address,
+    ) -> Result<TypedTransaction, FydeError<M>> {
+        let claimable =
+            self.get_claimable_of(tree, account)
+                .await?
+                .ok_or(FydeError::InvalidRequest(format!(
+                    "No claim for {:?} in the tree",
+                    account
+                )))?;
+        if !claimable.valid_proof {
+            return Err(FydeError::InvalidRequest(format!(
+                "Proof of {:?} does not match the on-chain root",
+                account
+            )));
+        }
+        if claimable.claimable.is_zero() {
+            return Err(FydeError::InvalidRequest(format!(
+                "Nothing to claim for {:?}",
+                account
+            )));
+        }
+
+        let claim = tree.claim_of(account).expect("claim checked above");
+        let mut tx = self
+            .contract
+            .claim(
+                account,
+                claim.cumulative_amount,
+                claim.proof.iter().map(|hash| hash.0).collect(),
+            )
+            .tx;
+        if let Some(from) = self.from {
+            tx.set_from(from);
+        }
+        Ok(tx)
+    }
+
+    /// RewardsClaimed and RootUpdated events between two blocks, with the cursor to resume
+    /// the scan from.
+    pub async fn get_history(
+        &self,
+        from_block: u64,
+        to_block: Option<u64>,
+    ) -> Result<(Vec<RevenueDistributorEvent>, ScanCursor), FydeError<M>> {
+        let filter = Filter::new().address(self.contract.address());
+        let scan = self.scanner.scan(&filter, from_block, to_block).await?;
+        let events: Vec<(RevenueVeFydeDistributorContractEvents, LogMeta)> = scan.decode();
+        self.block_cache
+            .prefetch(events.iter().map(|(_, meta)| meta.block_number.as_u64()))
+            .await?;
+
+        let mut history = vec![];
+        for (event, meta) in events {
+            let block_number = meta.block_number.as_u64();
+            let timestamp = self.block_cache.get_for_log(&meta).await?.timestamp;
+            match event {
+                RevenueVeFydeDistributorContractEvents::RewardsClaimedFilter(ev) => {
+                    history.push(RevenueDistributorEvent::RewardsClaimed {
+                        account: ev.account,
+                        amount: ev.amount,
+                        tx_hash: meta.transaction_hash,
+                        block_number,
+                        timestamp,
+                    })
+                }
+                RevenueVeFydeDistributorContractEvents::RootUpdatedFilter(ev) => {
+                    history.push(RevenueDistributorEvent::RootUpdated {
+                        root: H256(ev.new_root),
+                        tx_hash: meta.transaction_hash,
+                        block_number,
+                        timestamp,
+                    })
+                }
+                _ => {}
+            }
+        }
+
+        Ok((history, scan.cursor))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::revenue_ve_fyde_distributor_contract::{ClaimCall, RewardsClaimedFilter};
+    use ethers::{
+        abi::AbiDecode,
+        providers::{Http, Provider},
+    };
+
+    type Error = FydeError<Provider<Http>>;
+
+    #[test]
+    fn test_revenue_tree() {
+        let (alice, bob) = (Address::random(), Address::random());
+        let tree = RevenueTree::new([
+            (alice, U256::from(300)),
+            (bob, U256::from(700)),
+            (Address::random(), U256::zero()),
+        ]);
+
+        assert_eq!(tree.claims.len(), 2);
+        assert_eq!(tree.total(), U256::from(1_000));
+        assert!(tree.claims.iter().all(|claim| claim.verify(tree.root)));
+        assert_eq!(
+            RevenueTree::new([(bob, U256::from(700)), (alice, U256::from(300))]).root,
+            tree.root
+        );
+
+        let mut claim = tree.claim_of(alice).unwrap().clone();
+        claim.cumulative_amount = U256::from(301);
+        assert!(!claim.verify(tree.root));
+
+        let reloaded = RevenueTree::from_json(&tree.to_json().unwrap()).unwrap();
+        assert_eq!(reloaded, tree);
+    }
+
+    /// Claims accepted on-chain must verify against the root of their block, checking
+    /// the (account, cumulativeAmount) leaf layout
+    #[tokio::test]
+    #[ignore = "needs a mainnet archive RPC"]
+    async fn test_revenue_leaf_matches_on_chain_claims() -> Result<(), Error> {
+        let provider = Arc::new(
+            Provider::<Http>::try_from(
+                "https://eth-mainnet.g.alchemy.com/v2/6scwdLmXmD0Ifv_8TgZaNA5Y7MzBhoZP",
+            )
+            .expect("Failed to create provider"),
+        );
+        let address = AddressList::new(&Chain::Mainnet).vefyde_fee_distributor;
+        let contract = RevenueVeFydeDistributorContract::new(address, provider.clone());
+        let latest = provider
+            .get_block_number()
+            .await
+            .map_err(FydeError::MiddlewareError)?
+            .as_u64();
+        let scan = LogScanner::new(provider.clone())
+            .scan(
+                &Filter::new().address(address),
+                latest.saturating_sub(1_000_000),
+                Some(latest),
+            )
+            .await?;
+        let claims: Vec<(RewardsClaimedFilter, LogMeta)> = scan.decode();
+
+        let mut verified = 0;
+        for (event, meta) in claims.iter().rev().take(10) {
+            let tx = provider
+                .get_transaction(meta.transaction_hash)
+                .await
+                .map_err(FydeError::MiddlewareError)?
+                .expect("claim transaction");
+            // Claims sent through another contract have no claim calldata
+            let Ok(call) = ClaimCall::decode(&tx.input) else {
+                continue;
+            };
+            assert_eq!(call.account, event.account);
+
+            let root = contract.root().block(meta.block_number).call().await?;
+            let claim = RevenueClaim {
+                account: call.account,
+                cumulative_amount: call.claimable,
+                proof: call.proof.into_iter().map(H256).collect(),
+            };
+            assert!(
+                claim.verify(H256(root)),
+                "claim of {:?} does not verify",
+                claim.account
+            );
+            verified += 1;
+        }
+        assert!(verified > 0, "no direct claim in the scanned range");
+        Ok(())
+    }
+}
@@ -0,0 +1,423 @@
+use ethers::{
+    abi::Tokenizable,
+    contract::{ContractCall, Multicall},
+    providers::Middleware,
+    types::{Address, U256, U512},
+};
+use serde::Serialize;
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::{
+    errors::{DataError, EventContext, FydeError},
+    log_scanner::{LogScanner, WithScanner},
+    revenue_distributor::RevenueTree,
+    utils::FydeAmount,
+    ve_fyde::VeFyde,
+    vote_escrow_contract::{Checkpoint, VoteEscrowContract},
+    AddressList, Chain,
+};
+
+/// Calls per multicall when reading holder histories
+pub const DEFAULT_MULTICALL_BATCH_SIZE: usize = 500;
+
+/// Computes time-weighted veFyde shares over an epoch and the cumulative revenue tree
+/// allocating a TRSY amount to them.
+///
+/// Balances are sampled at each week boundary of the epoch, where `totalSupplyAt` is
+/// checkpointed. A holder weight is the sum of its sampled balances, and its share the
+/// weight over the sum of sampled total supplies.
+pub struct RevenueShareGenerator<M: Middleware> {
+    vote_escrow: VoteEscrowContract<M>,
+    ve_fyde: VeFyde<M>,
+    multicall: Multicall<M>,
+    batch_size: usize,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct HolderShare {
+    pub account: Address,
+    /// Sum of the sampled veFyde balances
+    pub weight: U256,
+    /// Fraction of the epoch total supply
+    pub share: FydeAmount,
+    /// TRSY allocated for the epoch
+    pub allocation: FydeAmount,
+}
+
+/// Epoch report, enough to reproduce and audit an `updateRoot`
+#[derive(Debug, Serialize, Clone)]
+pub struct RevenueEpoch {
+    pub start: u64,
+    pub end: u64,
+    /// Week boundaries the balances are sampled at
+    pub samples: Vec<u64>,
+    pub total_weight: U256,
+    pub amount: FydeAmount,
+    /// TRSY left unallocated by rounding or missing holders
+    pub unallocated: FydeAmount,
+    pub shares: Vec<HolderShare>,
+    pub tree: RevenueTree,
+}
+
+/// veFyde balance of a user at `timestamp` from its checkpoint history
+pub fn ve_balance_at(history: &[Checkpoint], timestamp: u64) -> u128 {
+    history
+        .iter()
+        .take_while(|checkpoint| checkpoint.timestamp <= timestamp as u128)
+        .last()
+        .map(|checkpoint| {
+            checkpoint
+                .value
+                .bias
+                .saturating_sub(checkpoint.value.slope.saturating_mul(timestamp as u128))
+        })
+        .unwrap_or_default()
+}
+
+/// Week boundaries within `[start, end)`
+pub fn epoch_samples(start: u64, end: u64, week: u64) -> Vec<u64> {
+    let first = start.div_ceil(week) * week;
+    (first..end).step_by(week as usize).collect()
+}
+
+/// Split `amount` pro rata of `weights` over `total_weight`, rounding down. A weight above
+/// the total is capped to the whole amount.
+pub fn allocate(amount: U256, weights: &[(Address, U256)], total_weight: U256) -> Vec<U256> {
+    weights
+        .iter()
+        .map(|(_, weight)| match total_weight.is_zero() {
+            true => U512::zero(),
+            false => (amount.full_mul(*weight) / U512::from(total_weight)).min(amount.into()),
+        })
+        .map(|allocation| U256::try_from(allocation).expect("allocation is at most amount"))
+        .collect()
+}
+
+/// Add the epoch allocations to the cumulative amounts of the previous tree
+pub fn cumulative_amounts(
+    previous: Option<&RevenueTree>,
+    allocations: impl IntoIterator<Item = (Address, U256)>,
+) -> BTreeMap<Address, U256> {
+    let mut cumulative: BTreeMap<Address, U256> = previous
+        .map(|tree| {
+            tree.claims
+                .iter()
+                .map(|claim| (claim.account, claim.cumulative_amount))
+                .collect()
+        })
+        .unwrap_or_default();
+    for (account, allocation) in allocations {
+        *cumulative.entry(account).or_default() += allocation;
+    }
+    cumulative
+}
+
+/// The scanner reads the veFyde holders list
+impl<M: Middleware> WithScanner<M> for RevenueShareGenerator<M> {
+    fn scanner_mut(&mut self) -> &mut LogScanner<M> {
+        self.ve_fyde.scanner_mut()
+    }
+}
+
+impl<M: Middleware> RevenueShareGenerator<M> {
+    pub async fn new(client: Arc<M>, chain: Chain) -> Result<Self, FydeError<M>> {
+        let address_list: AddressList = AddressList::new(&chain);
+        Ok(Self {
+            vote_escrow: VoteEscrowContract::new(address_list.vote_escrow, client.clone()),
+            ve_fyde: VeFyde::new(client.clone(), chain).await?,
+            multicall: Multicall::new(client, None).await?,
+            batch_size: DEFAULT_MULTICALL_BATCH_SIZE,
+        })
+    }
+
+    /// Maximum number of calls per multicall
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Run `calls` in multicalls of at most `batch_size` calls
+    async fn call_batched<D: Tokenizable + Send>(
+        &self,
+        calls: Vec<ContractCall<M, D>>,
+    ) -> Result<Vec<D>, FydeError<M>> {
+        let mut multicall = self.multicall.clone();
+        let mut results = Vec::with_capacity(calls.len());
+        for batch in calls.chunks(self.batch_size) {
+            multicall.clear_calls();
+            for call in batch {
+                multicall.add_call(call.clone(), false);
+            }
+            results.extend(multicall.call_array::<D>().await?);
+        }
+        Ok(results)
+    }
+
+    /// Full checkpoint history of each holder
+    pub async fn get_histories(
+        &self,
+        holders: &[Address],
+    ) -> Result<Vec<Vec<Checkpoint>>, FydeError<M>> {
+        let lengths: Vec<U256> = self
+            .call_batched(
+                holders
+                    .iter()
+                    .map(|holder| self.vote_escrow.get_user_history_length(*holder))
+                    .collect(),
+            )
+            .await?;
+        let lengths = holders
+            .iter()
+            .zip(lengths)
+            .map(|(holder, length)| {
+                usize::try_from(length).map_err(|_| {
+                    DataError::out_of_range(
+                        format!("veFyde history length of {:?}", holder),
+                        EventContext::default(),
+                    )
+                })
+            })
+            .collect::<Result<Vec<usize>, DataError>>()?;
+
+        let mut calls = vec![];
+        for (holder, length) in holders.iter().zip(&lengths) {
+            for index in 0..*length {
+                calls.push(
+                    self.vote_escrow
+                        .get_user_history_at(*holder, U256::from(index)),
+                );
+            }
+        }
+        let mut checkpoints: Vec<Checkpoint> = self.call_batched(calls).await?;
+
+        let mut histories = vec![];
+        for length in lengths {
+            let rest = checkpoints.split_off(length);
+            histories.push(std::mem::replace(&mut checkpoints, rest));
+        }
+        Ok(histories)
+    }
+
+    /// Time-weighted shares of every veFyde holder over `[start, end)`
+    pub async fn compute_shares(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> Result<(Vec<u64>, Vec<(Address, U256)>, U256), FydeError<M>> {
+        let mut multicall = self.multicall.clone();
+        multicall.clear_calls();
+        multicall.add_call(self.vote_escrow.week(), false);
+        multicall.add_call(self.vote_escrow.last_slope_change_applied_at(), false);
+        let (week, last_applied): (u128, u128) = multicall.call().await?;
+
+        let samples = epoch_samples(start, end, week as u64);
+        if samples.is_empty() {
+            return Err(FydeError::InvalidRequest(format!(
+                "Epoch [{}, {}) contains no week boundary",
+                start, end
+            )));
+        }
+        if let Some(sample) = samples.iter().find(|t| **t as u128 > last_applied) {
+            return Err(FydeError::InvalidRequest(format!(
+                "Total supply is not checkpointed at {} yet",
+                sample
+            )));
+        }
+
+        multicall.clear_calls();
+        for sample in &samples {
+            multicall.add_call(self.vote_escrow.total_supply_at(*sample as u128), false);
+        }
+        let supplies: Vec<u128> = multicall.call_array().await?;
+        let total_weight = supplies
+            .iter()
+            .fold(U256::zero(), |total, supply| total + U256::from(*supply));
+
+        let holders = self.ve_fyde.get_ve_fyde_holders_list().await?;
+        let histories = self.get_histories(&holders).await?;
+        let weights = holders
+            .into_iter()
+            .zip(histories)
+            .map(|(holder, history)| {
+                let weight = samples.iter().fold(U256::zero(), |total, sample| {
+                    total + U256::from(ve_balance_at(&history, *sample))
+                });
+                (holder, weight)
+            })
+            .filter(|(_, weight)| !weight.is_zero())
+            .collect();
+
+        Ok((samples, weights, total_weight))
+    }
+
+    /// Allocate `amount` TRSY over the epoch and add it to the `previous` cumulative tree
+    pub async fn generate(
+        &self,
+        start: u64,
+        end: u64,
+        amount: U256,
+        previous: Option<&RevenueTree>,
+    ) -> Result<RevenueEpoch, FydeError<M>> {
+        let (samples, weights, total_weight) = self.compute_shares(start, end).await?;
+        let allocations = allocate(amount, &weights, total_weight);
+        let allocated = allocations
+            .iter()
+            .fold(U256::zero(), |total, allocation| total + allocation);
+
+        let shares = weights
+            .iter()
+            .zip(&allocations)
+            .map(|((account, weight), allocation)| HolderShare {
+                account: *account,
+                weight: *weight,
+                share: FydeAmount::new(*weight, 18)
+                    .checked_div(&FydeAmount::new(total_weight, 18))
+                    .unwrap_or(FydeAmount::zero(18)),
+                allocation: FydeAmount::new(*allocation, 18),
+            })
+            .collect();
+        let tree = RevenueTree::new(cumulative_amounts(
+            previous,
+            weights
+                .iter()
+                .map(|(account, _)| *account)
+                .zip(allocations.iter().copied()),
+        ));
+
+        Ok(RevenueEpoch {
+            start,
+            end,
+            samples,
+            total_weight,
+            amount: FydeAmount::new(amount, 18),
+            unallocated: FydeAmount::new(amount.saturating_sub(allocated), 18),
+            shares,
+            tree,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        log_scanner::LogScanner,
+        merkle::verify_proof,
+        revenue_distributor::revenue_leaf,
+        revenue_ve_fyde_distributor_contract::{ClaimCall, RevenueVeFydeDistributorContractEvents},
+        vote_escrow_contract::VeBalance,
+        RevenueVeFydeDistributorContract,
+    };
+    use ethers::{
+        abi::AbiDecode,
+        contract::LogMeta,
+        providers::{Http, Provider},
+        types::{Filter, H256},
+    };
+
+    type Error = FydeError<Provider<Http>>;
+
+    fn checkpoint(timestamp: u128, bias: u128, slope: u128) -> Checkpoint {
+        Checkpoint {
+            timestamp,
+            value: VeBalance { bias, slope },
+        }
+    }
+
+    #[test]
+    fn test_time_weighted_allocation() {
+        // Locked at 100 with a balance reaching zero at 1_000, then increased at 400
+        let history = vec![checkpoint(100, 1_000, 1), checkpoint(400, 4_000, 2)];
+        assert_eq!(ve_balance_at(&history, 50), 0);
+        assert_eq!(ve_balance_at(&history, 300), 700);
+        assert_eq!(ve_balance_at(&history, 500), 3_000);
+        assert_eq!(ve_balance_at(&history, 3_000), 0);
+
+        assert_eq!(epoch_samples(150, 500, 100), vec![200, 300, 400]);
+        assert!(epoch_samples(150, 200, 100).is_empty());
+
+        let (alice, bob) = (Address::random(), Address::random());
+        let weights = vec![(alice, U256::from(1)), (bob, U256::from(2))];
+        let allocations = allocate(U256::from(100), &weights, U256::from(4));
+        assert_eq!(allocations, vec![U256::from(25), U256::from(50)]);
+        assert_eq!(
+            allocate(U256::from(100), &weights, U256::zero()),
+            vec![U256::zero(); 2]
+        );
+
+        let previous = RevenueTree::new([(alice, U256::from(10))]);
+        let cumulative = cumulative_amounts(
+            Some(&previous),
+            weights.iter().map(|(a, _)| *a).zip(allocations),
+        );
+        assert_eq!(cumulative[&alice], U256::from(35));
+        assert_eq!(cumulative[&bob], U256::from(50));
+    }
+
+    /// Roots posted with `updateRoot` must be rebuilt from the leaves and proofs of the
+    /// claims made under them. A full rebuild needs the off-chain epoch inputs, so this
+    /// checks the leaf encoding and pair hashing of the trees written by the generator.
+    #[tokio::test]
+    #[ignore = "needs a mainnet archive RPC"]
+    async fn test_root_updated_roots_match_claims() -> Result<(), Error> {
+        let provider = Arc::new(
+            Provider::<Http>::try_from(
+                "https://eth-mainnet.g.alchemy.com/v2/6scwdLmXmD0Ifv_8TgZaNA5Y7MzBhoZP",
+            )
+            .expect("Failed to create provider"),
+        );
+        let address = AddressList::new(&Chain::Mainnet).vefyde_fee_distributor;
+        let contract = RevenueVeFydeDistributorContract::new(address, provider.clone());
+        let latest = provider
+            .get_block_number()
+            .await
+            .map_err(FydeError::MiddlewareError)?
+            .as_u64();
+        let scan = LogScanner::new(provider.clone())
+            .scan(
+                &Filter::new().address(address),
+                latest.saturating_sub(1_000_000),
+                Some(latest),
+            )
+            .await?;
+        let events: Vec<(RevenueVeFydeDistributorContractEvents, LogMeta)> = scan.decode();
+
+        let mut root = None;
+        let mut verified = 0;
+        for (event, meta) in events {
+            match event {
+                RevenueVeFydeDistributorContractEvents::RootUpdatedFilter(event) => {
+                    let on_chain = contract.root().block(meta.block_number).call().await?;
+                    assert_eq!(event.new_root, on_chain);
+                    root = Some(H256(event.new_root));
+                }
+                RevenueVeFydeDistributorContractEvents::RewardsClaimedFilter(_) => {
+                    let Some(root) = root else {
+                        continue;
+                    };
+                    let tx = provider
+                        .get_transaction(meta.transaction_hash)
+                        .await
+                        .map_err(FydeError::MiddlewareError)?
+                        .expect("claim transaction");
+                    let Ok(call) = ClaimCall::decode(&tx.input) else {
+                        continue;
+                    };
+                    let proof: Vec<H256> = call.proof.into_iter().map(H256).collect();
+                    assert!(verify_proof(
+                        revenue_leaf(call.account, call.claimable),
+                        &proof,
+                        root
+                    ));
+                    verified += 1;
+                }
+                _ => {}
+            }
+        }
+        assert!(
+            verified > 0,
+            "no claim under a RootUpdated root in the scanned range"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,923 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use ethers::{
+    providers::Middleware,
+    types::{Address, U256},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::OnceCell;
+
+use crate::{
+    asset::{Asset, AssetAccounting, AssetTrait, TargetConcentrations, WeightStatus},
+    errors::FydeError,
+    governance::Governance,
+    liquid_vault::LiquidVault,
+    protocol_history::{PendingRequests, ProtocolHistory, UserAction},
+    user::User,
+    utils::FydeAmount,
+    ve_fyde::VeFyde,
+    Chain,
+};
+
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(30);
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+pub const MAX_PAGE_SIZE: usize = 1_000;
+/// Blocks scanned by `/history` when `from_block` is not given, about a day on mainnet
+pub const DEFAULT_HISTORY_BLOCKS: u64 = 7_200;
+/// Largest block range of a `/history` request
+pub const MAX_HISTORY_BLOCKS: u64 = 100_000;
+/// Blocks scanned at once by `/history` before checking whether the page is full
+pub const HISTORY_SCAN_BLOCKS: u64 = 10_000;
+
+/// A cached response with the time it was stored, empty while its first fetch runs
+type CacheEntry = Arc<OnceCell<(Instant, Value)>>;
+
+/// JSON responses by request path, kept for a fixed time after they are stored.
+/// Concurrent misses on a key share a single fetch.
+pub struct ResponseCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, CacheEntry>>,
+}
+
+impl ResponseCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether `entry` is being fetched or was stored less than `ttl` ago
+    fn is_live(&self, entry: &CacheEntry) -> bool {
+        entry
+            .get()
+            .is_none_or(|(stored_at, _)| stored_at.elapsed() < self.ttl)
+    }
+
+    pub fn get(&self, key: &str) -> Option<Value> {
+        let entries = self.entries.lock().ok()?;
+        let (stored_at, value) = entries.get(key)?.get()?;
+        (stored_at.elapsed() < self.ttl).then(|| value.clone())
+    }
+
+    pub fn insert(&self, key: String, value: Value) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.retain(|_, entry| self.is_live(entry));
+            entries.insert(
+                key,
+                Arc::new(OnceCell::new_with(Some((Instant::now(), value)))),
+            );
+        }
+    }
+
+    /// Cached value of `key`, or the value of `fetch` if it succeeds. Callers missing the
+    /// same key wait for the first fetch, a failed fetch is retried by the next caller.
+    pub async fn get_or_try_insert_with<F, Fut, E>(&self, key: String, fetch: F) -> Result<Value, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Value, E>>,
+    {
+        let cell = match self.entries.lock() {
+            Ok(mut entries) => {
+                entries.retain(|_, entry| self.is_live(entry));
+                entries.entry(key).or_default().clone()
+            }
+            Err(_) => Arc::new(OnceCell::new()),
+        };
+        let (_, value) = cell
+            .get_or_try_init(|| async {
+                let value = fetch().await?;
+                Ok((Instant::now(), value))
+            })
+            .await?;
+        Ok(value.clone())
+    }
+}
+
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl<M: Middleware> From<FydeError<M>> for ApiError {
+    fn from(error: FydeError<M>) -> Self {
+        let status = match error {
+            FydeError::InvalidRequest(_) | FydeError::UnsupportedAsset(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            _ => StatusCode::BAD_GATEWAY,
+        };
+        Self {
+            status,
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ApiError {
+    fn from(error: serde_json::Error) -> Self {
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: error.to_string(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(json!({ "error": self.message }))).into_response()
+    }
+}
+
+type ApiResult = Result<Json<Value>, ApiError>;
+
+#[derive(Debug, Serialize)]
+pub struct ProtocolState {
+    pub tvl: FydeAmount,
+    pub trsy_supply: FydeAmount,
+    pub trsy_staked: FydeAmount,
+    pub trsy_price: FydeAmount,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AssetState {
+    pub address: Address,
+    pub symbol: String,
+    pub decimals: u8,
+    pub aum: FydeAmount,
+    pub oracle_price: FydeAmount,
+    /// Share of the TVL in percents, as are the targets
+    pub current_concentration: FydeAmount,
+    pub target_concentrations: TargetConcentrations,
+    pub weight_status: WeightStatus,
+    pub accounting: AssetAccounting,
+    pub liquidity_profile: FydeAmount,
+    pub is_allowed_on_governance: bool,
+    pub is_quarantined: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserPortfolio {
+    pub address: Address,
+    pub trsy_balance: U256,
+    pub proxy: Option<Address>,
+    pub balances: HashMap<Address, U256>,
+    pub allowances: HashMap<Address, U256>,
+    pub st_trsy_balance: HashMap<Address, U256>,
+    pub governance_rights: HashMap<Address, U256>,
+    pub voting_rights: HashMap<Address, U256>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    pub from_block: Option<u64>,
+    pub to_block: Option<u64>,
+    pub page_size: Option<usize>,
+}
+
+/// A page of `/history`. Actions are those of the requests made from `from_block` up to
+/// `next_from_block`, the `from_block` of the next page, which is `None` once `to_block`
+/// is reached. Pages end on a block, so a page can hold a few more than `page_size` items.
+#[derive(Debug, Serialize)]
+pub struct HistoryPage {
+    pub from_block: u64,
+    pub to_block: u64,
+    pub next_from_block: Option<u64>,
+    pub items: Vec<UserAction>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VeFydeQuery {
+    #[serde(default)]
+    pub chart: bool,
+}
+
+struct ServerState<M: Middleware> {
+    client: Arc<M>,
+    chain: Chain,
+    cache: ResponseCache,
+}
+
+impl<M: Middleware> ServerState<M> {
+    /// Serve `key` from the cache, or compute and cache it
+    async fn cached<F, Fut>(&self, key: String, fetch: F) -> ApiResult
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Value, ApiError>>,
+    {
+        Ok(Json(self.cache.get_or_try_insert_with(key, fetch).await?))
+    }
+
+    /// Scan `[from_block, to_block]` by chunks until `page_size` actions are known
+    async fn history_page(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        page_size: usize,
+    ) -> Result<HistoryPage, ApiError>
+    where
+        M: 'static,
+    {
+        let history = ProtocolHistory::new(self.client.clone(), self.chain.clone());
+        let mut pending = PendingRequests::default();
+        let mut items = vec![];
+        let mut next_block = from_block;
+        while next_block <= to_block {
+            let chunk_end = to_block.min(next_block.saturating_add(HISTORY_SCAN_BLOCKS - 1));
+            let (actions, cursor) = history
+                .get_data_with_pending(&mut pending, next_block, Some(chunk_end))
+                .await?;
+            items.extend(actions.into_iter().map(|(action, _)| action));
+            next_block = cursor.next_block;
+            if next_block > to_block {
+                break;
+            }
+
+            // Actions of requests made before every pending one are final
+            let ready = ready_actions(&items, pending.earliest_block());
+            if ready >= page_size {
+                // Pages end on a block, so the last block of the page is kept whole
+                items.sort_by_key(|action| (action.block_number(), action.log_index()));
+                let last_block = items[page_size - 1].block_number();
+                items.retain(|action| action.block_number() <= last_block);
+                return Ok(HistoryPage {
+                    from_block,
+                    to_block,
+                    next_from_block: Some(last_block + 1),
+                    items,
+                });
+            }
+        }
+
+        // Requests still pending at `to_block` were not processed in the range
+        items.sort_by_key(|action| (action.block_number(), action.log_index()));
+        Ok(HistoryPage {
+            from_block,
+            to_block,
+            next_from_block: None,
+            items,
+        })
+    }
+
+    async fn asset_state(&self, address: Address, tvl: &FydeAmount) -> Result<AssetState, ApiError>
+    where
+        M: 'static,
+    {
+        let asset = Asset::new(address, self.client.clone(), self.chain.clone());
+        let decimals = asset.get_decimals().await?;
+        let aum = asset.get_asset_aum().await?;
+        let current_concentration = asset.get_current_concentration(&aum, tvl).await?;
+        let target_concentrations = asset
+            .get_asset_target_concentrations()
+            .await?
+            .to_percents()
+            .ok_or_else(|| {
+                FydeError::<M>::ArithmeticError(String::from("Target concentration overflow"))
+            })?;
+        let weight_status = asset
+            .get_weight_status(&target_concentrations, &current_concentration)
+            .await?;
+        let accounting = asset.get_asset_accounting(decimals).await?;
+
+        Ok(AssetState {
+            address,
+            symbol: asset.get_symbol().await?,
+            decimals,
+            aum,
+            oracle_price: asset.get_oracle_price(decimals).await?,
+            current_concentration,
+            target_concentrations,
+            weight_status,
+            liquidity_profile: asset.get_liquidity_profile(accounting.clone()).await?,
+            accounting,
+            is_allowed_on_governance: asset.get_is_allowed_on_governance().await?,
+            is_quarantined: asset.get_is_quarantined().await?,
+        })
+    }
+}
+
+/// REST API over the SDK clients, see [`openapi`] for the routes.
+pub struct Server<M: Middleware> {
+    client: Arc<M>,
+    chain: Chain,
+    cache_ttl: Duration,
+}
+
+impl<M: Middleware + 'static> Server<M> {
+    pub fn new(client: Arc<M>, chain: Chain) -> Self {
+        Self {
+            client,
+            chain,
+            cache_ttl: DEFAULT_CACHE_TTL,
+        }
+    }
+
+    /// How long responses are served from the cache
+    pub fn cache_ttl(mut self, cache_ttl: Duration) -> Self {
+        self.cache_ttl = cache_ttl;
+        self
+    }
+
+    pub fn router(self) -> Router {
+        let state = Arc::new(ServerState {
+            client: self.client,
+            chain: self.chain,
+            cache: ResponseCache::new(self.cache_ttl),
+        });
+
+        Router::new()
+            .route("/protocol", get(protocol::<M>))
+            .route("/assets", get(assets::<M>))
+            .route("/assets/{address}", get(asset::<M>))
+            .route("/users/{address}", get(user::<M>))
+            .route("/users/{address}/vefyde", get(ve_fyde::<M>))
+            .route("/governance/users", get(governance_users::<M>))
+            .route("/governance/rebalance/{asset}", get(rebalance_queue::<M>))
+            .route("/history", get(history::<M>))
+            .route("/openapi.json", get(|| async { Json(openapi()) }))
+            .with_state(state)
+    }
+
+    pub async fn serve(self, address: SocketAddr) -> std::io::Result<()> {
+        let listener = tokio::net::TcpListener::bind(address).await?;
+        axum::serve(listener, self.router()).await
+    }
+}
+
+async fn protocol<M: Middleware + 'static>(State(state): State<Arc<ServerState<M>>>) -> ApiResult {
+    state
+        .cached(String::from("protocol"), || async {
+            let liquid_vault = LiquidVault::new(state.client.clone(), state.chain.clone()).await?;
+            let tvl = FydeAmount::new(liquid_vault.get_tvl().await?, 18);
+            let trsy_supply = FydeAmount::new(liquid_vault.get_trsy_supply().await?, 18);
+            let protocol_state = ProtocolState {
+                trsy_price: tvl
+                    .checked_div(&trsy_supply)
+                    .unwrap_or(FydeAmount::zero(18)),
+                tvl,
+                trsy_supply,
+                trsy_staked: FydeAmount::new(liquid_vault.get_trsy_staked().await?, 18),
+            };
+            Ok(serde_json::to_value(protocol_state)?)
+        })
+        .await
+}
+
+async fn assets<M: Middleware + 'static>(State(state): State<Arc<ServerState<M>>>) -> ApiResult {
+    state
+        .cached(String::from("assets"), || async {
+            let liquid_vault = LiquidVault::new(state.client.clone(), state.chain.clone()).await?;
+            let tvl = FydeAmount::new(liquid_vault.get_tvl().await?, 18);
+            let mut assets = vec![];
+            for address in liquid_vault.get_assets_list().await? {
+                assets.push(state.asset_state(address, &tvl).await?);
+            }
+            Ok(serde_json::to_value(assets)?)
+        })
+        .await
+}
+
+async fn asset<M: Middleware + 'static>(
+    State(state): State<Arc<ServerState<M>>>,
+    Path(address): Path<Address>,
+) -> ApiResult {
+    state
+        .cached(format!("assets/{:?}", address), || async {
+            let liquid_vault = LiquidVault::new(state.client.clone(), state.chain.clone()).await?;
+            if !liquid_vault.get_assets_list().await?.contains(&address) {
+                return Err(FydeError::<M>::UnsupportedAsset(address).into());
+            }
+            let tvl = FydeAmount::new(liquid_vault.get_tvl().await?, 18);
+            Ok(serde_json::to_value(
+                state.asset_state(address, &tvl).await?,
+            )?)
+        })
+        .await
+}
+
+async fn user<M: Middleware + 'static>(
+    State(state): State<Arc<ServerState<M>>>,
+    Path(address): Path<Address>,
+) -> ApiResult {
+    state
+        .cached(format!("users/{:?}", address), || async {
+            let liquid_vault = LiquidVault::new(state.client.clone(), state.chain.clone()).await?;
+            let assets = liquid_vault.get_assets_list().await?;
+            let mut assets_in_gov = vec![];
+            for asset in &assets {
+                let asset = Asset::new(*asset, state.client.clone(), state.chain.clone());
+                if asset.get_is_allowed_on_governance().await? {
+                    assets_in_gov.push(asset.get_address().await?);
+                }
+            }
+
+            let user = User::new(state.client.clone(), state.chain.clone(), address).await?;
+            let governance_data = user.get_governance_data(&assets_in_gov).await?;
+            let portfolio = UserPortfolio {
+                address,
+                trsy_balance: user.get_trsy_balance().await?,
+                proxy: user.get_proxy_address().await?,
+                balances: user.get_balances(&assets).await?,
+                allowances: user.get_allowances(&assets).await?,
+                st_trsy_balance: governance_data.st_trsy_balance,
+                governance_rights: governance_data.current_governance_rights,
+                voting_rights: governance_data.total_voting_rights,
+            };
+            Ok(serde_json::to_value(portfolio)?)
+        })
+        .await
+}
+
+async fn ve_fyde<M: Middleware + 'static>(
+    State(state): State<Arc<ServerState<M>>>,
+    Path(address): Path<Address>,
+    Query(query): Query<VeFydeQuery>,
+) -> ApiResult {
+    state
+        .cached(
+            format!("users/{:?}/vefyde?chart={}", address, query.chart),
+            || async {
+                let ve_fyde = VeFyde::new(state.client.clone(), state.chain.clone()).await?;
+                let ve_fyde_user = ve_fyde.get_ve_fyde_data(address, query.chart).await?;
+                Ok(serde_json::to_value(ve_fyde_user)?)
+            },
+        )
+        .await
+}
+
+async fn governance_users<M: Middleware + 'static>(
+    State(state): State<Arc<ServerState<M>>>,
+) -> ApiResult {
+    state
+        .cached(String::from("governance/users"), || async {
+            let governance = Governance::new(state.client.clone(), state.chain.clone()).await?;
+            Ok(serde_json::to_value(
+                governance.get_list_of_governance_users().await?,
+            )?)
+        })
+        .await
+}
+
+async fn rebalance_queue<M: Middleware + 'static>(
+    State(state): State<Arc<ServerState<M>>>,
+    Path(asset): Path<Address>,
+) -> ApiResult {
+    state
+        .cached(format!("governance/rebalance/{:?}", asset), || async {
+            let mut governance = Governance::new(state.client.clone(), state.chain.clone()).await?;
+            Ok(serde_json::to_value(
+                governance.get_proxy_to_rebalance(asset).await?,
+            )?)
+        })
+        .await
+}
+
+async fn history<M: Middleware + 'static>(
+    State(state): State<Arc<ServerState<M>>>,
+    Query(query): Query<HistoryQuery>,
+) -> ApiResult {
+    let page_size = query
+        .page_size
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let latest_block = match query.to_block {
+        Some(to_block) => to_block,
+        None => state
+            .client
+            .get_block_number()
+            .await
+            .map_err(FydeError::<M>::MiddlewareError)?
+            .as_u64(),
+    };
+    let (from_block, to_block) = history_range(query.from_block, latest_block)
+        .map_err(|reason| ApiError::from(FydeError::<M>::InvalidRequest(reason)))?;
+
+    let key = format!(
+        "history?from={}&to={}&page_size={}",
+        from_block, to_block, page_size
+    );
+    state
+        .cached(key, || async {
+            Ok(serde_json::to_value(
+                state.history_page(from_block, to_block, page_size).await?,
+            )?)
+        })
+        .await
+}
+
+/// Block range of a `/history` request, the last [`DEFAULT_HISTORY_BLOCKS`] blocks up to
+/// `to_block` by default and at most [`MAX_HISTORY_BLOCKS`] blocks
+fn history_range(from_block: Option<u64>, to_block: u64) -> Result<(u64, u64), String> {
+    let from_block = from_block.unwrap_or(to_block.saturating_sub(DEFAULT_HISTORY_BLOCKS - 1));
+    if from_block > to_block {
+        return Err(format!(
+            "from_block {} is after to_block {}",
+            from_block, to_block
+        ));
+    }
+    if to_block - from_block >= MAX_HISTORY_BLOCKS {
+        return Err(format!(
+            "Block range is limited to {} blocks",
+            MAX_HISTORY_BLOCKS
+        ));
+    }
+    Ok((from_block, to_block))
+}
+
+/// Number of actions of requests made before the earliest pending request
+fn ready_actions(actions: &[UserAction], earliest_pending: Option<u64>) -> usize {
+    match earliest_pending {
+        Some(block) => actions
+            .iter()
+            .filter(|action| action.block_number() < block)
+            .count(),
+        None => actions.len(),
+    }
+}
+
+/// OpenAPI 3 description of the server routes
+pub fn openapi() -> Value {
+    let address_param = |name: &str| {
+        json!({
+            "name": name,
+            "in": "path",
+            "required": true,
+            "schema": { "$ref": "#/components/schemas/Address" }
+        })
+    };
+    let route = |summary: &str, parameters: Vec<Value>, schema: Value| {
+        json!({
+            "get": {
+                "summary": summary,
+                "parameters": parameters,
+                "responses": {
+                    "200": {
+                        "description": "JSON response, amounts are decimal strings",
+                        "content": { "application/json": { "schema": schema } }
+                    },
+                    "400": { "$ref": "#/components/responses/Error" },
+                    "502": { "$ref": "#/components/responses/Error" }
+                }
+            }
+        })
+    };
+    let query_param = |name: &str, kind: &str| json!({ "name": name, "in": "query", "required": false, "schema": { "type": kind } });
+    let schema = |name: &str| json!({ "$ref": format!("#/components/schemas/{}", name) });
+    let array = |items: Value| json!({ "type": "array", "items": items });
+    let map = |values: Value| json!({ "type": "object", "additionalProperties": values });
+    let object = |properties: Value| {
+        let required: Vec<&String> = properties
+            .as_object()
+            .into_iter()
+            .flat_map(|p| p.keys())
+            .collect();
+        json!({ "type": "object", "required": required, "properties": properties })
+    };
+    let integer = json!({ "type": "integer", "minimum": 0 });
+    let boolean = json!({ "type": "boolean" });
+    let action = |fields: Value| {
+        let mut properties = json!({
+            "tx_hash": schema("H256"),
+            "block_number": integer,
+            "block_hash": schema("H256"),
+            "log_index": integer,
+            "timestamp": integer,
+            "request_id": integer,
+            "user": schema("Address"),
+        });
+        if let (Some(properties), Some(fields)) = (properties.as_object_mut(), fields.as_object()) {
+            properties.extend(fields.clone());
+        }
+        object(properties)
+    };
+
+    json!({
+        "openapi": "3.0.3",
+        "info": { "title": "Fyde API", "version": env!("CARGO_PKG_VERSION") },
+        "paths": {
+            "/protocol": route(
+                "Protocol TVL, TRSY supply and TRSY price",
+                vec![],
+                schema("ProtocolState")
+            ),
+            "/assets": route(
+                "State of every asset of the protocol",
+                vec![],
+                array(schema("AssetState"))
+            ),
+            "/assets/{address}": route(
+                "State of an asset",
+                vec![address_param("address")],
+                schema("AssetState")
+            ),
+            "/users/{address}": route(
+                "Balances, allowances and governance data of a user",
+                vec![address_param("address")],
+                schema("UserPortfolio")
+            ),
+            "/users/{address}/vefyde": route(
+                "veFyde lock of a user",
+                vec![address_param("address"), query_param("chart", "boolean")],
+                schema("VeFydeUser")
+            ),
+            "/governance/users": route(
+                "Users keeping their governance rights",
+                vec![],
+                array(schema("Address"))
+            ),
+            "/governance/rebalance/{asset}": route(
+                "Governance proxies to rebalance for an asset, most unbalanced first",
+                vec![address_param("asset")],
+                array(schema("Address"))
+            ),
+            "/history": route(
+                "Deposits, withdrawals and swaps of a block range, paged by block with next_from_block",
+                vec![
+                    query_param("from_block", "integer"),
+                    query_param("to_block", "integer"),
+                    query_param("page_size", "integer"),
+                ],
+                schema("HistoryPage")
+            ),
+        },
+        "components": {
+            "responses": {
+                "Error": {
+                    "description": "Invalid request, or RPC or upstream error",
+                    "content": { "application/json": { "schema": object(json!({ "error": { "type": "string" } })) } }
+                }
+            },
+            "schemas": {
+                "Address": { "type": "string", "pattern": "^0x[0-9a-fA-F]{40}$" },
+                "H256": { "type": "string", "pattern": "^0x[0-9a-fA-F]{64}$" },
+                "U256": { "type": "string", "pattern": "^0x[0-9a-fA-F]+$", "description": "Hex encoded integer" },
+                "FydeAmount": { "type": "string", "pattern": "^-?[0-9]+(\\.[0-9]+)?$", "description": "Decimal amount" },
+                "ProtocolState": object(json!({
+                    "tvl": schema("FydeAmount"),
+                    "trsy_supply": schema("FydeAmount"),
+                    "trsy_staked": schema("FydeAmount"),
+                    "trsy_price": schema("FydeAmount"),
+                })),
+                "AssetState": object(json!({
+                    "address": schema("Address"),
+                    "symbol": { "type": "string" },
+                    "decimals": integer,
+                    "aum": schema("FydeAmount"),
+                    "oracle_price": schema("FydeAmount"),
+                    "current_concentration": schema("FydeAmount"),
+                    "target_concentrations": object(json!({
+                        "target_concentration_deposit": schema("FydeAmount"),
+                        "target_concentration_withdraw": schema("FydeAmount"),
+                        "target_concentration_fyde": schema("FydeAmount"),
+                    })),
+                    "weight_status": {
+                        "type": "string",
+                        "enum": ["Overweight", "Underweight", "InRange", "Undertemined"]
+                    },
+                    "accounting": object(json!({
+                        "token_in_protocol": schema("FydeAmount"),
+                        "token_in_standard_pool": schema("FydeAmount"),
+                        "token_in_governance_pool": schema("FydeAmount"),
+                    })),
+                    "liquidity_profile": schema("FydeAmount"),
+                    "is_allowed_on_governance": boolean,
+                    "is_quarantined": boolean,
+                })),
+                "UserPortfolio": {
+                    "type": "object",
+                    "required": [
+                        "address", "trsy_balance", "proxy", "balances", "allowances",
+                        "st_trsy_balance", "governance_rights", "voting_rights"
+                    ],
+                    "properties": {
+                        "address": schema("Address"),
+                        "trsy_balance": schema("U256"),
+                        "proxy": { "allOf": [schema("Address")], "nullable": true },
+                        "balances": map(schema("U256")),
+                        "allowances": map(schema("U256")),
+                        "st_trsy_balance": map(schema("U256")),
+                        "governance_rights": map(schema("U256")),
+                        "voting_rights": map(schema("U256")),
+                    }
+                },
+                "VeFydeUser": object(json!({
+                    "ve_fyde_balance": integer,
+                    "fyde_locked": integer,
+                    "last_locking_date": integer,
+                    "history_length": integer,
+                    "lock_duration_in_days": integer,
+                    "unlock_date": integer,
+                    "ve_balance_chart": {
+                        "allOf": [object(json!({
+                            "ts": array(integer.clone()),
+                            "ve_balance": array(schema("FydeAmount")),
+                        }))],
+                        "nullable": true
+                    },
+                })),
+                "HistoryPage": {
+                    "type": "object",
+                    "required": ["from_block", "to_block", "next_from_block", "items"],
+                    "properties": {
+                        "from_block": integer,
+                        "to_block": integer,
+                        "next_from_block": { "type": "integer", "minimum": 0, "nullable": true },
+                        "items": array(schema("UserAction")),
+                    }
+                },
+                "UserAction": {
+                    "oneOf": [
+                        object(json!({ "Deposit": action(json!({
+                            "asset_in": array(schema("Address")),
+                            "amount_in": array(schema("U256")),
+                            "keep_gov_rights": boolean,
+                            "minted_at_trsy_price": schema("U256"),
+                            "usd_value_deposited": schema("U256"),
+                            "trsy_minted": schema("U256"),
+                        })) })),
+                        object(json!({ "Withdraw": action(json!({
+                            "asset_out": array(schema("Address")),
+                            "amount_out": array(schema("U256")),
+                            "burned_at_trsy_price": schema("U256"),
+                            "usd_value_withdrawn": schema("U256"),
+                            "trsy_burned": schema("U256"),
+                        })) })),
+                        object(json!({ "Swap": action(json!({
+                            "asset_in": schema("Address"),
+                            "asset_out": schema("Address"),
+                            "amount_in": schema("U256"),
+                            "amount_out": schema("U256"),
+                        })) })),
+                    ]
+                },
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_response_cache_ttl() {
+        let cache = ResponseCache::new(Duration::from_secs(60));
+        cache.insert(String::from("protocol"), json!({ "tvl": "1.0" }));
+        assert_eq!(cache.get("protocol"), Some(json!({ "tvl": "1.0" })));
+        assert_eq!(cache.get("assets"), None);
+
+        let cache = ResponseCache::new(Duration::ZERO);
+        cache.insert(String::from("protocol"), json!({ "tvl": "1.0" }));
+        assert_eq!(cache.get("protocol"), None);
+    }
+
+    #[tokio::test]
+    async fn test_response_cache_single_flight() {
+        let cache = ResponseCache::new(Duration::from_secs(60));
+        let fetches = std::sync::atomic::AtomicUsize::new(0);
+        let fetch = || async {
+            fetches.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Ok::<_, ApiError>(json!({ "tvl": "1.0" }))
+        };
+        let (first, second) = tokio::join!(
+            cache.get_or_try_insert_with(String::from("protocol"), fetch),
+            cache.get_or_try_insert_with(String::from("protocol"), fetch),
+        );
+        assert_eq!(first.unwrap(), second.unwrap());
+        assert_eq!(fetches.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        let failed = cache
+            .get_or_try_insert_with(String::from("assets"), || async {
+                Err::<Value, _>("RPC error")
+            })
+            .await;
+        assert!(failed.is_err());
+        let retried = cache
+            .get_or_try_insert_with(String::from("assets"), || async {
+                Ok::<_, &str>(json!([]))
+            })
+            .await;
+        assert_eq!(retried, Ok(json!([])));
+    }
+
+    #[tokio::test]
+    async fn test_response_cache_ttl_starts_when_stored() {
+        let cache = ResponseCache::new(Duration::from_millis(200));
+        let value = cache
+            .get_or_try_insert_with(String::from("protocol"), || async {
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                Ok::<_, ApiError>(json!({ "tvl": "1.0" }))
+            })
+            .await
+            .unwrap();
+        assert_eq!(cache.get("protocol"), Some(value));
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(cache.get("protocol"), None);
+    }
+
+    #[test]
+    fn test_history_range() {
+        assert_eq!(
+            history_range(None, 20_000_000),
+            Ok((20_000_000 - DEFAULT_HISTORY_BLOCKS + 1, 20_000_000))
+        );
+        assert_eq!(history_range(None, 100), Ok((0, 100)));
+        assert_eq!(
+            history_range(Some(19_950_000), 20_000_000),
+            Ok((19_950_000, 20_000_000))
+        );
+        assert!(history_range(Some(0), 20_000_000).is_err());
+        assert!(history_range(Some(20_000_001), 20_000_000).is_err());
+    }
+
+    /// `$ref`s of `value` and its children
+    fn refs(value: &Value) -> Vec<String> {
+        match value {
+            Value::Object(fields) => fields
+                .iter()
+                .flat_map(|(key, value)| match (key.as_str(), value) {
+                    ("$ref", Value::String(path)) => vec![path.clone()],
+                    _ => refs(value),
+                })
+                .collect(),
+            Value::Array(values) => values.iter().flat_map(refs).collect(),
+            _ => vec![],
+        }
+    }
+
+    #[test]
+    fn test_openapi_describes_every_route() {
+        let openapi = openapi();
+        let paths = openapi["paths"].as_object().unwrap();
+        assert_eq!(paths.len(), 8);
+        for (path, route) in paths {
+            let schema = &route["get"]["responses"]["200"]["content"]["application/json"]["schema"];
+            assert!(schema.is_object(), "{} has no response schema", path);
+        }
+        for path in refs(&openapi) {
+            let pointer = path.strip_prefix('#').unwrap();
+            assert!(
+                openapi.pointer(pointer).is_some(),
+                "{} is not defined",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_openapi_schemas_match_responses() {
+        let openapi = openapi();
+        let fields = |name: &str| {
+            let mut fields: Vec<String> = openapi["components"]["schemas"][name]["properties"]
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect();
+            fields.sort();
+            fields
+        };
+        let keys = |value: Value| {
+            let mut keys: Vec<String> = value.as_object().unwrap().keys().cloned().collect();
+            keys.sort();
+            keys
+        };
+        let amount = || FydeAmount::zero(18);
+        let protocol_state = ProtocolState {
+            tvl: amount(),
+            trsy_supply: amount(),
+            trsy_staked: amount(),
+            trsy_price: amount(),
+        };
+        assert_eq!(
+            keys(serde_json::to_value(protocol_state).unwrap()),
+            fields("ProtocolState")
+        );
+        let page = HistoryPage {
+            from_block: 0,
+            to_block: 0,
+            next_from_block: None,
+            items: vec![],
+        };
+        assert_eq!(
+            keys(serde_json::to_value(page).unwrap()),
+            fields("HistoryPage")
+        );
+    }
+}
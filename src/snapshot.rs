@@ -2,7 +2,10 @@ use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-use crate::Chain;
+use crate::{
+    errors::{DataError, EventContext},
+    Chain,
+};
 
 #[derive(Serialize, Deserialize, Debug)]
 struct AssetFields {
@@ -84,18 +87,19 @@ struct SnapshotUrl {
 
 impl SnapshotUrl {
     pub fn new(chain: Chain) -> Self {
+        // Custom chains are usually mainnet forks and share the mainnet endpoints
         let asset_endpoint = match chain {
-            Chain::Mainnet => "https://api.fyde.fi/api/assets",
+            Chain::Mainnet | Chain::Custom(_) => "https://api.fyde.fi/api/assets",
             Chain::Sepolia => "https://test.fyde.fi/api/assets",
         };
 
         let snapshot_graphql = match chain {
-            Chain::Mainnet => "https://hub.snapshot.org/graphql",
+            Chain::Mainnet | Chain::Custom(_) => "https://hub.snapshot.org/graphql",
             Chain::Sepolia => "https://testnet.hub.snapshot.org/graphql",
         };
 
         let space_name = match chain {
-            Chain::Mainnet => "vefyde.eth",
+            Chain::Mainnet | Chain::Custom(_) => "vefyde.eth",
             Chain::Sepolia => "vefyde.eth",
         };
 
@@ -115,12 +119,13 @@ impl Snapshot {
         }
     }
 
-    pub async fn fetch_address(&self) -> Result<APIResponse, Box<dyn std::error::Error>> {
+    pub async fn fetch_address(&self) -> Result<APIResponse, DataError> {
         let response = self
             .client
             .get(&self.snapshot_url.asset_endpoint)
             .send()
             .await?
+            .error_for_status()?
             .json::<Vec<AssetFields>>()
             .await?;
 
@@ -138,15 +143,57 @@ impl Snapshot {
     pub async fn fetch_latest_proposal(
         &self,
         skip_index: usize,
-    ) -> Result<ProposalsResponse, Box<dyn std::error::Error>> {
-        println!("Fetching latest proposal...");
+    ) -> Result<ProposalsResponse, DataError> {
         let fyde_response = self.fetch_address().await?;
 
+        let proposal = self
+            .fetch_proposals(1, skip_index)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or(DataError::missing(
+                "closed proposal",
+                EventContext::default(),
+            ))?;
+
+        let temp_proposal = proposal.clone();
+        let mut choices = Vec::new();
+
+        for choice in temp_proposal.choices {
+            choices.push(choice.clone());
+        }
+
+        let mut choices_address = Vec::new();
+        for choice in choices {
+            let address = fyde_response
+                .symbol_mapping
+                .get(&choice)
+                .ok_or(DataError::missing(
+                    format!("address for symbol {}", choice),
+                    EventContext::default(),
+                ))?;
+            choices_address.push(address.clone());
+        }
+
+        let proposal_response = ProposalsResponse {
+            proposals: vec![proposal],
+            choices: choices_address,
+        };
+
+        Ok(proposal_response)
+    }
+
+    /// Closed proposals of the space, most recent first
+    pub async fn fetch_proposals(
+        &self,
+        num_proposals: usize,
+        skip_index: usize,
+    ) -> Result<Vec<Proposal>, DataError> {
         let query = json!({
             "query": format!(r#"
             {{
                 proposals(
-                    first: 1,
+                    first: {},
                     skip: {},
                     where: {{
                         space_in: ["{}"],
@@ -174,6 +221,7 @@ impl Snapshot {
                 }}
             }}
             "#,
+                num_proposals,
                 skip_index,
                 self.snapshot_url.space_name
             )
@@ -185,34 +233,14 @@ impl Snapshot {
             .json(&query)
             .send()
             .await?
+            .error_for_status()?
             .json::<serde_json::Value>()
             .await?;
+        check_graphql_errors(&response)?;
 
-        let proposal = serde_json::from_value::<ProposalsVector>(response["data"].clone())?
-            .proposals
-            .into_iter()
-            .next()
-            .ok_or("No proposals found")?;
+        let proposals = serde_json::from_value::<ProposalsVector>(response["data"].clone())?;
 
-        let temp_proposal = proposal.clone();
-        let mut choices = Vec::new();
-
-        for choice in temp_proposal.choices {
-            choices.push(choice.clone());
-        }
-
-        let mut choices_address = Vec::new();
-        for choice in choices {
-            let address = fyde_response.symbol_mapping.get(&choice).unwrap();
-            choices_address.push(address.clone());
-        }
-
-        let proposal_response = ProposalsResponse {
-            proposals: vec![proposal],
-            choices: choices_address,
-        };
-
-        Ok(proposal_response)
+        Ok(proposals.proposals)
     }
 
     pub async fn fetch_votes(
@@ -220,7 +248,7 @@ impl Snapshot {
         proposal_id: &str,
         num_votes: usize,
         skip_index: usize,
-    ) -> Result<Vec<Vote>, Box<dyn std::error::Error>> {
+    ) -> Result<Vec<Vote>, DataError> {
         let query = json!({
             "query": format!(
                 r#"
@@ -262,8 +290,10 @@ impl Snapshot {
             .json(&query)
             .send()
             .await?
+            .error_for_status()?
             .json::<serde_json::Value>()
             .await?;
+        check_graphql_errors(&response)?;
 
         let votes = serde_json::from_value::<VotesResponse>(response["data"].clone())?.votes;
 
@@ -271,6 +301,22 @@ impl Snapshot {
     }
 }
 
+fn check_graphql_errors(response: &serde_json::Value) -> Result<(), DataError> {
+    match response["errors"].as_array() {
+        Some(errors) if !errors.is_empty() => {
+            let messages: Vec<String> = errors
+                .iter()
+                .map(|error| match error["message"].as_str() {
+                    Some(message) => message.to_string(),
+                    None => error.to_string(),
+                })
+                .collect();
+            Err(DataError::GraphQLError(messages.join("; ")))
+        }
+        _ => Ok(()),
+    }
+}
+
 /*
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,242 @@
+use ethers::{
+    contract::Multicall,
+    providers::Middleware,
+    types::{Address, U256},
+};
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::{
+    errors::{DataError, EventContext, FydeError},
+    trsy_staking::SECONDS_PER_YEAR,
+    utils::{latest_timestamp, FydeAmount},
+    AddressList, Chain, Strsy,
+};
+
+/// Client of the sTRSY ERC-4626 vault. Rewards are vested linearly at `issuanceRate` until
+/// `vestingPeriodFinish`, the rate being scaled by `precision`.
+pub struct StTrsy<M: Middleware> {
+    contract: Strsy<M>,
+    multicall: Multicall<M>,
+    client: Arc<M>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct StTrsyVault {
+    /// TRSY held, including vested rewards
+    pub total_assets: FydeAmount,
+    /// TRSY at the last update, before vesting since then
+    pub free_assets: FydeAmount,
+    pub total_supply: FydeAmount,
+    /// TRSY vested per second, scaled by `precision`
+    pub issuance_rate: U256,
+    pub precision: U256,
+    pub last_updated: u64,
+    pub vesting_period_finish: u64,
+    /// TRSY per sTRSY
+    pub exchange_rate: FydeAmount,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct StTrsyUser {
+    pub shares: FydeAmount,
+    /// TRSY redeemable for the shares
+    pub assets: FydeAmount,
+    pub max_withdraw: FydeAmount,
+    pub max_redeem: FydeAmount,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct VestingProjection {
+    pub timestamp: u64,
+    pub vesting_period_finish: u64,
+    /// Seconds left until the rewards are fully vested
+    pub remaining_seconds: u64,
+    /// TRSY left to vest
+    pub unvested_assets: FydeAmount,
+    pub vested_per_day: FydeAmount,
+    /// Yearly yield at the current issuance rate, in percent
+    pub annualized_yield: FydeAmount,
+}
+
+/// Project the vesting schedule of the vault at `timestamp`
+pub fn project_vesting(vault: &StTrsyVault, timestamp: u64) -> VestingProjection {
+    let remaining_seconds = vault.vesting_period_finish.saturating_sub(timestamp);
+    let vested = |seconds: u64| match vault.precision.is_zero() {
+        true => U256::zero(),
+        false => vault.issuance_rate.saturating_mul(U256::from(seconds)) / vault.precision,
+    };
+    let yearly = match remaining_seconds {
+        0 => U256::zero(),
+        _ => vested(SECONDS_PER_YEAR),
+    };
+    let annualized_yield = FydeAmount::new(yearly, 18)
+        .checked_mul_u64(100)
+        .and_then(|yearly| yearly.checked_div(&vault.total_assets))
+        .unwrap_or(FydeAmount::zero(18));
+
+    VestingProjection {
+        timestamp,
+        vesting_period_finish: vault.vesting_period_finish,
+        remaining_seconds,
+        unvested_assets: FydeAmount::new(vested(remaining_seconds), 18),
+        vested_per_day: match remaining_seconds {
+            0 => FydeAmount::zero(18),
+            _ => FydeAmount::new(vested(86_400), 18),
+        },
+        annualized_yield,
+    }
+}
+
+fn to_timestamp(value: U256, what: &str) -> Result<u64, DataError> {
+    u64::try_from(value).map_err(|_| DataError::out_of_range(what, EventContext::default()))
+}
+
+impl<M: Middleware> StTrsy<M> {
+    pub async fn new(client: Arc<M>, chain: Chain) -> Result<Self, FydeError<M>> {
+        let address_list: AddressList = AddressList::new(&chain);
+        Ok(Self {
+            contract: Strsy::new(address_list.strsy, client.clone()),
+            multicall: Multicall::new(client.clone(), None).await?,
+            client,
+        })
+    }
+
+    pub async fn get_vault(&self) -> Result<StTrsyVault, FydeError<M>> {
+        let mut multicall = self.multicall.clone();
+        multicall.clear_calls();
+        multicall.add_call(self.contract.total_assets(), false);
+        multicall.add_call(self.contract.free_assets(), false);
+        multicall.add_call(self.contract.total_supply(), false);
+        multicall.add_call(self.contract.issuance_rate(), false);
+        multicall.add_call(self.contract.precision(), false);
+        multicall.add_call(self.contract.last_updated(), false);
+        multicall.add_call(self.contract.vesting_period_finish(), false);
+        multicall.add_call(self.contract.convert_to_assets(U256::exp10(18)), false);
+        let (
+            total_assets,
+            free_assets,
+            total_supply,
+            issuance_rate,
+            precision,
+            last_updated,
+            vesting_period_finish,
+            exchange_rate,
+        ): (U256, U256, U256, U256, U256, U256, U256, U256) = multicall.call().await?;
+
+        Ok(StTrsyVault {
+            total_assets: FydeAmount::new(total_assets, 18),
+            free_assets: FydeAmount::new(free_assets, 18),
+            total_supply: FydeAmount::new(total_supply, 18),
+            issuance_rate,
+            precision,
+            last_updated: to_timestamp(last_updated, "stTRSY lastUpdated")?,
+            vesting_period_finish: to_timestamp(
+                vesting_period_finish,
+                "stTRSY vestingPeriodFinish",
+            )?,
+            exchange_rate: FydeAmount::new(exchange_rate, 18),
+        })
+    }
+
+    pub async fn get_user(&self, user: Address) -> Result<StTrsyUser, FydeError<M>> {
+        let mut multicall = self.multicall.clone();
+        multicall.clear_calls();
+        multicall.add_call(self.contract.balance_of(user), false);
+        multicall.add_call(self.contract.balance_of_assets(user), false);
+        multicall.add_call(self.contract.max_withdraw(user), false);
+        multicall.add_call(self.contract.max_redeem(user), false);
+        let (shares, assets, max_withdraw, max_redeem): (U256, U256, U256, U256) =
+            multicall.call().await?;
+
+        Ok(StTrsyUser {
+            shares: FydeAmount::new(shares, 18),
+            assets: FydeAmount::new(assets, 18),
+            max_withdraw: FydeAmount::new(max_withdraw, 18),
+            max_redeem: FydeAmount::new(max_redeem, 18),
+        })
+    }
+
+    pub async fn convert_to_assets(&self, shares: U256) -> Result<U256, FydeError<M>> {
+        Ok(self.contract.convert_to_assets(shares).call().await?)
+    }
+
+    pub async fn convert_to_shares(&self, assets: U256) -> Result<U256, FydeError<M>> {
+        Ok(self.contract.convert_to_shares(assets).call().await?)
+    }
+
+    /// Shares minted for depositing `assets`
+    pub async fn preview_deposit(&self, assets: U256) -> Result<U256, FydeError<M>> {
+        Ok(self.contract.preview_deposit(assets).call().await?)
+    }
+
+    /// Assets needed to mint `shares`
+    pub async fn preview_mint(&self, shares: U256) -> Result<U256, FydeError<M>> {
+        Ok(self.contract.preview_mint(shares).call().await?)
+    }
+
+    /// Assets received for redeeming `shares`
+    pub async fn preview_redeem(&self, shares: U256) -> Result<U256, FydeError<M>> {
+        Ok(self.contract.preview_redeem(shares).call().await?)
+    }
+
+    /// Shares burned for withdrawing `assets`
+    pub async fn preview_withdraw(&self, assets: U256) -> Result<U256, FydeError<M>> {
+        Ok(self.contract.preview_withdraw(assets).call().await?)
+    }
+
+    pub async fn max_deposit(&self, receiver: Address) -> Result<U256, FydeError<M>> {
+        Ok(self.contract.max_deposit(receiver).call().await?)
+    }
+
+    pub async fn max_mint(&self, receiver: Address) -> Result<U256, FydeError<M>> {
+        Ok(self.contract.max_mint(receiver).call().await?)
+    }
+
+    pub async fn max_redeem(&self, owner: Address) -> Result<U256, FydeError<M>> {
+        Ok(self.contract.max_redeem(owner).call().await?)
+    }
+
+    pub async fn max_withdraw(&self, owner: Address) -> Result<U256, FydeError<M>> {
+        Ok(self.contract.max_withdraw(owner).call().await?)
+    }
+
+    /// Vesting projection and annualized yield as of the latest block
+    pub async fn get_vesting_projection(&self) -> Result<VestingProjection, FydeError<M>> {
+        let vault = self.get_vault().await?;
+        let now = latest_timestamp(self.client.as_ref()).await?;
+        Ok(project_vesting(&vault, now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_vesting() {
+        let precision = U256::exp10(30);
+        // 1 TRSY per second on 3_153_600 TRSY, until 10_000
+        let vault = StTrsyVault {
+            total_assets: FydeAmount::new(U256::from(3_153_600) * U256::exp10(18), 18),
+            free_assets: FydeAmount::new(U256::from(3_153_600) * U256::exp10(18), 18),
+            total_supply: FydeAmount::new(U256::from(3_000_000) * U256::exp10(18), 18),
+            issuance_rate: U256::exp10(18) * precision,
+            precision,
+            last_updated: 1_000,
+            vesting_period_finish: 10_000,
+            exchange_rate: "1.0512".parse().unwrap(),
+        };
+
+        let projection = project_vesting(&vault, 4_000);
+        assert_eq!(projection.remaining_seconds, 6_000);
+        assert_eq!(projection.unvested_assets, "6000".parse().unwrap());
+        assert_eq!(projection.vested_per_day, "86400".parse().unwrap());
+        assert_eq!(projection.annualized_yield, "1000".parse().unwrap());
+
+        let finished = project_vesting(&vault, 12_000);
+        assert_eq!(finished.remaining_seconds, 0);
+        assert!(finished.unvested_assets.is_zero());
+        assert!(finished.annualized_yield.is_zero());
+    }
+}
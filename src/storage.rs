@@ -0,0 +1,678 @@
+use ethers::types::{Address, U256};
+use sqlx::{
+    any::{install_default_drivers, AnyPoolOptions},
+    AnyPool,
+};
+
+use crate::{
+    errors::DataError,
+    indexer::{IndexerEvent, IndexerState},
+    protocol_history::{FeeEvent, StakingUnstaking, UserAction, VeFydeLock},
+};
+
+/// Tables holding indexed records, all keyed by (tx_hash, log_index)
+const RECORD_TABLES: [&str; 6] = [
+    "deposits",
+    "withdrawals",
+    "swaps",
+    "staking_events",
+    "ve_fyde_locks",
+    "fee_events",
+];
+
+/// Tables of user actions, also retracted with the block of the LiquidVault event processing them
+const ACTION_TABLES: [&str; 3] = ["deposits", "withdrawals", "swaps"];
+
+/// Portable between SQLite and Postgres: amounts are decimal strings, addresses and
+/// hashes are hex strings and asset lists are JSON arrays.
+const SCHEMA: [&str; 7] = [
+    "CREATE TABLE IF NOT EXISTS deposits (
+        tx_hash TEXT NOT NULL,
+        log_index BIGINT NOT NULL,
+        block_number BIGINT NOT NULL,
+        processed_block_number BIGINT,
+        timestamp BIGINT NOT NULL,
+        request_id BIGINT NOT NULL,
+        user_address TEXT NOT NULL,
+        asset_in TEXT NOT NULL,
+        amount_in TEXT NOT NULL,
+        keep_gov_rights BOOLEAN NOT NULL,
+        minted_at_trsy_price TEXT NOT NULL,
+        usd_value_deposited TEXT NOT NULL,
+        trsy_minted TEXT NOT NULL,
+        PRIMARY KEY (tx_hash, log_index)
+    )",
+    "CREATE TABLE IF NOT EXISTS withdrawals (
+        tx_hash TEXT NOT NULL,
+        log_index BIGINT NOT NULL,
+        block_number BIGINT NOT NULL,
+        processed_block_number BIGINT,
+        timestamp BIGINT NOT NULL,
+        request_id BIGINT NOT NULL,
+        user_address TEXT NOT NULL,
+        asset_out TEXT NOT NULL,
+        amount_out TEXT NOT NULL,
+        burned_at_trsy_price TEXT NOT NULL,
+        usd_value_withdrawn TEXT NOT NULL,
+        trsy_burned TEXT NOT NULL,
+        PRIMARY KEY (tx_hash, log_index)
+    )",
+    "CREATE TABLE IF NOT EXISTS swaps (
+        tx_hash TEXT NOT NULL,
+        log_index BIGINT NOT NULL,
+        block_number BIGINT NOT NULL,
+        processed_block_number BIGINT,
+        timestamp BIGINT NOT NULL,
+        request_id BIGINT NOT NULL,
+        user_address TEXT NOT NULL,
+        asset_in TEXT NOT NULL,
+        asset_out TEXT NOT NULL,
+        amount_in TEXT NOT NULL,
+        amount_out TEXT NOT NULL,
+        PRIMARY KEY (tx_hash, log_index)
+    )",
+    "CREATE TABLE IF NOT EXISTS staking_events (
+        tx_hash TEXT NOT NULL,
+        log_index BIGINT NOT NULL,
+        block_number BIGINT NOT NULL,
+        timestamp BIGINT NOT NULL,
+        kind TEXT NOT NULL,
+        caller TEXT NOT NULL,
+        receiver TEXT NOT NULL,
+        owner TEXT,
+        assets TEXT NOT NULL,
+        shares TEXT NOT NULL,
+        PRIMARY KEY (tx_hash, log_index)
+    )",
+    "CREATE TABLE IF NOT EXISTS ve_fyde_locks (
+        tx_hash TEXT NOT NULL,
+        log_index BIGINT NOT NULL,
+        block_number BIGINT NOT NULL,
+        kind TEXT NOT NULL,
+        user_address TEXT NOT NULL,
+        amount TEXT NOT NULL,
+        expiry BIGINT,
+        PRIMARY KEY (tx_hash, log_index)
+    )",
+    "CREATE TABLE IF NOT EXISTS fee_events (
+        tx_hash TEXT NOT NULL,
+        log_index BIGINT NOT NULL,
+        block_number BIGINT NOT NULL,
+        kind TEXT NOT NULL,
+        recipient TEXT,
+        trsy_amount TEXT NOT NULL,
+        PRIMARY KEY (tx_hash, log_index)
+    )",
+    "CREATE TABLE IF NOT EXISTS sync_state (
+        name TEXT PRIMARY KEY,
+        block_number BIGINT NOT NULL,
+        state TEXT NOT NULL
+    )",
+];
+
+/// `sync_state` row of the indexer, its `block_number` is the next block to index
+const INDEXER_STATE: &str = "indexer";
+
+/// SQLite or Postgres store of indexed records, picked from the connection url
+/// (`sqlite://fyde.db`, `postgres://user@host/fyde`).
+/// Every write is an upsert, so re-indexing a block range is harmless.
+pub struct Storage {
+    pool: AnyPool,
+}
+
+impl Storage {
+    /// Connect and create the missing tables
+    pub async fn connect(url: &str) -> Result<Self, DataError> {
+        install_default_drivers();
+        let pool = AnyPoolOptions::new().connect(url).await?;
+        Self::from_pool(pool).await
+    }
+
+    pub async fn from_pool(pool: AnyPool) -> Result<Self, DataError> {
+        for statement in SCHEMA {
+            sqlx::query(statement).execute(&pool).await?;
+        }
+        Ok(Self { pool })
+    }
+
+    /// Upsert a user action, with the block of the LiquidVault event processing it when known
+    pub async fn upsert_user_action(
+        &self,
+        action: &UserAction,
+        processed_block: Option<u64>,
+    ) -> Result<(), DataError> {
+        let processed_block = processed_block.map(|block_number| block_number as i64);
+        match action {
+            UserAction::Deposit {
+                tx_hash,
+                log_index,
+                block_number,
+                timestamp,
+                request_id,
+                user,
+                asset_in,
+                amount_in,
+                keep_gov_rights,
+                minted_at_trsy_price,
+                usd_value_deposited,
+                trsy_minted,
+                ..
+            } => {
+                let sql = upsert_sql(
+                    "deposits",
+                    &[
+                        "tx_hash",
+                        "log_index",
+                        "block_number",
+                        "processed_block_number",
+                        "timestamp",
+                        "request_id",
+                        "user_address",
+                        "asset_in",
+                        "amount_in",
+                        "keep_gov_rights",
+                        "minted_at_trsy_price",
+                        "usd_value_deposited",
+                        "trsy_minted",
+                    ],
+                );
+                sqlx::query(&sql)
+                    .bind(hex(tx_hash))
+                    .bind(*log_index as i64)
+                    .bind(*block_number as i64)
+                    .bind(processed_block)
+                    .bind(*timestamp as i64)
+                    .bind(*request_id as i64)
+                    .bind(hex(user))
+                    .bind(addresses_json(asset_in)?)
+                    .bind(amounts_json(amount_in)?)
+                    .bind(*keep_gov_rights)
+                    .bind(minted_at_trsy_price.to_string())
+                    .bind(usd_value_deposited.to_string())
+                    .bind(trsy_minted.to_string())
+                    .execute(&self.pool)
+                    .await?;
+            }
+            UserAction::Withdraw {
+                tx_hash,
+                log_index,
+                block_number,
+                timestamp,
+                request_id,
+                user,
+                asset_out,
+                amount_out,
+                burned_at_trsy_price,
+                usd_value_withdrawn,
+                trsy_burned,
+                ..
+            } => {
+                let sql = upsert_sql(
+                    "withdrawals",
+                    &[
+                        "tx_hash",
+                        "log_index",
+                        "block_number",
+                        "processed_block_number",
+                        "timestamp",
+                        "request_id",
+                        "user_address",
+                        "asset_out",
+                        "amount_out",
+                        "burned_at_trsy_price",
+                        "usd_value_withdrawn",
+                        "trsy_burned",
+                    ],
+                );
+                sqlx::query(&sql)
+                    .bind(hex(tx_hash))
+                    .bind(*log_index as i64)
+                    .bind(*block_number as i64)
+                    .bind(processed_block)
+                    .bind(*timestamp as i64)
+                    .bind(*request_id as i64)
+                    .bind(hex(user))
+                    .bind(addresses_json(asset_out)?)
+                    .bind(amounts_json(amount_out)?)
+                    .bind(burned_at_trsy_price.to_string())
+                    .bind(usd_value_withdrawn.to_string())
+                    .bind(trsy_burned.to_string())
+                    .execute(&self.pool)
+                    .await?;
+            }
+            UserAction::Swap {
+                tx_hash,
+                log_index,
+                block_number,
+                timestamp,
+                request_id,
+                user,
+                asset_in,
+                asset_out,
+                amount_in,
+                amount_out,
+                ..
+            } => {
+                let sql = upsert_sql(
+                    "swaps",
+                    &[
+                        "tx_hash",
+                        "log_index",
+                        "block_number",
+                        "processed_block_number",
+                        "timestamp",
+                        "request_id",
+                        "user_address",
+                        "asset_in",
+                        "asset_out",
+                        "amount_in",
+                        "amount_out",
+                    ],
+                );
+                sqlx::query(&sql)
+                    .bind(hex(tx_hash))
+                    .bind(*log_index as i64)
+                    .bind(*block_number as i64)
+                    .bind(processed_block)
+                    .bind(*timestamp as i64)
+                    .bind(*request_id as i64)
+                    .bind(hex(user))
+                    .bind(hex(asset_in))
+                    .bind(hex(asset_out))
+                    .bind(amount_in.to_string())
+                    .bind(amount_out.to_string())
+                    .execute(&self.pool)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn upsert_staking_unstaking(
+        &self,
+        record: &StakingUnstaking,
+    ) -> Result<(), DataError> {
+        let (kind, caller, receiver, owner, assets, shares, block_number, timestamp) = match record
+        {
+            StakingUnstaking::Staking {
+                caller,
+                receiver,
+                assets,
+                shares,
+                block_number,
+                timestamp,
+                ..
+            } => (
+                "staking",
+                caller,
+                receiver,
+                None,
+                assets,
+                shares,
+                block_number,
+                timestamp,
+            ),
+            StakingUnstaking::Unstaking {
+                caller,
+                receiver,
+                owner,
+                assets,
+                shares,
+                block_number,
+                timestamp,
+                ..
+            } => (
+                "unstaking",
+                caller,
+                receiver,
+                Some(hex(owner)),
+                assets,
+                shares,
+                block_number,
+                timestamp,
+            ),
+        };
+        let sql = upsert_sql(
+            "staking_events",
+            &[
+                "tx_hash",
+                "log_index",
+                "block_number",
+                "timestamp",
+                "kind",
+                "caller",
+                "receiver",
+                "owner",
+                "assets",
+                "shares",
+            ],
+        );
+        sqlx::query(&sql)
+            .bind(hex(&record.tx_hash()))
+            .bind(record.log_index() as i64)
+            .bind(*block_number as i64)
+            .bind(*timestamp as i64)
+            .bind(kind)
+            .bind(hex(caller))
+            .bind(hex(receiver))
+            .bind(owner)
+            .bind(assets.to_string())
+            .bind(shares.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn upsert_ve_fyde_lock(&self, lock: &VeFydeLock) -> Result<(), DataError> {
+        let kind = match lock.expiry {
+            Some(_) => "update_lock",
+            None => "withdraw",
+        };
+        let sql = upsert_sql(
+            "ve_fyde_locks",
+            &[
+                "tx_hash",
+                "log_index",
+                "block_number",
+                "kind",
+                "user_address",
+                "amount",
+                "expiry",
+            ],
+        );
+        sqlx::query(&sql)
+            .bind(hex(&lock.tx_hash))
+            .bind(lock.log_index as i64)
+            .bind(lock.block_number as i64)
+            .bind(kind)
+            .bind(hex(&lock.user))
+            .bind(lock.amount.to_string())
+            .bind(lock.expiry.map(|expiry| expiry as i64))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn upsert_fee_event(&self, fee: &FeeEvent) -> Result<(), DataError> {
+        let kind = match fee.recipient {
+            Some(_) => "fees_collected",
+            None => "management_fee",
+        };
+        let sql = upsert_sql(
+            "fee_events",
+            &[
+                "tx_hash",
+                "log_index",
+                "block_number",
+                "kind",
+                "recipient",
+                "trsy_amount",
+            ],
+        );
+        sqlx::query(&sql)
+            .bind(hex(&fee.tx_hash))
+            .bind(fee.log_index as i64)
+            .bind(fee.block_number as i64)
+            .bind(kind)
+            .bind(fee.recipient.as_ref().map(hex))
+            .bind(fee.trsy_amount.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Delete the records of a reorged out block, and the user actions it processed
+    pub async fn retract_block(&self, block_number: u64) -> Result<(), DataError> {
+        for table in RECORD_TABLES {
+            let condition = match ACTION_TABLES.contains(&table) {
+                true => "block_number = $1 OR processed_block_number = $1",
+                false => "block_number = $1",
+            };
+            sqlx::query(&format!("DELETE FROM {} WHERE {}", table, condition))
+                .bind(block_number as i64)
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Store an indexer event, rolling back the retracted blocks
+    pub async fn apply(&self, event: &IndexerEvent) -> Result<(), DataError> {
+        match event {
+            IndexerEvent::UserAction {
+                action, processed, ..
+            } => {
+                self.upsert_user_action(action, Some(processed.number))
+                    .await
+            }
+            IndexerEvent::StakingUnstaking { record, .. } => {
+                self.upsert_staking_unstaking(record).await
+            }
+            IndexerEvent::VeFydeLock { lock, .. } => self.upsert_ve_fyde_lock(lock).await,
+            IndexerEvent::Fee { fee, .. } => self.upsert_fee_event(fee).await,
+            IndexerEvent::Retracted(block) => self.retract_block(block.number).await,
+        }
+    }
+
+    /// Last block indexed by the saved indexer state
+    pub async fn last_indexed_block(&self) -> Result<Option<u64>, DataError> {
+        Ok(self
+            .indexer_state()
+            .await?
+            .and_then(|state| state.next_block.checked_sub(1)))
+    }
+
+    /// Indexer state saved by [`Storage::save_indexer_state`], to restart the indexer
+    /// with [`Indexer::with_state`](crate::indexer::Indexer::with_state)
+    pub async fn indexer_state(&self) -> Result<Option<IndexerState>, DataError> {
+        let state: Option<String> =
+            sqlx::query_scalar("SELECT state FROM sync_state WHERE name = $1")
+                .bind(INDEXER_STATE)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(state
+            .map(|state| serde_json::from_str(&state))
+            .transpose()?)
+    }
+
+    /// Save the indexer state after applying the events of a poll. Its pending requests
+    /// and recent block hashes are kept, so a restart neither misses the processing of a
+    /// request made before the cursor nor a reorg of an indexed block.
+    pub async fn save_indexer_state(&self, state: &IndexerState) -> Result<(), DataError> {
+        sqlx::query(
+            "INSERT INTO sync_state (name, block_number, state) VALUES ($1, $2, $3)
+            ON CONFLICT (name) DO UPDATE SET
+                block_number = excluded.block_number, state = excluded.state",
+        )
+        .bind(INDEXER_STATE)
+        .bind(state.next_block as i64)
+        .bind(serde_json::to_string(state)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Number of rows of a record table, mostly useful to check a sync
+    pub async fn count(&self, table: &str) -> Result<u64, DataError> {
+        if !RECORD_TABLES.contains(&table) {
+            return Err(DataError::missing(
+                format!("table {}", table),
+                Default::default(),
+            ));
+        }
+        let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count as u64)
+    }
+}
+
+/// Insert a row, or update every column but the key when (tx_hash, log_index) exists
+fn upsert_sql(table: &str, columns: &[&str]) -> String {
+    let placeholders: Vec<String> = (1..=columns.len()).map(|i| format!("${}", i)).collect();
+    let updates: Vec<String> = columns
+        .iter()
+        .filter(|column| !["tx_hash", "log_index"].contains(column))
+        .map(|column| format!("{} = excluded.{}", column, column))
+        .collect();
+    format!(
+        "INSERT INTO {} ({}) VALUES ({}) ON CONFLICT (tx_hash, log_index) DO UPDATE SET {}",
+        table,
+        columns.join(", "),
+        placeholders.join(", "),
+        updates.join(", ")
+    )
+}
+
+fn hex<T: std::fmt::Debug>(value: &T) -> String {
+    format!("{:?}", value)
+}
+
+fn addresses_json(addresses: &[Address]) -> Result<String, DataError> {
+    Ok(serde_json::to_string(
+        &addresses.iter().map(hex).collect::<Vec<String>>(),
+    )?)
+}
+
+fn amounts_json(amounts: &[U256]) -> Result<String, DataError> {
+    Ok(serde_json::to_string(
+        &amounts.iter().map(U256::to_string).collect::<Vec<String>>(),
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::BlockRef;
+    use ethers::types::H256;
+
+    async fn memory_storage() -> Storage {
+        install_default_drivers();
+        // Each SQLite in-memory connection is its own database
+        let pool = AnyPoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        Storage::from_pool(pool).await.unwrap()
+    }
+
+    fn swap(block_number: u32, amount_out: u64) -> UserAction {
+        UserAction::Swap {
+            tx_hash: H256::from_low_u64_be(1),
+            log_index: 3,
+            block_number,
+            block_hash: H256::from_low_u64_be(block_number as u64),
+            timestamp: 1_700_000_000,
+            request_id: 7,
+            user: Address::from_low_u64_be(2),
+            asset_in: Address::from_low_u64_be(3),
+            asset_out: Address::from_low_u64_be(4),
+            amount_in: U256::from(100),
+            amount_out: U256::from(amount_out),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upsert_is_idempotent() {
+        let storage = memory_storage().await;
+        storage
+            .upsert_user_action(&swap(10, 99), Some(12))
+            .await
+            .unwrap();
+        storage
+            .upsert_user_action(&swap(10, 98), Some(12))
+            .await
+            .unwrap();
+        assert_eq!(storage.count("swaps").await.unwrap(), 1);
+
+        let amount_out: String = sqlx::query_scalar("SELECT amount_out FROM swaps")
+            .fetch_one(&storage.pool)
+            .await
+            .unwrap();
+        assert_eq!(amount_out, "98");
+
+        storage.retract_block(10).await.unwrap();
+        assert_eq!(storage.count("swaps").await.unwrap(), 0);
+
+        // Reorg of the block processing the request
+        storage
+            .upsert_user_action(&swap(10, 98), Some(12))
+            .await
+            .unwrap();
+        storage.retract_block(12).await.unwrap();
+        assert_eq!(storage.count("swaps").await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_apply_fee_event() {
+        let storage = memory_storage().await;
+        let block = BlockRef {
+            number: 20,
+            hash: H256::from_low_u64_be(20),
+        };
+        let fee = FeeEvent {
+            tx_hash: H256::from_low_u64_be(5),
+            log_index: 1,
+            block_number: 20,
+            block_hash: H256::from_low_u64_be(20),
+            recipient: None,
+            trsy_amount: U256::from(1_000),
+        };
+        storage
+            .apply(&IndexerEvent::Fee { block, fee })
+            .await
+            .unwrap();
+        assert_eq!(storage.count("fee_events").await.unwrap(), 1);
+
+        storage
+            .apply(&IndexerEvent::Retracted(block))
+            .await
+            .unwrap();
+        assert_eq!(storage.count("fee_events").await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_indexer_state() {
+        let storage = memory_storage().await;
+        assert_eq!(storage.indexer_state().await.unwrap(), None);
+        assert_eq!(storage.last_indexed_block().await.unwrap(), None);
+
+        // A swap requested at block 90, still waiting for the keeper at block 120
+        let pending = serde_json::from_value(serde_json::json!({
+            "pending": {
+                "7": {
+                    "kind": "Swap",
+                    "tx_hash": H256::from_low_u64_be(1),
+                    "block_number": 90,
+                    "block_hash": H256::from_low_u64_be(90),
+                    "log_index": 3,
+                    "timestamp": 1_700_000_000,
+                    "request_id": 7,
+                    "requestor": Address::from_low_u64_be(2),
+                    "asset_in": [Address::from_low_u64_be(3)],
+                    "asset_out": [Address::from_low_u64_be(4)],
+                    "amount_in": [U256::from(100)],
+                    "amount_out": [],
+                    "keep_gov_rights": false
+                }
+            },
+            "processed": {}
+        }))
+        .unwrap();
+        let mut state = IndexerState {
+            next_block: 101,
+            recent_blocks: [(100, H256::from_low_u64_be(100))].into(),
+            pending,
+        };
+        storage.save_indexer_state(&state).await.unwrap();
+        state.next_block = 121;
+        state.recent_blocks.insert(120, H256::from_low_u64_be(120));
+        storage.save_indexer_state(&state).await.unwrap();
+
+        let restored = storage.indexer_state().await.unwrap().unwrap();
+        assert_eq!(restored, state);
+        assert_eq!(restored.pending.earliest_block(), Some(90));
+        assert_eq!(storage.last_indexed_block().await.unwrap(), Some(120));
+    }
+}
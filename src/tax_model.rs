@@ -0,0 +1,810 @@
+use ethers::{
+    providers::Middleware,
+    types::{Address, BlockId, BlockNumber, I256, U256},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    errors::FydeError,
+    quoter::{effective_price, AssetQuote, Quote, SwapQuote},
+    AddressList, Chain, LiquidVaultContract, TaxModuleContract,
+};
+
+/// Precision of `taxFactor*` and `flatTaxRate*` in the TaxModule
+pub const TAX_PRECISION: u64 = 1_000_000;
+
+/// State of an asset needed to price a request offline
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AssetTaxState {
+    /// Target concentration above which deposits are taxed (18 decimals)
+    pub target_conc_deposit: U256,
+    /// Target concentration below which withdraws are taxed (18 decimals)
+    pub target_conc_withdraw: U256,
+    /// USD value of the asset held by the protocol (18 decimals)
+    pub asset_aum: U256,
+    /// USD price of one unit of the asset (18 decimals)
+    pub price: U256,
+    pub decimals: u8,
+    /// Share of the USD value swapped in paid out as an incentive (18 decimals), a negative
+    /// factor adding to the tax
+    #[serde(default)]
+    pub incentive_factor: i128,
+}
+
+/// Pure-Rust model of the TaxModule pricing curve.
+///
+/// The model is a snapshot: fetch it once with [`TaxModel::fetch`] and price as many
+/// candidate requests as needed without any RPC call.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TaxModel {
+    pub tax_factor_deposit: u32,
+    pub tax_factor_withdraw: u32,
+    pub flat_tax_rate_deposit: u32,
+    pub flat_tax_rate_withdraw: u32,
+    pub flat_tax_rate_swap: u32,
+    /// Maximum incentive of a swap in USD (18 decimals), unset when not positive
+    #[serde(default)]
+    pub incentive_cap: i128,
+    pub protocol_aum: U256,
+    pub trsy_supply: U256,
+    pub assets: HashMap<Address, AssetTaxState>,
+}
+
+struct TaxParams {
+    target_conc: U256,
+    tax_factor: u32,
+    flat_tax_rate: u32,
+}
+
+/// `a * b / c`, none on overflow or a zero `c`
+fn mul_div(a: U256, b: U256, c: U256) -> Option<U256> {
+    a.checked_mul(b)?.checked_div(c)
+}
+
+fn overflow<M: Middleware>(what: &str) -> FydeError<M> {
+    FydeError::ArithmeticError(format!("{} overflow", what))
+}
+
+impl TaxModel {
+    pub async fn fetch<M: Middleware + 'static>(
+        provider: Arc<M>,
+        chain: Chain,
+        assets: &[Address],
+    ) -> Result<Self, FydeError<M>> {
+        Self::fetch_at(provider, chain, assets, BlockNumber::Latest.into()).await
+    }
+
+    /// Snapshot of the model at `block`
+    pub async fn fetch_at<M: Middleware + 'static>(
+        provider: Arc<M>,
+        chain: Chain,
+        assets: &[Address],
+        block: BlockId,
+    ) -> Result<Self, FydeError<M>> {
+        let address_list: AddressList = AddressList::new(&chain);
+        let tax_module = TaxModuleContract::new(address_list.tax_module, provider.clone());
+        let liquid_vault = LiquidVaultContract::new(address_list.liquid_vault, provider.clone());
+
+        let mut model = Self {
+            tax_factor_deposit: tax_module.tax_factor_deposit().block(block).call().await?,
+            tax_factor_withdraw: tax_module.tax_factor_withdraw().block(block).call().await?,
+            flat_tax_rate_deposit: tax_module
+                .flat_tax_rate_deposit()
+                .block(block)
+                .call()
+                .await?,
+            flat_tax_rate_withdraw: tax_module
+                .flat_tax_rate_withdraw()
+                .block(block)
+                .call()
+                .await?,
+            flat_tax_rate_swap: tax_module.flat_tax_rate_swap().block(block).call().await?,
+            incentive_cap: liquid_vault.incentive_cap().block(block).call().await?,
+            protocol_aum: liquid_vault
+                .compute_protocol_aum()
+                .block(block)
+                .call()
+                .await?,
+            trsy_supply: liquid_vault.total_supply().block(block).call().await?,
+            assets: HashMap::new(),
+        };
+
+        for asset in assets {
+            let (target_conc_deposit, target_conc_withdraw) =
+                tax_module.tax_params(*asset).block(block).call().await?;
+            let (_, _, incentive_factor, decimals, _, _, _) =
+                liquid_vault.asset_info(*asset).block(block).call().await?;
+            let price = liquid_vault
+                .get_quote(*asset, U256::exp10(decimals as usize))
+                .block(block)
+                .call()
+                .await?;
+            let asset_aum = liquid_vault
+                .get_asset_aum(*asset)
+                .block(block)
+                .call()
+                .await?;
+            model.assets.insert(
+                *asset,
+                AssetTaxState {
+                    target_conc_deposit: target_conc_deposit.into(),
+                    target_conc_withdraw: target_conc_withdraw.into(),
+                    asset_aum,
+                    price,
+                    decimals,
+                    incentive_factor,
+                },
+            );
+        }
+
+        Ok(model)
+    }
+
+    fn asset_state<M: Middleware>(&self, asset: &Address) -> Result<&AssetTaxState, FydeError<M>> {
+        self.assets
+            .get(asset)
+            .ok_or(FydeError::UnsupportedAsset(*asset))
+    }
+
+    fn usd_value<M: Middleware>(state: &AssetTaxState, amount: U256) -> Result<U256, FydeError<M>> {
+        mul_div(amount, state.price, U256::exp10(state.decimals as usize))
+            .ok_or_else(|| overflow("USD value"))
+    }
+
+    fn convert_to_shares<M: Middleware>(&self, usd_value: U256) -> Result<U256, FydeError<M>> {
+        if self.protocol_aum.is_zero() || self.trsy_supply.is_zero() {
+            return Ok(usd_value);
+        }
+        mul_div(usd_value, self.trsy_supply, self.protocol_aum).ok_or_else(|| overflow("Shares"))
+    }
+
+    /// Tax applied to the part of a deposit pushing the asset above its target concentration.
+    /// None on overflow.
+    fn deposit_tax(
+        params: &TaxParams,
+        asset_aum: U256,
+        usd_value: U256,
+        new_protocol_aum: U256,
+    ) -> Option<(U256, U256)> {
+        let flat_tax = mul_div(usd_value, params.flat_tax_rate.into(), TAX_PRECISION.into())?;
+        let new_asset_aum = asset_aum.checked_add(usd_value)?;
+        if params.target_conc.is_zero() || new_protocol_aum.is_zero() {
+            return Some((U256::zero(), flat_tax));
+        }
+        let max_asset_aum = mul_div(params.target_conc, new_protocol_aum, U256::exp10(18))?;
+        if new_asset_aum <= max_asset_aum {
+            return Some((U256::zero(), flat_tax));
+        }
+        let taxable_amount = (new_asset_aum - max_asset_aum).min(usd_value);
+
+        // Relative deviation from the target, capped at 100%
+        let new_conc = mul_div(new_asset_aum, U256::exp10(18), new_protocol_aum)?;
+        let deviation = mul_div(
+            new_conc.saturating_sub(params.target_conc),
+            U256::exp10(18),
+            params.target_conc,
+        )?
+        .min(U256::exp10(18));
+        let tax = mul_div(
+            taxable_amount.checked_mul(params.tax_factor.into())?,
+            deviation,
+            U256::exp10(18),
+        )? / TAX_PRECISION;
+
+        Some((taxable_amount, tax.checked_add(flat_tax)?))
+    }
+
+    /// Tax applied to the part of a withdraw pulling the asset below its target concentration.
+    /// None on overflow.
+    fn withdraw_tax(
+        params: &TaxParams,
+        asset_aum: U256,
+        usd_value: U256,
+        new_protocol_aum: U256,
+    ) -> Option<(U256, U256)> {
+        let flat_tax = mul_div(usd_value, params.flat_tax_rate.into(), TAX_PRECISION.into())?;
+        let new_asset_aum = asset_aum.saturating_sub(usd_value);
+        let min_asset_aum = mul_div(params.target_conc, new_protocol_aum, U256::exp10(18))?;
+        if params.target_conc.is_zero() || new_asset_aum >= min_asset_aum {
+            return Some((U256::zero(), flat_tax));
+        }
+        let taxable_amount = (min_asset_aum - new_asset_aum).min(usd_value);
+
+        let new_conc = Self::concentration(new_asset_aum, new_protocol_aum)?;
+        let deviation = mul_div(
+            params.target_conc.saturating_sub(new_conc),
+            U256::exp10(18),
+            params.target_conc,
+        )?;
+        let tax = mul_div(
+            taxable_amount.checked_mul(params.tax_factor.into())?,
+            deviation,
+            U256::exp10(18),
+        )? / TAX_PRECISION;
+
+        Some((taxable_amount, tax.checked_add(flat_tax)?))
+    }
+
+    /// Incentive paid on a swap of `usd_in` of an asset, negative for a surcharge, within
+    /// the incentive cap
+    fn swap_incentive(&self, state_in: &AssetTaxState, usd_in: U256) -> Option<I256> {
+        let usd_in = I256::try_from(usd_in).ok()?;
+        let incentive =
+            usd_in.checked_mul(I256::from(state_in.incentive_factor))? / I256::exp10(18);
+        Some(match self.incentive_cap > 0 {
+            true => incentive.min(I256::from(self.incentive_cap)),
+            false => incentive,
+        })
+    }
+
+    fn concentration(asset_aum: U256, protocol_aum: U256) -> Option<U256> {
+        match protocol_aum.is_zero() {
+            true => Some(U256::zero()),
+            false => mul_div(asset_aum, U256::exp10(18), protocol_aum),
+        }
+    }
+
+    fn total_usd_value<M: Middleware>(
+        &self,
+        assets: &[Address],
+        amounts: &[U256],
+    ) -> Result<(Vec<U256>, U256), FydeError<M>> {
+        if assets.len() != amounts.len() {
+            return Err(FydeError::InvalidRequest(String::from(
+                "Assets and amounts length mismatch",
+            )));
+        }
+        let mut usd_values = vec![];
+        let mut total = U256::zero();
+        for (asset, amount) in assets.iter().zip(amounts) {
+            let usd_value = Self::usd_value(self.asset_state(asset)?, *amount)?;
+            total = total
+                .checked_add(usd_value)
+                .ok_or_else(|| overflow("Request USD value"))?;
+            usd_values.push(usd_value);
+        }
+        Ok((usd_values, total))
+    }
+
+    pub fn quote_deposit<M: Middleware>(
+        &self,
+        assets: &[Address],
+        amounts: &[U256],
+    ) -> Result<Quote, FydeError<M>> {
+        let (usd_values, total_usd_deposit) = self.total_usd_value(assets, amounts)?;
+        let new_protocol_aum = self
+            .protocol_aum
+            .checked_add(total_usd_deposit)
+            .ok_or_else(|| overflow("Protocol AUM"))?;
+
+        let mut quote = Quote {
+            trsy_amount: U256::zero(),
+            usd_value: total_usd_deposit,
+            tax_in_trsy: U256::zero(),
+            effective_price: U256::zero(),
+            assets: vec![],
+        };
+        for ((asset, amount), usd_value) in assets.iter().zip(amounts).zip(usd_values) {
+            let state = self.asset_state(asset)?;
+            let params = TaxParams {
+                target_conc: state.target_conc_deposit,
+                tax_factor: self.tax_factor_deposit,
+                flat_tax_rate: self.flat_tax_rate_deposit,
+            };
+            let (taxable_amount, tax_in_usd) =
+                Self::deposit_tax(&params, state.asset_aum, usd_value, new_protocol_aum)
+                    .ok_or_else(|| overflow("Deposit tax"))?;
+            let tax_in_usd = tax_in_usd.min(usd_value);
+            let trsy_before_tax = self.convert_to_shares(usd_value)?;
+            let trsy_after_tax = self.convert_to_shares(usd_value - tax_in_usd)?;
+
+            quote.trsy_amount += trsy_after_tax;
+            quote.tax_in_trsy += trsy_before_tax - trsy_after_tax;
+            quote.assets.push(AssetQuote {
+                asset: *asset,
+                amount: *amount,
+                target_concentration: state.target_conc_deposit,
+                current_concentration: Self::concentration(state.asset_aum, self.protocol_aum)
+                    .ok_or_else(|| overflow("Concentration"))?,
+                usd_value,
+                taxable_amount,
+                tax_in_usd,
+                trsy_before_tax,
+                trsy_after_tax,
+            });
+        }
+        quote.effective_price = effective_price(quote.usd_value, quote.trsy_amount);
+
+        Ok(quote)
+    }
+
+    pub fn quote_withdraw<M: Middleware>(
+        &self,
+        assets: &[Address],
+        amounts: &[U256],
+    ) -> Result<Quote, FydeError<M>> {
+        let (usd_values, total_usd_withdraw) = self.total_usd_value(assets, amounts)?;
+        let new_protocol_aum = self.protocol_aum.saturating_sub(total_usd_withdraw);
+
+        let mut quote = Quote {
+            trsy_amount: U256::zero(),
+            usd_value: total_usd_withdraw,
+            tax_in_trsy: U256::zero(),
+            effective_price: U256::zero(),
+            assets: vec![],
+        };
+        for ((asset, amount), usd_value) in assets.iter().zip(amounts).zip(usd_values) {
+            let state = self.asset_state(asset)?;
+            let params = TaxParams {
+                target_conc: state.target_conc_withdraw,
+                tax_factor: self.tax_factor_withdraw,
+                flat_tax_rate: self.flat_tax_rate_withdraw,
+            };
+            let (taxable_amount, tax_in_usd) =
+                Self::withdraw_tax(&params, state.asset_aum, usd_value, new_protocol_aum)
+                    .ok_or_else(|| overflow("Withdraw tax"))?;
+            let trsy_before_tax = self.convert_to_shares(usd_value)?;
+            let trsy_after_tax = self.convert_to_shares(
+                usd_value
+                    .checked_add(tax_in_usd)
+                    .ok_or_else(|| overflow("Withdraw tax"))?,
+            )?;
+
+            quote.trsy_amount += trsy_after_tax;
+            quote.tax_in_trsy += trsy_after_tax - trsy_before_tax;
+            quote.assets.push(AssetQuote {
+                asset: *asset,
+                amount: *amount,
+                target_concentration: state.target_conc_withdraw,
+                current_concentration: Self::concentration(state.asset_aum, self.protocol_aum)
+                    .ok_or_else(|| overflow("Concentration"))?,
+                usd_value,
+                taxable_amount,
+                tax_in_usd,
+                trsy_before_tax,
+                trsy_after_tax,
+            });
+        }
+        quote.effective_price = effective_price(quote.usd_value, quote.trsy_amount);
+
+        Ok(quote)
+    }
+
+    /// Amount of `asset_out` received for `amount_in` of `asset_in`, with the tax in USD,
+    /// in the shape of [`Quoter::quote_swap`](crate::quoter::Quoter::quote_swap). The tax
+    /// is negative when the incentive on `asset_in` exceeds the taxes of both legs.
+    pub fn quote_swap<M: Middleware>(
+        &self,
+        asset_in: Address,
+        amount_in: U256,
+        asset_out: Address,
+    ) -> Result<SwapQuote, FydeError<M>> {
+        let state_in = self.asset_state(&asset_in)?;
+        let state_out = self.asset_state(&asset_out)?;
+        let usd_in = Self::usd_value(state_in, amount_in)?;
+
+        let params_in = TaxParams {
+            target_conc: state_in.target_conc_deposit,
+            tax_factor: self.tax_factor_deposit,
+            flat_tax_rate: self.flat_tax_rate_swap,
+        };
+        let (_, tax_in) =
+            Self::deposit_tax(&params_in, state_in.asset_aum, usd_in, self.protocol_aum)
+                .ok_or_else(|| overflow("Swap tax"))?;
+
+        let params_out = TaxParams {
+            target_conc: state_out.target_conc_withdraw,
+            tax_factor: self.tax_factor_withdraw,
+            flat_tax_rate: 0,
+        };
+        let (_, tax_out) = Self::withdraw_tax(
+            &params_out,
+            state_out.asset_aum,
+            usd_in.saturating_sub(tax_in),
+            self.protocol_aum,
+        )
+        .ok_or_else(|| overflow("Swap tax"))?;
+
+        let incentive = self
+            .swap_incentive(state_in, usd_in)
+            .ok_or_else(|| overflow("Swap incentive"))?;
+        let tax_in_usd = tax_in
+            .checked_add(tax_out)
+            .and_then(|tax| I256::try_from(tax).ok())
+            .and_then(|tax| tax.checked_sub(incentive))
+            .ok_or_else(|| overflow("Swap tax"))?;
+        let usd_out = match tax_in_usd.is_negative() {
+            true => usd_in.checked_add(tax_in_usd.unsigned_abs()),
+            false => Some(usd_in.saturating_sub(tax_in_usd.unsigned_abs())),
+        }
+        .ok_or_else(|| overflow("Swap USD value"))?;
+
+        if state_out.price.is_zero() {
+            return Err(FydeError::InvalidRequest(format!(
+                "No price for asset {:?}",
+                asset_out
+            )));
+        }
+
+        Ok(SwapQuote {
+            asset_in,
+            amount_in,
+            asset_out,
+            amount_out: mul_div(
+                usd_out,
+                U256::exp10(state_out.decimals as usize),
+                state_out.price,
+            )
+            .ok_or_else(|| overflow("Swap amount out"))?,
+            tax_in_usd,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::liquid_vault_contract::RequestData;
+    use ethers::providers::{Http, Provider};
+
+    type Error = FydeError<Provider<Http>>;
+
+    /// TaxModule outputs recorded at a block, with the model snapshot of that block
+    #[derive(Debug, Serialize, Deserialize)]
+    struct QuoteFixture {
+        block: u64,
+        model: TaxModel,
+        deposits: Vec<RecordedQuote>,
+        withdraws: Vec<RecordedQuote>,
+        swaps: Vec<RecordedSwap>,
+    }
+
+    /// Inputs and outputs of `getProcessParamDeposit` or `getProcessParamWithdraw`
+    #[derive(Debug, Serialize, Deserialize)]
+    struct RecordedQuote {
+        asset: Address,
+        amount: U256,
+        trsy_amount: U256,
+        tax_in_trsy: U256,
+        usd_value: U256,
+        taxable_amount: U256,
+        tax_in_usd: U256,
+    }
+
+    /// Inputs and outputs of `getSwapAmountOut`
+    #[derive(Debug, Serialize, Deserialize)]
+    struct RecordedSwap {
+        asset_in: Address,
+        amount_in: U256,
+        asset_out: Address,
+        amount_out: U256,
+        tax_in_usd: I256,
+    }
+
+    const FIXTURES_PATH: &str = "src/fixtures/tax_quotes.json";
+
+    fn check_quote(quote: &Quote, recorded: &RecordedQuote, what: &str, block: u64) {
+        assert_eq!(
+            (
+                quote.trsy_amount,
+                quote.tax_in_trsy,
+                quote.usd_value,
+                quote.assets[0].taxable_amount,
+                quote.assets[0].tax_in_usd
+            ),
+            (
+                recorded.trsy_amount,
+                recorded.tax_in_trsy,
+                recorded.usd_value,
+                recorded.taxable_amount,
+                recorded.tax_in_usd
+            ),
+            "{} of {} {:?} at block {}",
+            what,
+            recorded.amount,
+            recorded.asset,
+            block
+        );
+    }
+
+    fn check_fixture(fixture: &QuoteFixture) -> Result<(), Error> {
+        let model = &fixture.model;
+        for recorded in &fixture.deposits {
+            let quote =
+                model.quote_deposit::<Provider<Http>>(&[recorded.asset], &[recorded.amount])?;
+            check_quote(&quote, recorded, "deposit", fixture.block);
+        }
+        for recorded in &fixture.withdraws {
+            let quote =
+                model.quote_withdraw::<Provider<Http>>(&[recorded.asset], &[recorded.amount])?;
+            check_quote(&quote, recorded, "withdraw", fixture.block);
+        }
+        for recorded in &fixture.swaps {
+            let quote = model.quote_swap::<Provider<Http>>(
+                recorded.asset_in,
+                recorded.amount_in,
+                recorded.asset_out,
+            )?;
+            assert_eq!(
+                (quote.amount_out, quote.tax_in_usd),
+                (recorded.amount_out, recorded.tax_in_usd),
+                "swap of {} {:?} to {:?} at block {}",
+                recorded.amount_in,
+                recorded.asset_in,
+                recorded.asset_out,
+                fixture.block
+            );
+        }
+        Ok(())
+    }
+
+    fn e18(n: u64) -> U256 {
+        U256::from(n) * U256::exp10(18)
+    }
+
+    fn model() -> (TaxModel, Address, Address) {
+        let weth = Address::from_low_u64_be(1);
+        let usdc = Address::from_low_u64_be(2);
+        let mut assets = HashMap::new();
+        // 40% target, $400k in protocol, $2000 per unit
+        assets.insert(
+            weth,
+            AssetTaxState {
+                target_conc_deposit: U256::exp10(17) * 4,
+                target_conc_withdraw: U256::exp10(17) * 3,
+                asset_aum: e18(400_000),
+                price: e18(2_000),
+                decimals: 18,
+                incentive_factor: 0,
+            },
+        );
+        // 60% target, $600k in protocol, $1 per unit
+        assets.insert(
+            usdc,
+            AssetTaxState {
+                target_conc_deposit: U256::exp10(17) * 7,
+                target_conc_withdraw: U256::exp10(17) * 5,
+                asset_aum: e18(600_000),
+                price: e18(1),
+                decimals: 6,
+                incentive_factor: 0,
+            },
+        );
+        let model = TaxModel {
+            tax_factor_deposit: 500_000,
+            tax_factor_withdraw: 500_000,
+            flat_tax_rate_deposit: 0,
+            flat_tax_rate_withdraw: 0,
+            flat_tax_rate_swap: 1_000,
+            incentive_cap: 0,
+            protocol_aum: e18(1_000_000),
+            trsy_supply: e18(500_000),
+            assets,
+        };
+        (model, weth, usdc)
+    }
+
+    #[test]
+    fn test_deposit_in_range_is_not_taxed() -> Result<(), Error> {
+        let (model, _, usdc) = model();
+        let quote =
+            model.quote_deposit::<Provider<Http>>(&[usdc], &[U256::from(100_000_000_000u64)])?;
+
+        assert_eq!(quote.usd_value, e18(100_000));
+        assert_eq!(quote.tax_in_trsy, U256::zero());
+        assert_eq!(quote.trsy_amount, e18(50_000));
+        assert_eq!(quote.effective_price, e18(2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_deposit_above_target_is_taxed() -> Result<(), Error> {
+        let (model, weth, _) = model();
+        // $200k of WETH: new AUM $1.2M, max WETH AUM $480k, $120k taxable.
+        // New concentration 50%, 25% above target, tax = 120k * 0.5 * 0.25 = $15k
+        let quote = model.quote_deposit::<Provider<Http>>(&[weth], &[e18(100)])?;
+
+        assert_eq!(quote.assets[0].taxable_amount, e18(120_000));
+        assert_eq!(quote.assets[0].tax_in_usd, e18(15_000));
+        assert_eq!(quote.trsy_amount, e18(92_500));
+        assert_eq!(quote.tax_in_trsy, e18(7_500));
+        Ok(())
+    }
+
+    #[test]
+    fn test_withdraw_below_target_is_taxed() -> Result<(), Error> {
+        let (model, weth, _) = model();
+        // $200k of WETH: new AUM $800k, min WETH AUM $240k, $40k taxable.
+        // New concentration 25%, 1/6 below target, tax = 40k * 0.5 / 6
+        let quote = model.quote_withdraw::<Provider<Http>>(&[weth], &[e18(100)])?;
+
+        assert_eq!(quote.assets[0].taxable_amount, e18(40_000));
+        let expected_tax =
+            e18(40_000) * 500_000u64 * (U256::exp10(18) / 6) / TAX_PRECISION / U256::exp10(18);
+        assert_eq!(quote.assets[0].tax_in_usd, expected_tax);
+        assert_eq!(
+            quote.trsy_amount,
+            (e18(200_000) + expected_tax) * e18(500_000) / e18(1_000_000)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_swap_pays_flat_tax() -> Result<(), Error> {
+        let (model, weth, usdc) = model();
+        let quote = model.quote_swap::<Provider<Http>>(usdc, U256::from(1_000_000_000u64), weth)?;
+
+        assert_eq!(quote.tax_in_usd, I256::from_raw(e18(1)));
+        assert_eq!(quote.amount_out, e18(999) / 2_000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_swap_incentive() -> Result<(), Error> {
+        let (mut model, weth, usdc) = model();
+        // 0.5% incentive on USDC swapped in, above the 0.1% flat tax
+        model.assets.get_mut(&usdc).unwrap().incentive_factor = 5_000_000_000_000_000;
+        let quote = model.quote_swap::<Provider<Http>>(usdc, U256::from(1_000_000_000u64), weth)?;
+
+        assert_eq!(quote.tax_in_usd, -I256::from_raw(e18(4)));
+        assert_eq!(quote.amount_out, e18(1_004) / 2_000);
+
+        // Capped at $2
+        model.incentive_cap = e18(2).as_u128() as i128;
+        let quote = model.quote_swap::<Provider<Http>>(usdc, U256::from(1_000_000_000u64), weth)?;
+        assert_eq!(quote.tax_in_usd, -I256::from_raw(e18(1)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_overflow_is_an_error() {
+        let (mut model, weth, _) = model();
+        model.assets.get_mut(&weth).unwrap().price = U256::MAX;
+        let res = model.quote_deposit::<Provider<Http>>(&[weth], &[e18(1)]);
+        assert!(matches!(res, Err(FydeError::ArithmeticError(_))));
+    }
+
+    /// Model quotes must equal the TaxModule outputs recorded by
+    /// `test_model_matches_on_chain_quotes`
+    #[test]
+    fn test_model_matches_recorded_quotes() -> Result<(), Error> {
+        let fixtures: Vec<QuoteFixture> =
+            serde_json::from_str(include_str!("fixtures/tax_quotes.json")).unwrap();
+        for fixture in &fixtures {
+            check_fixture(fixture)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_asset() {
+        let (model, _, _) = model();
+        let res = model.quote_deposit::<Provider<Http>>(&[Address::zero()], &[U256::one()]);
+        assert!(matches!(res, Err(FydeError::UnsupportedAsset(_))));
+    }
+
+    /// Model and TaxModule quotes read at the same block must be equal. With
+    /// `FYDE_RECORD_TAX_FIXTURES` set, the TaxModule outputs are appended to the fixtures
+    /// replayed offline by `test_model_matches_recorded_quotes`.
+    #[tokio::test]
+    #[ignore = "needs a mainnet archive RPC"]
+    async fn test_model_matches_on_chain_quotes() -> Result<(), Error> {
+        let provider = Arc::new(
+            Provider::<Http>::try_from(
+                std::env::var("FYDE_RPC_URL").expect("FYDE_RPC_URL must be set"),
+            )
+            .expect("Failed to create provider"),
+        );
+        let chain = Chain::Mainnet;
+        let liquid_vault =
+            LiquidVaultContract::new(AddressList::new(&chain).liquid_vault, provider.clone());
+        let block_number = provider
+            .get_block_number()
+            .await
+            .map_err(FydeError::MiddlewareError)?
+            .as_u64();
+        let block: BlockId = block_number.into();
+
+        let n_assets = liquid_vault
+            .get_assets_list_length()
+            .block(block)
+            .call()
+            .await?;
+        let mut assets = vec![];
+        for n in 0..n_assets.low_u64() {
+            assets.push(
+                liquid_vault
+                    .assets_list(n.into())
+                    .block(block)
+                    .call()
+                    .await?,
+            );
+        }
+        let model = TaxModel::fetch_at(provider.clone(), chain, &assets, block).await?;
+        let mut fixture = QuoteFixture {
+            block: block_number,
+            model: model.clone(),
+            deposits: vec![],
+            withdraws: vec![],
+            swaps: vec![],
+        };
+
+        for asset in &assets {
+            let state = &model.assets[asset];
+            if state.price.is_zero() {
+                continue;
+            }
+            // A small request, one moving 20% of the asset AUM past its targets, and a
+            // deposit tripling the asset AUM to check the deviation cap
+            for usd_value in [e18(1_000), state.asset_aum / 5, state.asset_aum * 2] {
+                let amount = usd_value * U256::exp10(state.decimals as usize) / state.price;
+
+                let request = RequestData {
+                    asset_in: vec![*asset],
+                    amount_in: vec![amount],
+                    ..Default::default()
+                };
+                let (params, trsy_amount, tax_in_trsy, usd_value) = liquid_vault
+                    .get_process_param_deposit(request, model.protocol_aum)
+                    .block(block)
+                    .call()
+                    .await?;
+                fixture.deposits.push(RecordedQuote {
+                    asset: *asset,
+                    amount,
+                    trsy_amount,
+                    tax_in_trsy,
+                    usd_value,
+                    taxable_amount: params[0].taxable_amount,
+                    tax_in_usd: params[0].tax_in_usd,
+                });
+
+                let request = RequestData {
+                    asset_out: vec![*asset],
+                    amount_out: vec![amount],
+                    ..Default::default()
+                };
+                let (params, trsy_amount, _, tax_in_trsy, usd_value) = liquid_vault
+                    .get_process_param_withdraw(request, model.protocol_aum)
+                    .block(block)
+                    .call()
+                    .await?;
+                fixture.withdraws.push(RecordedQuote {
+                    asset: *asset,
+                    amount,
+                    trsy_amount,
+                    tax_in_trsy,
+                    usd_value,
+                    taxable_amount: params[0].taxable_amount,
+                    tax_in_usd: params[0].tax_in_usd,
+                });
+
+                for asset_out in assets.iter().filter(|asset_out| *asset_out != asset) {
+                    let (amount_out, tax_in_usd) = liquid_vault
+                        .get_swap_amount_out(*asset, amount, *asset_out, model.protocol_aum)
+                        .block(block)
+                        .call()
+                        .await?;
+                    fixture.swaps.push(RecordedSwap {
+                        asset_in: *asset,
+                        amount_in: amount,
+                        asset_out: *asset_out,
+                        amount_out,
+                        tax_in_usd,
+                    });
+                }
+            }
+        }
+
+        if std::env::var("FYDE_RECORD_TAX_FIXTURES").is_ok() {
+            let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(FIXTURES_PATH);
+            let mut fixtures: Vec<QuoteFixture> =
+                serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+            fixtures.push(fixture);
+            std::fs::write(&path, serde_json::to_string_pretty(&fixtures).unwrap()).unwrap();
+            return Ok(());
+        }
+        check_fixture(&fixture)
+    }
+}
@@ -0,0 +1,406 @@
+use ethers::{
+    contract::{LogMeta, Multicall},
+    providers::Middleware,
+    types::{transaction::eip2718::TypedTransaction, Address, Filter, H256, U256},
+};
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::{
+    block_cache::{BlockMetaCache, WithBlockCache},
+    errors::FydeError,
+    log_scanner::{LogScanner, ScanCursor, WithScanner},
+    utils::{latest_timestamp, FydeAmount},
+    AddressList, Chain, LiquidVaultContract, StakingTRSY, StakingTRSYEvents,
+};
+
+pub const SECONDS_PER_YEAR: u64 = 31_536_000;
+
+pub struct TrsyStaking<M: Middleware> {
+    contract: StakingTRSY<M>,
+    liquid_vault: LiquidVaultContract<M>,
+    multicall: Multicall<M>,
+    client: Arc<M>,
+    scanner: LogScanner<M>,
+    block_cache: BlockMetaCache<M>,
+    from: Option<Address>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TrsyStakingUser {
+    /// TRSY staked
+    pub balance: FydeAmount,
+    /// Rewards accrued at the last user checkpoint
+    pub rewards: FydeAmount,
+    /// Rewards earned so far, StakingTRSY has no claim entrypoint
+    pub earned: FydeAmount,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct RewardSchedule {
+    /// Rewards distributed per second
+    pub reward_rate: FydeAmount,
+    pub reward_per_token: FydeAmount,
+    pub start_date: u64,
+    pub period_finish: u64,
+    pub total_staked: FydeAmount,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct StakingApr {
+    /// Yearly rewards per TRSY staked, in percent
+    pub apr_in_trsy: FydeAmount,
+    /// Yearly rewards value per TRSY value staked, in percent
+    pub apr_in_usd: FydeAmount,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub enum TrsyStakingEvent {
+    Staked {
+        user: Address,
+        amount: U256,
+        tx_hash: H256,
+        block_number: u64,
+        log_index: u64,
+        timestamp: u64,
+    },
+    Withdrawn {
+        user: Address,
+        amount: U256,
+        tx_hash: H256,
+        block_number: u64,
+        log_index: u64,
+        timestamp: u64,
+    },
+}
+
+impl<M: Middleware> WithScanner<M> for TrsyStaking<M> {
+    fn scanner_mut(&mut self) -> &mut LogScanner<M> {
+        &mut self.scanner
+    }
+}
+
+impl<M: Middleware> WithBlockCache<M> for TrsyStaking<M> {
+    fn block_cache_mut(&mut self) -> &mut BlockMetaCache<M> {
+        &mut self.block_cache
+    }
+}
+
+impl<M: Middleware> TrsyStaking<M> {
+    pub async fn new(client: Arc<M>, chain: Chain) -> Result<Self, FydeError<M>> {
+        let address_list: AddressList = AddressList::new(&chain);
+        Ok(Self {
+            contract: StakingTRSY::new(address_list.staking_trsy, client.clone()),
+            liquid_vault: LiquidVaultContract::new(address_list.liquid_vault, client.clone()),
+            multicall: Multicall::new(client.clone(), None).await?,
+            scanner: LogScanner::new(client.clone()),
+            block_cache: BlockMetaCache::new(client.clone()),
+            client,
+            from: None,
+        })
+    }
+
+    /// Sender of the built transactions
+    pub fn from(mut self, from: Address) -> Self {
+        self.from = Some(from);
+        self
+    }
+
+    pub async fn get_user(&self, user: Address) -> Result<TrsyStakingUser, FydeError<M>> {
+        let mut multicall = self.multicall.clone();
+        multicall.clear_calls();
+        multicall.add_call(self.contract.balance_of(user), false);
+        multicall.add_call(self.contract.rewards(user), false);
+        multicall.add_call(self.contract.reward_per_token(), false);
+        let (balance, rewards, reward_per_token): (U256, U256, U256) = multicall.call().await?;
+
+        let earned = self
+            .contract
+            .earned(user, balance, reward_per_token, rewards)
+            .call()
+            .await?;
+
+        Ok(TrsyStakingUser {
+            balance: FydeAmount::new(balance, 18),
+            rewards: FydeAmount::new(rewards, 18),
+            earned: FydeAmount::new(earned, 18),
+        })
+    }
+
+    pub async fn get_reward_schedule(&self) -> Result<RewardSchedule, FydeError<M>> {
+        let mut multicall = self.multicall.clone();
+        multicall.clear_calls();
+        multicall.add_call(self.contract.reward_rate(), false);
+        multicall.add_call(self.contract.reward_per_token(), false);
+        multicall.add_call(self.contract.start_date(), false);
+        multicall.add_call(self.contract.period_finish(), false);
+        multicall.add_call(self.contract.total_supply(), false);
+        let (reward_rate, reward_per_token, start_date, period_finish, total_staked): (
+            U256,
+            U256,
+            u64,
+            u64,
+            U256,
+        ) = multicall.call().await?;
+
+        Ok(RewardSchedule {
+            reward_rate: FydeAmount::new(reward_rate, 18),
+            reward_per_token: FydeAmount::new(reward_per_token, 18),
+            start_date,
+            period_finish,
+            total_staked: FydeAmount::new(total_staked, 18),
+        })
+    }
+
+    /// Current APR, zero outside of the reward period. `reward_price` is the USD price of
+    /// the reward token, the TRSY price comes from the protocol AUM.
+    pub async fn get_apr(&self, reward_price: &FydeAmount) -> Result<StakingApr, FydeError<M>> {
+        let schedule = self.get_reward_schedule().await?;
+        let now = latest_timestamp(self.client.as_ref()).await?;
+        if now < schedule.start_date || now >= schedule.period_finish {
+            return Ok(StakingApr {
+                apr_in_trsy: FydeAmount::zero(18),
+                apr_in_usd: FydeAmount::zero(18),
+            });
+        }
+
+        let protocol_aum = self.liquid_vault.compute_protocol_aum().call().await?;
+        let trsy_supply = self.liquid_vault.total_supply().call().await?;
+        let trsy_price = FydeAmount::new(protocol_aum, 18)
+            .checked_div(&FydeAmount::new(trsy_supply, 18))
+            .ok_or(FydeError::ArithmeticError(String::from(
+                "TRSY price of an empty protocol",
+            )))?;
+
+        compute_apr(
+            &schedule.reward_rate,
+            &schedule.total_staked,
+            reward_price,
+            &trsy_price,
+        )
+        .ok_or(FydeError::ArithmeticError(String::from(
+            "APR of an empty staking pool",
+        )))
+    }
+
+    /// Staked and Withdrawn events between two blocks, with the cursor to resume the scan from.
+    pub async fn get_history(
+        &self,
+        from_block: u64,
+        to_block: Option<u64>,
+    ) -> Result<(Vec<TrsyStakingEvent>, ScanCursor), FydeError<M>> {
+        let filter = Filter::new().address(self.contract.address());
+        let scan = self.scanner.scan(&filter, from_block, to_block).await?;
+        let events: Vec<(StakingTRSYEvents, LogMeta)> = scan.decode();
+        self.block_cache
+            .prefetch(events.iter().map(|(_, meta)| meta.block_number.as_u64()))
+            .await?;
+
+        let mut history = vec![];
+        for (event, meta) in events {
+            let block_number = meta.block_number.as_u64();
+            let log_index = meta.log_index.as_u64();
+            let timestamp = self.block_cache.get_for_log(&meta).await?.timestamp;
+            match event {
+                StakingTRSYEvents::StakedFilter(ev) => history.push(TrsyStakingEvent::Staked {
+                    user: ev.user,
+                    amount: ev.amount,
+                    tx_hash: meta.transaction_hash,
+                    block_number,
+                    log_index,
+                    timestamp,
+                }),
+                StakingTRSYEvents::WithdrawnFilter(ev) => {
+                    history.push(TrsyStakingEvent::Withdrawn {
+                        user: ev.user,
+                        amount: ev.amount,
+                        tx_hash: meta.transaction_hash,
+                        block_number,
+                        log_index,
+                        timestamp,
+                    })
+                }
+                _ => {}
+            }
+        }
+
+        Ok((history, scan.cursor))
+    }
+
+    fn with_from(&self, mut tx: TypedTransaction) -> TypedTransaction {
+        if let Some(from) = self.from {
+            tx.set_from(from);
+        }
+        tx
+    }
+
+    /// Approve the staking contract to pull `amount` TRSY, needed before staking
+    pub fn approve(&self, amount: U256) -> TypedTransaction {
+        self.with_from(
+            self.liquid_vault
+                .approve(self.contract.address(), amount)
+                .tx,
+        )
+    }
+
+    pub fn stake(&self, amount: U256) -> Result<TypedTransaction, FydeError<M>> {
+        if amount.is_zero() {
+            return Err(FydeError::InvalidRequest(String::from(
+                "Cannot stake zero TRSY",
+            )));
+        }
+        Ok(self.with_from(self.contract.stake(amount).tx))
+    }
+
+    /// Withdraw staked TRSY, checking the staked balance when the sender is known
+    pub async fn withdraw(&self, amount: U256) -> Result<TypedTransaction, FydeError<M>> {
+        if amount.is_zero() {
+            return Err(FydeError::InvalidRequest(String::from(
+                "Cannot withdraw zero TRSY",
+            )));
+        }
+        if let Some(from) = self.from {
+            let balance = self.contract.balance_of(from).call().await?;
+            if amount > balance {
+                return Err(FydeError::InvalidRequest(format!(
+                    "Cannot withdraw {} TRSY, {} staked",
+                    FydeAmount::new(amount, 18),
+                    FydeAmount::new(balance, 18)
+                )));
+            }
+        }
+        Ok(self.with_from(self.contract.withdraw(amount).tx))
+    }
+
+    /// Withdraw everything without rewards, only when enabled by the owner
+    pub async fn emergency_withdraw(&self) -> Result<TypedTransaction, FydeError<M>> {
+        if !self.contract.emergency_withdraw_allowed().call().await? {
+            return Err(FydeError::InvalidRequest(String::from(
+                "Emergency withdraw is not allowed",
+            )));
+        }
+        Ok(self.with_from(self.contract.emergency_withdraw().tx))
+    }
+}
+
+/// APR of a pool distributing `reward_rate` tokens per second to `total_staked` TRSY
+pub fn compute_apr(
+    reward_rate: &FydeAmount,
+    total_staked: &FydeAmount,
+    reward_price: &FydeAmount,
+    trsy_price: &FydeAmount,
+) -> Option<StakingApr> {
+    let reward_per_year = reward_rate.checked_mul_u64(SECONDS_PER_YEAR)?;
+    let apr_in_trsy = reward_per_year
+        .checked_mul_u64(100)?
+        .checked_div(total_staked)?;
+    let apr_in_usd = apr_in_trsy
+        .checked_mul(reward_price)?
+        .checked_div(trsy_price)?;
+    Some(StakingApr {
+        apr_in_trsy,
+        apr_in_usd,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::staking_trsy::{StakedFilter, WithdrawnFilter};
+    use ethers::{
+        abi::Token,
+        contract::EthEvent,
+        providers::{MockProvider, Provider},
+        types::{Block, Log, Transaction, U64},
+    };
+
+    async fn mocked_staking() -> (TrsyStaking<Provider<MockProvider>>, MockProvider) {
+        let (provider, mock) = Provider::mocked();
+        let client = Arc::new(provider);
+        let staking = TrsyStaking {
+            contract: StakingTRSY::new(Address::random(), client.clone()),
+            liquid_vault: LiquidVaultContract::new(Address::random(), client.clone()),
+            multicall: Multicall::new(client.clone(), Some(Address::random()))
+                .await
+                .unwrap(),
+            scanner: LogScanner::new(client.clone()),
+            block_cache: BlockMetaCache::new(client.clone()).chain_id(1),
+            client,
+            from: None,
+        };
+        (staking, mock)
+    }
+
+    /// Log of an event indexed by `user`
+    fn user_log(signature: H256, user: Address, amount: u64, block: u64, log_index: u64) -> Log {
+        Log {
+            topics: vec![signature, H256::from(user)],
+            data: ethers::abi::encode(&[Token::Uint(amount.into())]).into(),
+            block_hash: Some(H256::from_low_u64_be(block)),
+            block_number: Some(U64::from(block)),
+            transaction_hash: Some(H256::from_low_u64_be(1_000 + log_index)),
+            transaction_index: Some(U64::zero()),
+            log_index: Some(U256::from(log_index)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_compute_apr() {
+        // 1 reward token per second for 3_153_600 TRSY staked: 10 tokens per TRSY per year
+        let reward_rate = FydeAmount::new(U256::exp10(18), 18);
+        let total_staked = FydeAmount::new(U256::from(3_153_600) * U256::exp10(18), 18);
+        let reward_price: FydeAmount = "0.5".parse().unwrap();
+        let trsy_price: FydeAmount = "1.25".parse().unwrap();
+
+        let apr = compute_apr(&reward_rate, &total_staked, &reward_price, &trsy_price).unwrap();
+        assert_eq!(apr.apr_in_trsy, "1000".parse().unwrap());
+        assert_eq!(apr.apr_in_usd, "400".parse().unwrap());
+
+        assert!(compute_apr(
+            &reward_rate,
+            &FydeAmount::zero(18),
+            &reward_price,
+            &trsy_price
+        )
+        .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_history_keeps_log_indexes() {
+        let (staking, mock) = mocked_staking().await;
+        let user = Address::from_low_u64_be(7);
+        // Responses are served last in, first out: logs, then their block
+        mock.push::<Block<Transaction>, _>(Block {
+            number: Some(U64::from(101)),
+            hash: Some(H256::from_low_u64_be(101)),
+            timestamp: U256::from(1_700_000_101),
+            ..Default::default()
+        })
+        .unwrap();
+        mock.push::<Vec<Log>, _>(vec![
+            user_log(StakedFilter::signature(), user, 10, 101, 2),
+            user_log(WithdrawnFilter::signature(), user, 4, 101, 5),
+        ])
+        .unwrap();
+
+        let (history, cursor) = staking.get_history(100, Some(110)).await.unwrap();
+        assert_eq!(cursor.next_block, 111);
+        let log_indexes: Vec<(u64, u64)> = history
+            .iter()
+            .map(|event| match event {
+                TrsyStakingEvent::Staked {
+                    log_index,
+                    timestamp,
+                    ..
+                }
+                | TrsyStakingEvent::Withdrawn {
+                    log_index,
+                    timestamp,
+                    ..
+                } => (*log_index, *timestamp),
+            })
+            .collect();
+        assert_eq!(log_indexes, vec![(2, 1_700_000_101), (5, 1_700_000_101)]);
+    }
+}
@@ -0,0 +1,383 @@
+use ethers::{
+    providers::Middleware,
+    types::{Address, U256, U512},
+};
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::{
+    errors::{DataError, EventContext, FydeError},
+    oracle::{deviation_bps, Oracle},
+    utils::FydeAmount,
+    Chain, UniswapV3Pool,
+};
+
+pub const MIN_TICK: i32 = -887_272;
+pub const MAX_TICK: i32 = 887_272;
+
+/// Uniswap V3 pool of a protocol asset, as configured in the liquid vault
+pub struct UniswapPool<M: Middleware> {
+    asset: Address,
+    pool: UniswapV3Pool<M>,
+    oracle: Oracle<M>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Slot0 {
+    pub sqrt_price_x96: U256,
+    pub tick: i32,
+    pub observation_index: u16,
+    pub observation_cardinality: u16,
+}
+
+/// Prices of the asset in the quote token, and the oracle price for comparison
+#[derive(Debug, Serialize, Clone)]
+pub struct PoolPrices {
+    pub pool: Address,
+    pub asset: Address,
+    pub quote_token: Address,
+    pub spot_tick: i32,
+    pub spot_price: FydeAmount,
+    pub twap_period_short: u32,
+    pub twap_short_tick: i32,
+    pub twap_short_price: FydeAmount,
+    pub twap_period_long: u32,
+    pub twap_long_tick: i32,
+    pub twap_long_price: FydeAmount,
+    /// Oracle price of the asset over the oracle price of the quote token, none when the
+    /// quote token has no oracle price
+    pub oracle_price_in_quote: Option<FydeAmount>,
+    /// Deviation of the spot price from the short TWAP, in basis points
+    pub spot_deviation_bps: Option<u64>,
+    /// Deviation of the short TWAP from the long TWAP, in basis points
+    pub twap_deviation_bps: Option<u64>,
+    /// Deviation of the short TWAP from the oracle price, in basis points
+    pub oracle_deviation_bps: Option<u64>,
+}
+
+/// `TickMath.getSqrtRatioAtTick`: sqrt(1.0001^tick) as a Q64.96
+pub fn sqrt_ratio_at_tick(tick: i32) -> Option<U256> {
+    if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+        return None;
+    }
+    const FACTORS: [(u32, &str); 19] = [
+        (0x2, "fff97272373d413259a46990580e213a"),
+        (0x4, "fff2e50f5f656932ef12357cf3c7fdcc"),
+        (0x8, "ffe5caca7e10e4e61c3624eaa0941cd0"),
+        (0x10, "ffcb9843d60f6159c9db58835c926644"),
+        (0x20, "ff973b41fa98c081472e6896dfb254c0"),
+        (0x40, "ff2ea16466c96a3843ec78b326b52861"),
+        (0x80, "fe5dee046a99a2a811c461f1969c3053"),
+        (0x100, "fcbe86c7900a88aedcffc83b479aa3a4"),
+        (0x200, "f987a7253ac413176f2b074cf7815e54"),
+        (0x400, "f3392b0822b70005940c7a398e4b70f3"),
+        (0x800, "e7159475a2c29b7443b29c7fa6e889d9"),
+        (0x1000, "d097f3bdfd2022b8845ad8f792aa5825"),
+        (0x2000, "a9f746462d870fdf8a65dc1f90e061e5"),
+        (0x4000, "70d869a156d2a1b890bb3df62baf32f7"),
+        (0x8000, "31be135f97d08fd981231505542fcfa6"),
+        (0x10000, "9aa508b5b7a84e1c677de54f3e99bc9"),
+        (0x20000, "5d6af8dedb81196699c329225ee604"),
+        (0x40000, "2216e584f5fa1ea926041bedfe98"),
+        (0x80000, "48a170391f7dc42444e8fa2"),
+    ];
+
+    let abs_tick = tick.unsigned_abs();
+    let mut ratio = match abs_tick & 0x1 {
+        0 => U256::one() << 128,
+        _ => U256::from_str_radix("fffcb933bd6fad37aa2d162d1a594001", 16).ok()?,
+    };
+    for (bit, factor) in FACTORS {
+        if abs_tick & bit != 0 {
+            ratio = (ratio * U256::from_str_radix(factor, 16).ok()?) >> 128;
+        }
+    }
+    if tick > 0 {
+        ratio = U256::MAX / ratio;
+    }
+
+    let remainder = ratio & ((U256::one() << 32) - 1);
+    Some((ratio >> 32) + U256::from(!remainder.is_zero() as u8))
+}
+
+/// Price of one asset in quote tokens, with 18 decimals, from a Q64.96 square root price
+pub fn sqrt_price_to_price(
+    sqrt_price_x96: U256,
+    asset_is_token0: bool,
+    asset_decimals: u8,
+    quote_decimals: u8,
+) -> Option<FydeAmount> {
+    if sqrt_price_x96.is_zero() {
+        return None;
+    }
+    let price_x192 = sqrt_price_x96.full_mul(sqrt_price_x96);
+    let q192 = U512::one() << 192;
+    let asset_unit = U512::from(10).pow(U512::from(asset_decimals));
+    let quote_unit = U512::from(10).pow(U512::from(quote_decimals));
+    let one = U512::exp10(18);
+
+    let price = match asset_is_token0 {
+        // token1 per token0 is price_x192 / 2^192
+        true => price_x192 * asset_unit / quote_unit * one / q192,
+        false => q192 * asset_unit / quote_unit * one / price_x192,
+    };
+    Some(FydeAmount::new(U256::try_from(price).ok()?, 18))
+}
+
+pub fn tick_to_price(
+    tick: i32,
+    asset_is_token0: bool,
+    asset_decimals: u8,
+    quote_decimals: u8,
+) -> Option<FydeAmount> {
+    sqrt_price_to_price(
+        sqrt_ratio_at_tick(tick)?,
+        asset_is_token0,
+        asset_decimals,
+        quote_decimals,
+    )
+}
+
+/// Arithmetic mean tick between two tick cumulatives, rounded toward negative infinity as in
+/// `OracleLibrary.consult`
+pub fn mean_tick(tick_cumulative_start: i64, tick_cumulative_end: i64, period: u32) -> i32 {
+    let delta = tick_cumulative_end - tick_cumulative_start;
+    let mut tick = delta / period as i64;
+    if delta < 0 && delta % period as i64 != 0 {
+        tick -= 1;
+    }
+    tick as i32
+}
+
+impl<M: Middleware> UniswapPool<M> {
+    /// Pool of `asset`, read from its asset info
+    pub async fn new(asset: Address, client: Arc<M>, chain: Chain) -> Result<Self, FydeError<M>> {
+        let oracle = Oracle::new(client.clone(), chain).await?;
+        let asset_info = oracle.get_asset_info(asset).await?;
+        if asset_info.uniswap_pool.is_zero() {
+            return Err(FydeError::InvalidRequest(format!(
+                "No Uniswap pool configured for {:?}",
+                asset
+            )));
+        }
+        Ok(Self {
+            asset,
+            pool: UniswapV3Pool::new(asset_info.uniswap_pool, client),
+            oracle,
+        })
+    }
+
+    pub async fn get_slot0(&self) -> Result<Slot0, FydeError<M>> {
+        let (sqrt_price_x96, tick, observation_index, observation_cardinality, _, _, _) =
+            self.pool.slot_0().call().await?;
+        Ok(Slot0 {
+            sqrt_price_x96,
+            tick,
+            observation_index,
+            observation_cardinality,
+        })
+    }
+
+    /// Mean tick over the last `period` seconds
+    pub async fn get_twap_tick(&self, period: u32) -> Result<i32, FydeError<M>> {
+        if period == 0 {
+            return Err(FydeError::InvalidRequest(String::from(
+                "TWAP period must not be zero",
+            )));
+        }
+        let (tick_cumulatives, _) = self.pool.observe(vec![period, 0]).call().await?;
+        match tick_cumulatives[..] {
+            [past, now] => Ok(mean_tick(past, now, period)),
+            _ => Err(DataError::inconsistent(
+                format!(
+                    "observe returned {} tick cumulatives for 2 timestamps",
+                    tick_cumulatives.len()
+                ),
+                EventContext::default(),
+            )
+            .into()),
+        }
+    }
+
+    /// Spot and TWAP prices over the oracle module periods, compared with the oracle price
+    pub async fn get_prices(&self) -> Result<PoolPrices, FydeError<M>> {
+        let config = self.oracle.get_config().await?;
+        let asset_info = self.oracle.get_asset_info(self.asset).await?;
+        let token0 = self.pool.token_0().call().await?;
+        let slot0 = self.get_slot0().await?;
+
+        // The oracle module stores the periods as uint16, widening them is lossless
+        let twap_period_short = u32::from(config.twap_period_short);
+        let twap_period_long = u32::from(config.twap_period_long);
+        let twap_short_tick = self.get_twap_tick(twap_period_short).await?;
+        let twap_long_tick = self.get_twap_tick(twap_period_long).await?;
+
+        let asset_is_token0 = token0 == self.asset;
+        let (asset_decimals, quote_decimals) =
+            (asset_info.asset_decimals, asset_info.quote_token_decimals);
+        let price_error =
+            || FydeError::ArithmeticError(String::from("Pool price out of the U256 range"));
+        let spot_price = sqrt_price_to_price(
+            slot0.sqrt_price_x96,
+            asset_is_token0,
+            asset_decimals,
+            quote_decimals,
+        )
+        .ok_or_else(price_error)?;
+        let twap_short_price = tick_to_price(
+            twap_short_tick,
+            asset_is_token0,
+            asset_decimals,
+            quote_decimals,
+        )
+        .ok_or_else(price_error)?;
+        let twap_long_price = tick_to_price(
+            twap_long_tick,
+            asset_is_token0,
+            asset_decimals,
+            quote_decimals,
+        )
+        .ok_or_else(price_error)?;
+
+        let oracle_price_in_quote = self
+            .get_oracle_price_in_quote(asset_info.uniswap_quote_token)
+            .await?;
+        let oracle_deviation_bps = oracle_price_in_quote
+            .as_ref()
+            .and_then(|oracle_price| deviation_bps(&twap_short_price, oracle_price));
+
+        Ok(PoolPrices {
+            pool: self.pool.address(),
+            asset: self.asset,
+            quote_token: asset_info.uniswap_quote_token,
+            spot_tick: slot0.tick,
+            spot_deviation_bps: deviation_bps(&spot_price, &twap_short_price),
+            spot_price,
+            twap_period_short,
+            twap_short_tick,
+            twap_deviation_bps: deviation_bps(&twap_short_price, &twap_long_price),
+            twap_short_price,
+            twap_period_long,
+            twap_long_tick,
+            twap_long_price,
+            oracle_price_in_quote,
+            oracle_deviation_bps,
+        })
+    }
+
+    async fn get_oracle_price_in_quote(
+        &self,
+        quote_token: Address,
+    ) -> Result<Option<FydeAmount>, FydeError<M>> {
+        let price = |token| async move {
+            match self.oracle.get_price_in_usd(token).await {
+                Ok(price) => Ok(Some(price)),
+                Err(FydeError::ContractError(err)) if err.is_revert() => Ok(None),
+                Err(err) => Err(err),
+            }
+        };
+        let (Some(asset_price), Some(quote_price)) =
+            (price(self.asset).await?, price(quote_token).await?)
+        else {
+            return Ok(None);
+        };
+        Ok(asset_price.checked_div(&quote_price))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        abi::Token,
+        providers::{MockProvider, Provider},
+        types::{Bytes, I256},
+    };
+
+    async fn mocked_pool() -> (UniswapPool<Provider<MockProvider>>, MockProvider) {
+        let (provider, mock) = Provider::mocked();
+        let client = Arc::new(provider);
+        // Chain id of the oracle and vault multicalls
+        mock.push(U256::one()).unwrap();
+        mock.push(U256::one()).unwrap();
+        let pool = UniswapPool {
+            asset: Address::random(),
+            pool: UniswapV3Pool::new(Address::random(), client.clone()),
+            oracle: Oracle::new(client, Chain::Mainnet).await.unwrap(),
+        };
+        (pool, mock)
+    }
+
+    fn push_observation(mock: &MockProvider, tick_cumulatives: &[i64]) {
+        let encoded = ethers::abi::encode(&[
+            Token::Array(
+                tick_cumulatives
+                    .iter()
+                    .map(|tick| Token::Int(I256::from(*tick).into_raw()))
+                    .collect(),
+            ),
+            Token::Array(
+                tick_cumulatives
+                    .iter()
+                    .map(|_| Token::Uint(U256::zero()))
+                    .collect(),
+            ),
+        ]);
+        mock.push::<Bytes, _>(Bytes::from(encoded)).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_twap_tick_checks_observations() {
+        let (pool, mock) = mocked_pool().await;
+
+        push_observation(&mock, &[-1_000, 2_000]);
+        assert_eq!(pool.get_twap_tick(100).await.unwrap(), 30);
+
+        push_observation(&mock, &[2_000]);
+        assert!(matches!(
+            pool.get_twap_tick(100).await,
+            Err(FydeError::DataError(DataError::InconsistentEvent { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_tick_math() {
+        assert_eq!(sqrt_ratio_at_tick(0), Some(U256::one() << 96));
+        assert_eq!(
+            sqrt_ratio_at_tick(MIN_TICK),
+            Some(U256::from(4_295_128_739u64))
+        );
+        assert_eq!(
+            sqrt_ratio_at_tick(MAX_TICK),
+            U256::from_dec_str("1461446703485210103287273052203988822378723970342").ok()
+        );
+        assert_eq!(sqrt_ratio_at_tick(MAX_TICK + 1), None);
+
+        // Every bit factor against the floating point formula
+        for bit in 0..20 {
+            for tick in [1i32 << bit, -(1i32 << bit)] {
+                let expected = 1.0001f64.powf(tick as f64 / 2.0) * 2f64.powi(96);
+                let actual: f64 = sqrt_ratio_at_tick(tick)
+                    .unwrap()
+                    .to_string()
+                    .parse()
+                    .unwrap();
+                assert!(
+                    ((actual - expected) / expected).abs() < 1e-9,
+                    "tick {}",
+                    tick
+                );
+            }
+        }
+
+        // 1 WETH (token0, 18 decimals) for 2000 USDC (token1, 6 decimals) is around tick -200311
+        let price = tick_to_price(-200_311, true, 18, 6).unwrap().to_f64();
+        assert!((price - 2000.0).abs() < 1.0, "{}", price);
+        let inverse = tick_to_price(-200_311, false, 6, 18).unwrap().to_f64();
+        assert!((inverse * price - 1.0).abs() < 1e-6, "{}", inverse);
+
+        assert_eq!(mean_tick(0, 600, 60), 10);
+        assert_eq!(mean_tick(0, -610, 60), -11);
+        assert_eq!(mean_tick(0, -600, 60), -10);
+    }
+}
@@ -3,19 +3,19 @@ use crate::{
 };
 use ethers::{
     contract::Multicall,
-    providers::{Http, Provider},
+    providers::Middleware,
     types::{Address, U256},
 };
 use std::{collections::HashMap, sync::Arc};
 
-pub type ERC20s = Vec<ERC20<Provider<Http>>>;
+pub type ERC20s<M> = Vec<ERC20<M>>;
 
-pub struct User {
+pub struct User<M: Middleware> {
     address: Address,
-    provider: Arc<Provider<Http>>,
-    multicall: Multicall<Provider<Http>>,
-    governance_module: GovernanceModuleContract<Provider<Http>>,
-    liquid_vault: LiquidVaultContract<Provider<Http>>,
+    provider: Arc<M>,
+    multicall: Multicall<M>,
+    governance_module: GovernanceModuleContract<M>,
+    liquid_vault: LiquidVaultContract<M>,
     address_list: AddressList,
 }
 
@@ -25,36 +25,38 @@ pub struct GovernanceData {
     pub total_voting_rights: HashMap<Address, U256>,
 }
 
-impl User {
-    pub async fn new(provider: Arc<Provider<Http>>, chain: Chain, user_address: Address) -> Self {
+impl<M: Middleware> User<M> {
+    pub async fn new(
+        provider: Arc<M>,
+        chain: Chain,
+        user_address: Address,
+    ) -> Result<Self, FydeError<M>> {
         let address_list: AddressList = AddressList::new(&chain);
-        Self {
+        Ok(Self {
             address: user_address,
             provider: provider.clone(),
-            multicall: Multicall::new(provider.clone(), None)
-                .await
-                .expect("Failed to create Multicall"),
+            multicall: Multicall::new(provider.clone(), None).await?,
             governance_module: GovernanceModuleContract::new(
                 address_list.governance_module,
                 provider.clone(),
             ),
             liquid_vault: LiquidVaultContract::new(address_list.liquid_vault, provider.clone()),
             address_list,
-        }
+        })
     }
 
-    pub async fn get_trsy_balance(&self) -> Result<U256, FydeError> {
+    pub async fn get_trsy_balance(&self) -> Result<U256, FydeError<M>> {
         Ok(self.liquid_vault.balance_of(self.address).call().await?)
     }
 
     pub async fn get_allowances(
         &self,
         assets: &[Address],
-    ) -> Result<HashMap<Address, U256>, FydeError> {
+    ) -> Result<HashMap<Address, U256>, FydeError<M>> {
         let mut multicall = self.multicall.clone();
         multicall.clear_calls();
 
-        let erc20s: ERC20s = assets
+        let erc20s: ERC20s<M> = assets
             .iter()
             .map(|&addr| ERC20::new(addr, self.provider.clone()))
             .collect();
@@ -78,11 +80,11 @@ impl User {
     pub async fn get_balances(
         &self,
         assets: &[Address],
-    ) -> Result<HashMap<Address, U256>, FydeError> {
+    ) -> Result<HashMap<Address, U256>, FydeError<M>> {
         let mut multicall = self.multicall.clone();
         multicall.clear_calls();
 
-        let erc20s: ERC20s = assets
+        let erc20s: ERC20s<M> = assets
             .iter()
             .map(|&addr| ERC20::new(addr, self.provider.clone()))
             .collect();
@@ -101,7 +103,7 @@ impl User {
         Ok(balances)
     }
 
-    pub async fn get_proxy_address(&self) -> Result<Option<Address>, FydeError> {
+    pub async fn get_proxy_address(&self) -> Result<Option<Address>, FydeError<M>> {
         let proxy_address: Option<Address> = match self
             .governance_module
             .user_to_proxy(self.address)
@@ -117,7 +119,7 @@ impl User {
     pub async fn get_governance_data(
         &self,
         assets_in_gov: &[Address],
-    ) -> Result<GovernanceData, FydeError> {
+    ) -> Result<GovernanceData, FydeError<M>> {
         let mut multicall = self.multicall.clone();
         multicall.clear_calls();
         for asset in assets_in_gov {
@@ -158,7 +160,7 @@ impl User {
     pub async fn get_governance_data_when_multicall_fail(
         &self,
         assets_in_gov: &[Address],
-    ) -> Result<GovernanceData, FydeError> {
+    ) -> Result<GovernanceData, FydeError<M>> {
         let mut multicall = self.multicall.clone();
         multicall.clear_calls();
         for asset in assets_in_gov {
@@ -1,11 +1,308 @@
-use ethers::prelude::U256;
+use ethers::{
+    prelude::U256,
+    providers::Middleware,
+    types::{BlockId, BlockNumber},
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{cmp::Ordering, fmt, str::FromStr};
 
-pub trait ToF32 {
-    fn to_f32(self, divisor: f32) -> f32;
+use crate::errors::{DataError, EventContext, FydeError};
+
+/// Fixed-point decimal amount: a raw `U256` value carrying its number of decimals.
+///
+/// Arithmetic is checked and exact (results are truncated toward zero when a division
+/// is involved). Amounts serialize to a decimal string holding every decimal, e.g.
+/// `"1.500000"` for 1.5 USDC, so a serialize/deserialize roundtrip is lossless.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FydeAmount {
+    value: U256,
+    decimals: u8,
+}
+
+impl FydeAmount {
+    pub fn new(value: U256, decimals: u8) -> Self {
+        Self { value, decimals }
+    }
+
+    pub fn zero(decimals: u8) -> Self {
+        Self::new(U256::zero(), decimals)
+    }
+
+    /// Raw value, in units of `10^-decimals`
+    pub fn value(&self) -> U256 {
+        self.value
+    }
+
+    pub fn decimals(&self) -> u8 {
+        self.decimals
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.value.is_zero()
+    }
+
+    /// Express the amount with `decimals` decimals, truncating when decimals are removed.
+    pub fn rescale(&self, decimals: u8) -> Option<Self> {
+        let value = match decimals.cmp(&self.decimals) {
+            Ordering::Equal => self.value,
+            Ordering::Greater => self.value.checked_mul(pow10(decimals - self.decimals)?)?,
+            Ordering::Less => self.value / pow10(self.decimals - decimals)?,
+        };
+        Some(Self::new(value, decimals))
+    }
+
+    fn aligned(&self, other: &Self) -> Option<(U256, U256, u8)> {
+        let decimals = self.decimals.max(other.decimals);
+        Some((
+            self.rescale(decimals)?.value,
+            other.rescale(decimals)?.value,
+            decimals,
+        ))
+    }
+
+    /// Sum of both amounts, expressed with the larger number of decimals
+    pub fn checked_add(&self, other: &Self) -> Option<Self> {
+        let (a, b, decimals) = self.aligned(other)?;
+        Some(Self::new(a.checked_add(b)?, decimals))
+    }
+
+    /// Difference of both amounts, expressed with the larger number of decimals
+    pub fn checked_sub(&self, other: &Self) -> Option<Self> {
+        let (a, b, decimals) = self.aligned(other)?;
+        Some(Self::new(a.checked_sub(b)?, decimals))
+    }
+
+    /// Product of both amounts, expressed with the decimals of `self`
+    pub fn checked_mul(&self, other: &Self) -> Option<Self> {
+        let value = self.value.checked_mul(other.value)? / pow10(other.decimals)?;
+        Some(Self::new(value, self.decimals))
+    }
+
+    /// Quotient of both amounts, expressed with the decimals of `self`
+    pub fn checked_div(&self, other: &Self) -> Option<Self> {
+        if other.value.is_zero() {
+            return None;
+        }
+        let value = self.value.checked_mul(pow10(other.decimals)?)? / other.value;
+        Some(Self::new(value, self.decimals))
+    }
+
+    /// Multiply by an integer, e.g. to express a ratio in percent
+    pub fn checked_mul_u64(&self, factor: u64) -> Option<Self> {
+        Some(Self::new(
+            self.value.checked_mul(factor.into())?,
+            self.decimals,
+        ))
+    }
+
+    /// Lossy conversion, for display or charting purposes only
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
 }
 
-impl ToF32 for U256 {
-    fn to_f32(self, decimals: f32) -> f32 {
-        self.as_u128() as f32 / 10f32.powf(decimals)
+fn pow10(exp: u8) -> Option<U256> {
+    U256::from(10).checked_pow(U256::from(exp))
+}
+
+impl PartialEq for FydeAmount {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for FydeAmount {}
+
+impl PartialOrd for FydeAmount {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FydeAmount {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match self.aligned(other) {
+            Some((a, b, _)) => a.cmp(&b),
+            // Rescaling can only overflow the amount with the fewer decimals
+            None => self.decimals.cmp(&other.decimals).reverse(),
+        }
+    }
+}
+
+impl fmt::Display for FydeAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.value.to_string();
+        let decimals = self.decimals as usize;
+        let (integer, fraction) = match digits.len() > decimals {
+            true => {
+                let (integer, fraction) = digits.split_at(digits.len() - decimals);
+                (integer.to_string(), fraction.to_string())
+            }
+            false => (
+                String::from("0"),
+                format!("{:0>width$}", digits, width = decimals),
+            ),
+        };
+        // `{:.N}` truncates the fractional part to N digits
+        let fraction = match f.precision() {
+            Some(precision) if precision < fraction.len() => &fraction[..precision],
+            _ => fraction.as_str(),
+        };
+        match fraction.is_empty() {
+            true => write!(f, "{}", integer),
+            false => write!(f, "{}.{}", integer, fraction),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseFydeAmountError(String);
+
+impl fmt::Display for ParseFydeAmountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid amount: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseFydeAmountError {}
+
+impl FromStr for FydeAmount {
+    type Err = ParseFydeAmountError;
+
+    /// Parse a decimal string, the number of decimals being the number of fractional digits.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (integer, fraction) = s.split_once('.').unwrap_or((s, ""));
+        let is_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+        if integer.is_empty() || !is_digits(integer) || !is_digits(fraction) {
+            return Err(ParseFydeAmountError(s.to_string()));
+        }
+        let decimals =
+            u8::try_from(fraction.len()).map_err(|_| ParseFydeAmountError(s.to_string()))?;
+        let value = U256::from_dec_str(&format!("{}{}", integer, fraction))
+            .map_err(|_| ParseFydeAmountError(s.to_string()))?;
+        Ok(Self::new(value, decimals))
+    }
+}
+
+impl Serialize for FydeAmount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for FydeAmount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Timestamp of `block`, an error when the node has no such block
+pub async fn block_timestamp<M: Middleware>(
+    client: &M,
+    block: BlockNumber,
+) -> Result<u64, FydeError<M>> {
+    let context = match block.as_number() {
+        Some(number) => EventContext::default().with_block(number.as_u64()),
+        None => EventContext::default(),
+    };
+    let timestamp = client
+        .get_block(BlockId::Number(block))
+        .await
+        .map_err(FydeError::MiddlewareError)?
+        .ok_or_else(|| DataError::missing("block", context.clone()))?
+        .timestamp;
+    Ok(
+        u64::try_from(timestamp)
+            .map_err(|_| DataError::out_of_range("block timestamp", context))?,
+    )
+}
+
+/// Timestamp of the latest block
+pub async fn latest_timestamp<M: Middleware>(client: &M) -> Result<u64, FydeError<M>> {
+    block_timestamp(client, BlockNumber::Latest).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            FydeAmount::new(U256::from(1_500_000), 6).to_string(),
+            "1.500000"
+        );
+        assert_eq!(FydeAmount::new(U256::from(42), 6).to_string(), "0.000042");
+        assert_eq!(FydeAmount::new(U256::from(42), 0).to_string(), "42");
+        assert_eq!(
+            format!("{:.2}", FydeAmount::new(U256::from(1_239_999), 6)),
+            "1.23"
+        );
+        assert_eq!(FydeAmount::new(U256::MAX, 18).to_string().len(), 79);
+    }
+
+    #[test]
+    fn test_parse_roundtrip() {
+        let amount = FydeAmount::new(U256::MAX, 18);
+        let json = serde_json::to_string(&amount).unwrap();
+        let parsed: FydeAmount = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.value(), U256::MAX);
+        assert_eq!(parsed.decimals(), 18);
+
+        assert!("1.2.3".parse::<FydeAmount>().is_err());
+        assert!("-1".parse::<FydeAmount>().is_err());
+        assert!(".5".parse::<FydeAmount>().is_err());
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let a = FydeAmount::new(U256::from(1_500_000), 6);
+        let b = FydeAmount::new(U256::exp10(18) * 2, 18);
+
+        let sum = a.checked_add(&b).unwrap();
+        assert_eq!(sum.to_string(), "3.500000000000000000");
+        assert_eq!(b.checked_sub(&a).unwrap().decimals(), 18);
+        assert!(a.checked_sub(&b).is_none());
+        assert_eq!(a.checked_mul(&b).unwrap().to_string(), "3.000000");
+        assert_eq!(a.checked_div(&b).unwrap().to_string(), "0.750000");
+        assert!(a.checked_div(&FydeAmount::zero(18)).is_none());
+        assert!(FydeAmount::new(U256::MAX, 0).checked_add(&a).is_none());
+    }
+
+    #[test]
+    fn test_ordering_across_decimals() {
+        let a = FydeAmount::new(U256::from(1_000_000), 6);
+        let b = FydeAmount::new(U256::exp10(18), 18);
+        assert_eq!(a, b);
+        assert!(FydeAmount::new(U256::MAX, 0) > b);
+        assert!(FydeAmount::new(U256::one(), 18) < a);
+    }
+
+    #[tokio::test]
+    async fn test_block_timestamp() {
+        let (provider, mock) = ethers::providers::Provider::mocked();
+        mock.push(ethers::types::Block::<ethers::types::H256> {
+            timestamp: U256::from(1_700_000_000),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(latest_timestamp(&provider).await.unwrap(), 1_700_000_000);
+
+        mock.push(ethers::types::Block::<ethers::types::H256> {
+            timestamp: U256::MAX,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(matches!(
+            latest_timestamp(&provider).await,
+            Err(FydeError::DataError(DataError::OutOfRange { .. }))
+        ));
+
+        mock.push(serde_json::Value::Null).unwrap();
+        assert!(matches!(
+            block_timestamp(&provider, BlockNumber::Number(10.into())).await,
+            Err(FydeError::DataError(DataError::MissingData { .. }))
+        ));
     }
 }
@@ -1,17 +1,24 @@
 use crate::{
-    errors::FydeError, AddressList, Chain, Checkpoint, VoteEscrowContract, VoteEscrowContractEvents,
+    errors::FydeError,
+    log_scanner::{LogScanner, WithScanner},
+    utils::FydeAmount,
+    AddressList, Chain, Checkpoint, VoteEscrowContract, VoteEscrowContractEvents,
 };
 use ethers::{
-    contract::Multicall,
-    providers::{Http, Provider},
-    types::{Address, U256},
+    contract::{LogMeta, Multicall},
+    providers::Middleware,
+    types::{Address, Filter, U256},
 };
 use serde::Serialize;
 use std::{sync::Arc, vec};
 
-pub struct VeFyde {
-    vote_escrow: VoteEscrowContract<Provider<Http>>,
-    multicall: Multicall<Provider<Http>>,
+/// Block of the VoteEscrow deployment on mainnet, no lock can be found before it
+pub const VOTE_ESCROW_DEPLOYMENT_BLOCK: u64 = 20231776;
+
+pub struct VeFyde<M: Middleware> {
+    vote_escrow: VoteEscrowContract<M>,
+    multicall: Multicall<M>,
+    scanner: LogScanner<M>,
 }
 
 #[derive(Serialize, Default, Debug)]
@@ -30,12 +37,12 @@ pub struct VeBalanceChart {
     /// Timestamps
     pub ts: Vec<u64>,
     /// Ve balances decaying over time
-    pub ve_balance: Vec<f32>,
+    pub ve_balance: Vec<FydeAmount>,
 }
 
 impl VeBalanceChart {
     pub fn get_decay_graph(last_locking_date: u64, expiry: u64, checkpoint: Checkpoint) -> Self {
-        let range = expiry - last_locking_date;
+        let range = expiry.saturating_sub(last_locking_date);
         let mut ve_balance_chart = VeBalanceChart {
             ts: vec![],
             ve_balance: vec![],
@@ -45,63 +52,71 @@ impl VeBalanceChart {
         // Loop through each day until the expiry
         for i in (0..range).step_by(seconds_in_a_day as usize) {
             let ts = last_locking_date + i;
-            let ve_balance = checkpoint.value.bias - checkpoint.value.slope * ts as u128;
+            let ve_balance = checkpoint
+                .value
+                .bias
+                .saturating_sub(checkpoint.value.slope.saturating_mul(ts as u128));
             ve_balance_chart.ts.push(ts);
             ve_balance_chart
                 .ve_balance
-                .push(ve_balance as f32 / 1e18_f32);
+                .push(FydeAmount::new(U256::from(ve_balance), 18));
         }
 
         // Ensure the last data point is exactly at expiry if not already included
         if ve_balance_chart.ts.last().copied() != Some(expiry) {
-            let ve_balance = checkpoint.value.bias - checkpoint.value.slope * expiry as u128;
+            let ve_balance = checkpoint
+                .value
+                .bias
+                .saturating_sub(checkpoint.value.slope.saturating_mul(expiry as u128));
             ve_balance_chart.ts.push(expiry);
             ve_balance_chart
                 .ve_balance
-                .push(ve_balance as f32 / 1e18_f32);
+                .push(FydeAmount::new(U256::from(ve_balance), 18));
         }
 
         ve_balance_chart
     }
 }
 
-impl VeFyde {
-    pub async fn new(provider: Arc<Provider<Http>>, chain: Chain) -> Self {
+impl<M: Middleware> WithScanner<M> for VeFyde<M> {
+    fn scanner_mut(&mut self) -> &mut LogScanner<M> {
+        &mut self.scanner
+    }
+}
+
+impl<M: Middleware> VeFyde<M> {
+    pub async fn new(provider: Arc<M>, chain: Chain) -> Result<Self, FydeError<M>> {
         let address_list: AddressList = AddressList::new(&chain);
         let vote_escrow = VoteEscrowContract::new(address_list.vote_escrow, provider.clone());
-        let multicall = Multicall::new(provider.clone(), None)
-            .await
-            .expect("Failed to create Multicall");
-        Self {
+        let multicall = Multicall::new(provider.clone(), None).await?;
+        Ok(Self {
             vote_escrow,
             multicall,
-        }
+            scanner: LogScanner::new(provider),
+        })
     }
 
-    pub async fn get_ve_fyde_holders_list(&self) -> Result<Vec<Address>, FydeError> {
-        let events = self
-            .vote_escrow
-            .events()
-            .from_block(20231776)
-            .query()
+    pub async fn get_ve_fyde_holders_list(&self) -> Result<Vec<Address>, FydeError<M>> {
+        let filter = Filter::new().address(self.vote_escrow.address());
+        let scan = self
+            .scanner
+            .scan(&filter, VOTE_ESCROW_DEPLOYMENT_BLOCK, None)
             .await?;
+        let events: Vec<(VoteEscrowContractEvents, LogMeta)> = scan.decode();
 
         let mut holders: Vec<Address> = vec![];
-        for event in events {
-            match event {
-                VoteEscrowContractEvents::UpdateLockFilter(ev) => {
-                    if holders.contains(&ev.user) {
-                        continue;
-                    }
-                    holders.push(ev.user);
+        for (event, _) in events {
+            if let VoteEscrowContractEvents::UpdateLockFilter(ev) = event {
+                if holders.contains(&ev.user) {
+                    continue;
                 }
-                _ => {}
+                holders.push(ev.user);
             }
         }
         Ok(holders)
     }
 
-    pub async fn get_ve_fyde_balance(&self, user: Address) -> Result<u128, FydeError> {
+    pub async fn get_ve_fyde_balance(&self, user: Address) -> Result<u128, FydeError<M>> {
         let balance = self.vote_escrow.balance_of(user).call().await?;
         Ok(balance)
     }
@@ -110,7 +125,7 @@ impl VeFyde {
         &self,
         user: Address,
         draw_vefyde_chart: bool,
-    ) -> Result<VeFydeUser, FydeError> {
+    ) -> Result<VeFydeUser, FydeError<M>> {
         let mut multicall = self.multicall.clone();
         multicall.clear_calls();
 
@@ -133,7 +148,7 @@ impl VeFyde {
             .await?;
 
         let last_locking_date = checkpoint.timestamp as u64;
-        let lock_duration = expiry as u64 - last_locking_date;
+        let lock_duration = (expiry as u64).saturating_sub(last_locking_date);
 
         let ve_balance_chart = if draw_vefyde_chart {
             Some(VeBalanceChart::get_decay_graph(
@@ -162,9 +177,10 @@ impl VeFyde {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ethers::providers::{Http, Provider};
 
     #[tokio::test]
-    async fn test_ve_fyde() -> Result<(), FydeError> {
+    async fn test_ve_fyde() -> Result<(), FydeError<Provider<Http>>> {
         let provider = Arc::new(
             Provider::<Http>::try_from(
                 "https://eth-mainnet.g.alchemy.com/v2/6scwdLmXmD0Ifv_8TgZaNA5Y7MzBhoZP",
@@ -172,7 +188,7 @@ mod tests {
             .expect("Failed to create provider"),
         );
         let chain = Chain::Mainnet;
-        let ve_fyde = VeFyde::new(provider.clone(), chain).await;
+        let ve_fyde = VeFyde::new(provider.clone(), chain).await?;
         let holders = ve_fyde.get_ve_fyde_holders_list().await?;
 
         for holder in holders {